@@ -0,0 +1,351 @@
+//! Sweep every modem mode over SNR through the channel simulator and report raw
+//! (pre-FEC) BER and frame error rate, as CSV and optionally as an SVG plot.
+
+use clap::Parser;
+use meshcq_channel_sim::{ChannelSimulator, Fading};
+use meshcq_modem::constellation::hard_decision;
+use meshcq_modem::{
+    ChannelEstimate, Equalizer, Frame, FrameReceiver, Header, Mode, Modulation, OfdmConfig,
+    OfdmModulator, PilotPattern, PreambleDetector, Profile, TxShaper,
+};
+use rustfft::num_complex::Complex;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+const LEAD_SECS: f32 = 0.25;
+const TAIL_SECS: f32 = 0.25;
+const PLOT_FLOOR: f32 = 1e-5;
+const COLOURS: [&str; 8] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+];
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "modem-bench",
+    about = "BER/FER of every modem mode against SNR"
+)]
+struct Args {
+    /// Fading profile.
+    #[arg(long, value_enum, default_value_t = Fading::None)]
+    channel: Fading,
+    /// Numerology profile (hf500, ssb2400 or fm).
+    #[arg(long, default_value = "ssb2400")]
+    profile: String,
+    /// Lowest SNR in dB (3 kHz noise bandwidth).
+    #[arg(long, default_value_t = -2.0)]
+    snr_min: f32,
+    /// Highest SNR in dB.
+    #[arg(long, default_value_t = 26.0)]
+    snr_max: f32,
+    /// SNR step in dB.
+    #[arg(long, default_value_t = 2.0)]
+    snr_step: f32,
+    /// Frames per mode and SNR.
+    #[arg(long, default_value_t = 20)]
+    frames: usize,
+    /// Payload bytes per frame.
+    #[arg(long, default_value_t = 200)]
+    payload: usize,
+    /// Carrier frequency offset in Hz.
+    #[arg(long, default_value_t = 0.0)]
+    offset_hz: f32,
+    /// Receiver sample clock offset in ppm.
+    #[arg(long, default_value_t = 0.0)]
+    drift_ppm: f32,
+    /// Preamble detector threshold.
+    #[arg(long, default_value_t = 0.5)]
+    sync_threshold: f32,
+    /// Random seed.
+    #[arg(long, default_value_t = 1)]
+    seed: u32,
+    /// Write a BER/FER plot here.
+    #[arg(long)]
+    svg: Option<PathBuf>,
+}
+
+struct Point {
+    mode: Mode,
+    snr_db: f32,
+    ber: f32,
+    fer: f32,
+}
+
+/// Outcome of one frame: bit errors and bits compared, and whether it decoded.
+struct Trial {
+    bit_errors: usize,
+    bits: usize,
+    delivered: bool,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let config = Profile::from_name(&args.profile)
+        .ok_or_else(|| format!("unknown profile '{}'", args.profile))?
+        .config();
+    config.validate()?;
+    if args.snr_step <= 0.0 {
+        return Err("--snr-step must be positive".into());
+    }
+
+    let mut points = Vec::new();
+    println!("channel,mode,snr_db,raw_ber,fer,frames");
+    for mode in Mode::ALL {
+        let mut snr_db = args.snr_min;
+        while snr_db <= args.snr_max + 1e-3 {
+            let (mut errors, mut bits, mut lost) = (0, 0, 0);
+            for i in 0..args.frames {
+                let seed = args
+                    .seed
+                    .wrapping_mul(7919)
+                    .wrapping_add(i as u32 * 104_729);
+                let trial = run_frame(&args, &config, mode, snr_db, i as u16, seed)?;
+                errors += trial.bit_errors;
+                bits += trial.bits;
+                lost += (!trial.delivered) as usize;
+            }
+            let point = Point {
+                mode,
+                snr_db,
+                // No frame synced, so there is nothing to measure.
+                ber: if bits == 0 {
+                    f32::NAN
+                } else {
+                    errors as f32 / bits as f32
+                },
+                fer: lost as f32 / args.frames.max(1) as f32,
+            };
+            println!(
+                "{:?},{},{:.1},{:.3e},{:.3},{}",
+                args.channel,
+                mode_name(mode),
+                point.snr_db,
+                point.ber,
+                point.fer,
+                args.frames
+            );
+            points.push(point);
+            snr_db += args.snr_step;
+        }
+    }
+
+    if let Some(path) = &args.svg {
+        std::fs::write(path, plot(&args, &points))?;
+        eprintln!("wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn run_frame(
+    args: &Args,
+    config: &OfdmConfig,
+    mode: Mode,
+    snr_db: f32,
+    seq: u16,
+    seed: u32,
+) -> Result<Trial, Box<dyn std::error::Error>> {
+    let pattern = config.pilot_pattern();
+    let mut state = seed.max(1);
+    let payload = (0..args.payload)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state >> 24) as u8
+        })
+        .collect();
+    let frame = Frame {
+        header: Header {
+            mode,
+            destination: "CQ".into(),
+            source: "N0CALL".into(),
+            seq,
+            flags: 0,
+        },
+        payload,
+    };
+    let sent = frame.encode(&pattern)?;
+    let shaper = TxShaper::from_config(config);
+    let burst = shaper.shape(&OfdmModulator::from_config(config).modulate_frame(&sent)?)?;
+
+    let fs = config.sample_rate_hz;
+    let band = config.passband();
+    let mut baseband = vec![Complex::new(0.0, 0.0); (LEAD_SECS * fs) as usize];
+    let burst_start = baseband.len();
+    baseband.extend(&burst);
+    baseband.extend(vec![Complex::new(0.0, 0.0); (TAIL_SECS * fs) as usize]);
+    let mut audio = Vec::new();
+    band.upconverter()?.process(&baseband, &mut audio);
+    let burst_audio = &audio[burst_start..burst_start + burst.len()];
+    let power = burst_audio.iter().map(|s| s * s).sum::<f32>() / burst_audio.len() as f32;
+
+    let received = ChannelSimulator::new(fs)
+        .with_seed(seed)
+        .with_fading(args.channel)
+        .with_frequency_offset_hz(args.offset_hz)
+        .with_snr_db(snr_db)
+        .with_signal_power(power)
+        .with_passband(config.audio_low_hz, config.audio_high_hz)
+        .with_clock_drift_ppm(args.drift_ppm)
+        .process(&audio);
+    let mut rx = Vec::new();
+    band.downconverter()?.process(&received, &mut rx);
+
+    let lost = Trial {
+        bit_errors: 0,
+        bits: 0,
+        delivered: false,
+    };
+    let mut detector = PreambleDetector::from_config(config).with_threshold(args.sync_threshold);
+    let Some(sync) = detector.push(&rx).into_iter().next() else {
+        return Ok(lost);
+    };
+    let Ok(symbols) = FrameReceiver::from_config(config).demodulate(
+        &sync,
+        &rx[sync.start as usize..],
+        sent.len(),
+    ) else {
+        return Ok(lost);
+    };
+    let (bit_errors, bits) = raw_errors(&pattern, mode, frame.payload.len(), &sent, &symbols)?;
+    let delivered = Frame::decode(&symbols, &pattern).is_ok_and(|f| f == frame);
+    Ok(Trial {
+        bit_errors,
+        bits,
+        delivered,
+    })
+}
+
+/// Hard-decision errors on the coded payload carriers before FEC (filler excluded).
+fn raw_errors(
+    pattern: &PilotPattern,
+    mode: Mode,
+    sent_payload_len: usize,
+    sent: &[Vec<Complex<f32>>],
+    received: &[Vec<Complex<f32>>],
+) -> Result<(usize, usize), String> {
+    let estimate = ChannelEstimate::estimate(pattern, received)?;
+    let header_symbols = Frame::header_symbols(pattern);
+    let coded = Frame::payload_coded_len(mode, sent_payload_len);
+    let mut remaining = coded.div_ceil(mode.modulation.bits_per_symbol());
+    let (mut errors, mut bits) = (0, 0);
+    for t in header_symbols..sent.len() {
+        let eq = Equalizer::ZeroForcing.equalize(&received[t], &estimate.gains[t], 1.0)?;
+        let mut tx = pattern.extract(t, &sent[t]);
+        let mut rx = pattern.extract(t, &eq.symbols);
+        let used = remaining.min(tx.len());
+        tx.truncate(used);
+        rx.truncate(used);
+        remaining -= used;
+        let unit = vec![1.0; tx.len()];
+        let tx_bits = hard_decision(&mode.modulation.demap(&tx, &unit)?);
+        let rx_bits = hard_decision(&mode.modulation.demap(&rx, &unit)?);
+        errors += tx_bits.iter().zip(&rx_bits).filter(|(a, b)| a != b).count();
+        bits += tx_bits.len();
+    }
+    Ok((errors, bits))
+}
+
+fn mode_name(mode: Mode) -> String {
+    let modulation = match mode.modulation {
+        Modulation::Bpsk => "BPSK",
+        Modulation::Qpsk => "QPSK",
+        Modulation::Psk8 => "8PSK",
+        Modulation::Qam16 => "16QAM",
+        Modulation::Qam64 => "64QAM",
+    };
+    let rate = format!("{:?}", mode.rate);
+    format!(
+        "{} {}",
+        modulation,
+        rate.trim_start_matches('R').replace('_', "/")
+    )
+}
+
+/// Two log-scale panels, BER on the left and FER on the right.
+fn plot(args: &Args, points: &[Point]) -> String {
+    let (panel_w, panel_h, margin) = (420.0, 320.0, 50.0);
+    let width = 2.0 * (panel_w + margin) + margin;
+    let height = panel_h + 2.0 * margin + 20.0 * Mode::ALL.len() as f32 / 2.0;
+    let decades = -PLOT_FLOOR.log10();
+    let x = |snr: f32, left: f32| {
+        left + (snr - args.snr_min) / (args.snr_max - args.snr_min).max(1e-3) * panel_w
+    };
+    let y = |v: f32| margin + (-v.max(PLOT_FLOOR).log10()) / decades * panel_h;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" font-family="sans-serif" font-size="12">"#,
+        w = width,
+        h = height
+    );
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+    for (panel, title) in ["Raw BER", "FER"].iter().enumerate() {
+        let left = margin + panel as f32 * (panel_w + margin);
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{left}" y="{margin}" width="{panel_w}" height="{panel_h}" fill="none" stroke="black"/>"#
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="middle">{} ({:?} channel) vs SNR (dB)</text>"#,
+            left + panel_w / 2.0,
+            margin - 10.0,
+            title,
+            args.channel
+        );
+        for d in 0..=decades as i32 {
+            let v = 10f32.powi(-d);
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{left}" x2="{}" y1="{yv}" y2="{yv}" stroke="#ddd"/><text x="{}" y="{}" text-anchor="end">1e-{d}</text>"##,
+                left + panel_w,
+                left - 4.0,
+                y(v) + 4.0,
+                yv = y(v)
+            );
+        }
+        let mut snr = args.snr_min;
+        while snr <= args.snr_max + 1e-3 {
+            let _ = writeln!(
+                svg,
+                r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+                x(snr, left),
+                margin + panel_h + 15.0,
+                snr
+            );
+            snr += args.snr_step
+                * (((args.snr_max - args.snr_min) / args.snr_step / 8.0).ceil()).max(1.0);
+        }
+        for (i, mode) in Mode::ALL.iter().enumerate() {
+            let line: Vec<String> = points
+                .iter()
+                .filter(|p| p.mode == *mode)
+                .map(|p| (p.snr_db, if panel == 0 { p.ber } else { p.fer }))
+                .filter(|(_, v)| !v.is_nan())
+                .map(|(snr_db, v)| format!("{:.1},{:.1}", x(snr_db, left), y(v)))
+                .collect();
+            let _ = writeln!(
+                svg,
+                r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="1.5"/>"#,
+                line.join(" "),
+                COLOURS[i % COLOURS.len()]
+            );
+        }
+    }
+    for (i, mode) in Mode::ALL.iter().enumerate() {
+        let lx = margin + (i % 4) as f32 * 200.0;
+        let ly = margin + panel_h + 40.0 + (i / 4) as f32 * 20.0;
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{lx}" x2="{}" y1="{ly}" y2="{ly}" stroke="{}" stroke-width="3"/><text x="{}" y="{}">{}</text>"#,
+            lx + 20.0,
+            COLOURS[i % COLOURS.len()],
+            lx + 26.0,
+            ly + 4.0,
+            mode_name(*mode)
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
@@ -0,0 +1,119 @@
+//! Watterson two-path fading.
+//!
+//! Each path has a complex gain with a Gaussian Doppler spectrum, made here as a sum
+//! of sinusoids at Gaussian-distributed frequencies. The CCIR profiles use two equal
+//! paths; the quoted frequency spread is twice the Doppler standard deviation.
+
+use clap::ValueEnum;
+use rustfft::num_complex::Complex;
+
+const SINUSOIDS: usize = 24;
+
+/// Multipath fading profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Fading {
+    /// A single static path.
+    None,
+    /// CCIR good: 0.5 ms delay, 0.1 Hz spread.
+    Good,
+    /// CCIR moderate: 1 ms delay, 0.5 Hz spread.
+    Moderate,
+    /// CCIR poor: 2 ms delay, 1 Hz spread.
+    Poor,
+}
+
+impl Fading {
+    pub const ALL: [Fading; 4] = [Fading::None, Fading::Good, Fading::Moderate, Fading::Poor];
+
+    /// Differential delay between the paths in seconds and the frequency spread in Hz.
+    pub fn parameters(self) -> Option<(f32, f32)> {
+        match self {
+            Self::None => None,
+            Self::Good => Some((0.0005, 0.1)),
+            Self::Moderate => Some((0.001, 0.5)),
+            Self::Poor => Some((0.002, 1.0)),
+        }
+    }
+
+    pub(crate) fn paths(self, sample_rate_hz: f32, rng: &mut Rng) -> Vec<FadingPath> {
+        let Some((delay_secs, spread_hz)) = self.parameters() else {
+            return Vec::new();
+        };
+        let delay = (delay_secs * sample_rate_hz).round() as usize;
+        [0, delay]
+            .into_iter()
+            .map(|delay| FadingPath::new(delay, spread_hz / 2.0, rng))
+            .collect()
+    }
+}
+
+/// One fading path.
+pub(crate) struct FadingPath {
+    pub delay: usize,
+    tones: Vec<(f64, f64)>,
+}
+
+impl FadingPath {
+    fn new(delay: usize, sigma_hz: f32, rng: &mut Rng) -> Self {
+        let tones = (0..SINUSOIDS)
+            .map(|_| {
+                let f = sigma_hz as f64 * rng.gaussian() as f64;
+                let phase = std::f64::consts::TAU * rng.uniform() as f64;
+                (std::f64::consts::TAU * f, phase)
+            })
+            .collect();
+        Self { delay, tones }
+    }
+
+    /// Complex gain at `t` seconds. Two paths of mean power 1/2 each.
+    pub fn gain(&self, t: f64) -> Complex<f32> {
+        let sum: Complex<f64> = self
+            .tones
+            .iter()
+            .map(|&(w, phase)| Complex::from_polar(1.0, w * t + phase))
+            .sum();
+        let g = sum / (2.0 * SINUSOIDS as f64).sqrt();
+        Complex::new(g.re as f32, g.im as f32)
+    }
+}
+
+/// Small deterministic generator so runs are repeatable.
+pub(crate) struct Rng(u32);
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub fn uniform(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 as f32 / u32::MAX as f32).max(1e-9)
+    }
+
+    pub fn gaussian(&mut self) -> f32 {
+        // Box-Muller.
+        let r = (-2.0 * self.uniform().ln()).sqrt();
+        r * (std::f32::consts::TAU * self.uniform()).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_fade_with_unit_total_power() {
+        let mut rng = Rng::new(7);
+        let paths = Fading::Poor.paths(48_000.0, &mut rng);
+        assert_eq!(paths[1].delay, 96);
+        let gains: Vec<f32> = (0..20_000)
+            .map(|i| paths[0].gain(i as f64 * 0.01).norm_sqr())
+            .collect();
+        let mean = gains.iter().sum::<f32>() / gains.len() as f32;
+        assert!((mean - 0.5).abs() < 0.1, "{}", mean);
+        // Rayleigh fading dips well below the mean.
+        assert!(gains.iter().any(|&g| g < 0.05 * mean));
+    }
+}
@@ -0,0 +1,247 @@
+//! HF/VHF channel simulator for modem testing.
+//!
+//! [`ChannelSimulator`] takes transmit audio and returns what a receiver's sound card
+//! would see: Watterson two-path fading (the CCIR good, moderate and poor profiles), a
+//! carrier frequency offset, AWGN at a set SNR, the radio's audio passband filter and
+//! a sample clock offset, applied in that order.
+
+pub mod fading;
+
+pub use fading::Fading;
+
+use fading::{FadingPath, Rng};
+use meshcq_modem::Resampler;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+/// Noise bandwidth the SNR refers to, the usual convention for HF modems.
+pub const NOISE_BANDWIDTH_HZ: f32 = 3000.0;
+
+const PASSBAND_EDGE_HZ: f32 = 50.0;
+
+/// Audio channel simulator.
+pub struct ChannelSimulator {
+    sample_rate_hz: f32,
+    snr_db: Option<f32>,
+    signal_power: Option<f32>,
+    fading: Fading,
+    offset_hz: f32,
+    drift_ppm: f32,
+    passband: Option<(f32, f32)>,
+    rng: Rng,
+    paths: Vec<FadingPath>,
+    elapsed: u64,
+    clock: Option<Resampler<f32>>,
+}
+
+impl ChannelSimulator {
+    /// A transparent channel at `sample_rate_hz`.
+    pub fn new(sample_rate_hz: f32) -> Self {
+        Self {
+            sample_rate_hz,
+            snr_db: None,
+            signal_power: None,
+            fading: Fading::None,
+            offset_hz: 0.0,
+            drift_ppm: 0.0,
+            passband: None,
+            rng: Rng::new(1),
+            paths: Vec::new(),
+            elapsed: 0,
+            clock: None,
+        }
+    }
+
+    /// Add white Gaussian noise for `snr_db` in a [`NOISE_BANDWIDTH_HZ`] bandwidth.
+    pub fn with_snr_db(mut self, snr_db: f32) -> Self {
+        self.snr_db = Some(snr_db);
+        self
+    }
+
+    /// Signal power the SNR refers to. By default it is measured over each buffer,
+    /// which understates it when the buffer includes silence.
+    pub fn with_signal_power(mut self, power: f32) -> Self {
+        self.signal_power = Some(power);
+        self
+    }
+
+    /// Apply multipath fading.
+    pub fn with_fading(mut self, fading: Fading) -> Self {
+        self.fading = fading;
+        self.paths = fading.paths(self.sample_rate_hz, &mut self.rng);
+        self
+    }
+
+    /// Shift the whole spectrum by `hz`, as SSB mistuning does.
+    pub fn with_frequency_offset_hz(mut self, hz: f32) -> Self {
+        self.offset_hz = hz;
+        self
+    }
+
+    /// Run the receiving sound card `ppm` parts per million fast (negative for slow).
+    pub fn with_clock_drift_ppm(mut self, ppm: f32) -> Self {
+        self.drift_ppm = ppm;
+        self.clock = (ppm != 0.0).then(|| {
+            Resampler::new(1.0, (1.0 + ppm as f64 * 1e-6) as f32).expect("positive rates")
+        });
+        self
+    }
+
+    /// Band-limit the received audio to `low_hz`..`high_hz`, like a radio's filter.
+    pub fn with_passband(mut self, low_hz: f32, high_hz: f32) -> Self {
+        self.passband = Some((low_hz, high_hz));
+        self
+    }
+
+    /// Seed the noise and fading generators.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.rng = Rng::new(seed);
+        self.paths = self.fading.paths(self.sample_rate_hz, &mut self.rng);
+        self
+    }
+
+    /// Pass `audio` through the channel. Fading, offset and clock stay continuous
+    /// across calls, but filtering works per buffer, so pass whole transmissions.
+    pub fn process(&mut self, audio: &[f32]) -> Vec<f32> {
+        if audio.is_empty() {
+            return Vec::new();
+        }
+        let fs = self.sample_rate_hz;
+        let mut out = if self.paths.is_empty() && self.offset_hz == 0.0 {
+            audio.to_vec()
+        } else {
+            let analytic = analytic(audio);
+            let step = std::f64::consts::TAU * self.offset_hz as f64 / fs as f64;
+            (0..audio.len())
+                .map(|n| {
+                    let t = (self.elapsed + n as u64) as f64 / fs as f64;
+                    let y = if self.paths.is_empty() {
+                        analytic[n]
+                    } else {
+                        self.paths
+                            .iter()
+                            .filter(|p| n >= p.delay)
+                            .map(|p| analytic[n - p.delay] * p.gain(t))
+                            .sum()
+                    };
+                    let phase =
+                        (step * (self.elapsed + n as u64) as f64).rem_euclid(std::f64::consts::TAU);
+                    (y * Complex::from_polar(1.0, phase as f32)).re
+                })
+                .collect()
+        };
+        self.elapsed += audio.len() as u64;
+
+        if let Some(snr_db) = self.snr_db {
+            let power = self
+                .signal_power
+                .unwrap_or_else(|| audio.iter().map(|s| s * s).sum::<f32>() / audio.len() as f32);
+            // White noise of variance v has v * bandwidth / (fs / 2) in the bandwidth.
+            let variance = power / 10f32.powf(snr_db / 10.0) * (fs / 2.0) / NOISE_BANDWIDTH_HZ;
+            let sigma = variance.sqrt();
+            for s in &mut out {
+                *s += sigma * self.rng.gaussian();
+            }
+        }
+
+        if let Some((low, high)) = self.passband {
+            out = bandpass(&out, fs, low, high);
+        }
+
+        match self.clock.as_mut() {
+            Some(clock) => {
+                let mut drifted = Vec::with_capacity(out.len());
+                clock.process(&out, &mut drifted);
+                drifted
+            }
+            None => out,
+        }
+    }
+}
+
+/// Analytic signal of `x` (real part `x`, imaginary part its Hilbert transform).
+fn analytic(x: &[f32]) -> Vec<Complex<f32>> {
+    let size = x.len().next_power_of_two() * 2;
+    let mut planner = FftPlanner::<f32>::new();
+    let mut buf: Vec<Complex<f32>> = x.iter().map(|&s| Complex::new(s, 0.0)).collect();
+    buf.resize(size, Complex::new(0.0, 0.0));
+    planner.plan_fft_forward(size).process(&mut buf);
+    for (k, b) in buf.iter_mut().enumerate() {
+        if k > 0 && k < size / 2 {
+            *b *= 2.0;
+        } else if k > size / 2 {
+            *b = Complex::new(0.0, 0.0);
+        }
+    }
+    planner.plan_fft_inverse(size).process(&mut buf);
+    buf.truncate(x.len());
+    for b in &mut buf {
+        *b /= size as f32;
+    }
+    buf
+}
+
+/// Band-pass with raised-cosine edges, applied in the frequency domain.
+fn bandpass(x: &[f32], fs: f32, low: f32, high: f32) -> Vec<f32> {
+    let size = x.len().next_power_of_two() * 2;
+    let mut planner = FftPlanner::<f32>::new();
+    let mut buf: Vec<Complex<f32>> = x.iter().map(|&s| Complex::new(s, 0.0)).collect();
+    buf.resize(size, Complex::new(0.0, 0.0));
+    planner.plan_fft_forward(size).process(&mut buf);
+    let edge = |f: f32, at: f32| {
+        let u = ((f - at) / PASSBAND_EDGE_HZ + 0.5).clamp(0.0, 1.0);
+        0.5 - 0.5 * (std::f32::consts::PI * u).cos()
+    };
+    for (k, b) in buf.iter_mut().enumerate() {
+        let f = k.min(size - k) as f32 * fs / size as f32;
+        *b *= edge(f, low) * (1.0 - edge(f, high)) / size as f32;
+    }
+    planner.plan_fft_inverse(size).process(&mut buf);
+    buf.iter().take(x.len()).map(|b| b.re).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(hz: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|n| (std::f32::consts::TAU * hz * n as f32 / 48_000.0).sin())
+            .collect()
+    }
+
+    #[test]
+    fn noise_matches_snr() {
+        let clean = tone(1000.0, 48_000);
+        let noisy = ChannelSimulator::new(48_000.0)
+            .with_snr_db(10.0)
+            .process(&clean);
+        let noise: f32 = noisy
+            .iter()
+            .zip(&clean)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f32>()
+            / clean.len() as f32;
+        // 0.5 W of signal at 10 dB in 3 kHz of a 24 kHz band.
+        let expected = 0.05 * 8.0;
+        assert!((noise / expected - 1.0).abs() < 0.05, "{}", noise);
+    }
+
+    #[test]
+    fn offset_and_passband() {
+        let shifted = ChannelSimulator::new(48_000.0)
+            .with_frequency_offset_hz(50.0)
+            .process(&tone(1000.0, 48_000));
+        // Count rising zero crossings over one second.
+        let crossings = shifted
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count();
+        assert!(crossings.abs_diff(1050) <= 1, "{}", crossings);
+
+        let mut band = ChannelSimulator::new(48_000.0).with_passband(300.0, 3000.0);
+        let power = |x: &[f32]| x[4800..43200].iter().map(|s| s * s).sum::<f32>() / 38400.0;
+        assert!((power(&band.process(&tone(1500.0, 48_000))) - 0.5).abs() < 0.01);
+        assert!(power(&band.process(&tone(4000.0, 48_000))) < 1e-4);
+    }
+}
@@ -0,0 +1,125 @@
+//! Frequency and clock tracking at low SNR, through the channel simulator.
+
+use meshcq_channel_sim::ChannelSimulator;
+use meshcq_modem::{
+    ClockTracker, Frame, FrameReceiver, Header, Mode, OfdmConfig, OfdmModulator, PreambleDetector,
+    TxShaper,
+};
+use rustfft::num_complex::Complex;
+
+const GAP: usize = 12_000;
+
+/// What a receiver made of one frame: whether it decoded, and its final frequency
+/// and clock offset estimates.
+struct Outcome {
+    decoded: bool,
+    offset_hz: f32,
+    ppm: f32,
+}
+
+/// Send a 600-byte BPSK frame through AWGN at `snr_db` with a carrier offset and a
+/// receive clock offset, or `None` if the preamble was missed.
+fn receive(
+    mut receiver: FrameReceiver,
+    snr_db: f32,
+    offset_hz: f32,
+    ppm: f32,
+    seed: u32,
+) -> Option<Outcome> {
+    let config = OfdmConfig::default();
+    let pattern = config.pilot_pattern();
+    let frame = Frame {
+        header: Header {
+            mode: Mode::most_robust(),
+            destination: "CQ".into(),
+            source: "N0CALL".into(),
+            seq: seed as u16,
+            flags: 0,
+        },
+        payload: (0..600).map(|i| (i * 37 + i / 5) as u8).collect(),
+    };
+    let symbols = frame.encode(&pattern).expect("encode");
+    let burst = OfdmModulator::from_config(&config)
+        .modulate_frame(&symbols)
+        .expect("modulate");
+    let burst = TxShaper::from_config(&config).shape(&burst).expect("shape");
+
+    let band = config.passband();
+    let mut baseband = vec![Complex::new(0.0, 0.0); GAP];
+    baseband.extend(&burst);
+    baseband.extend(vec![Complex::new(0.0, 0.0); GAP]);
+    let mut audio = Vec::new();
+    band.upconverter()
+        .expect("band")
+        .process(&baseband, &mut audio);
+    let on_air = &audio[GAP..GAP + burst.len()];
+    let power = on_air.iter().map(|s| s * s).sum::<f32>() / on_air.len() as f32;
+
+    let received = ChannelSimulator::new(config.sample_rate_hz)
+        .with_seed(seed)
+        .with_frequency_offset_hz(offset_hz)
+        .with_snr_db(snr_db)
+        .with_signal_power(power)
+        .with_passband(config.audio_low_hz, config.audio_high_hz)
+        .with_clock_drift_ppm(ppm)
+        .process(&audio);
+    let mut rx = Vec::new();
+    band.downconverter()
+        .expect("band")
+        .process(&received, &mut rx);
+
+    let sync = PreambleDetector::from_config(&config)
+        .push(&rx)
+        .into_iter()
+        .next()?;
+    let decoded = receiver
+        .demodulate(&sync, &rx[sync.start as usize..], symbols.len())
+        .is_ok_and(|symbols| Frame::decode(&symbols, &pattern) == Ok(frame));
+    Some(Outcome {
+        decoded,
+        offset_hz: receiver.frequency_tracker().offset_hz(),
+        ppm: receiver.clock_tracker().ppm(),
+    })
+}
+
+#[test]
+fn frequency_tracker_holds_at_low_snr() {
+    // Clock tracking off, so only the frequency loop moves.
+    let receiver =
+        || FrameReceiver::default().with_clock_tracker(ClockTracker::default().with_gain(0.0));
+    let mut synced = 0;
+    for seed in 1..9 {
+        let Some(outcome) = receive(receiver(), 3.0, 20.0, 0.0, seed) else {
+            continue;
+        };
+        synced += 1;
+        let error = outcome.offset_hz - 20.0;
+        assert!(error.abs() < 1.0, "seed {}: off by {} Hz", seed, error);
+        assert!(outcome.decoded, "seed {}", seed);
+    }
+    assert!(synced >= 5, "only {} frames synced", synced);
+}
+
+#[test]
+fn clock_tracker_holds_at_low_snr() {
+    // At 3 dB some frames are lost to noise whatever the clock does.
+    for (snr_db, limit, decodes) in [(3.0, 200.0, false), (10.0, 50.0, true)] {
+        let mut synced = 0;
+        for seed in 1..9 {
+            let Some(outcome) = receive(FrameReceiver::default(), snr_db, 0.0, 100.0, seed) else {
+                continue;
+            };
+            synced += 1;
+            let error = outcome.ppm - 100.0;
+            assert!(
+                error.abs() < limit,
+                "{} dB seed {}: off by {} ppm",
+                snr_db,
+                seed,
+                error
+            );
+            assert!(outcome.decoded || !decodes, "{} dB seed {}", snr_db, seed);
+        }
+        assert!(synced >= 5, "{} dB: only {} frames synced", snr_db, synced);
+    }
+}
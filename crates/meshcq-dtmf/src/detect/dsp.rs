@@ -99,6 +99,16 @@ impl DtmfDetector {
     }
 }
 
+/// Return the (row, column) frequencies in Hz for a DTMF key.
+pub fn key_frequencies(key: char) -> Option<(f32, f32)> {
+    for (row, keys) in DTMF_KEYS.iter().enumerate() {
+        if let Some(col) = keys.iter().position(|&k| k == key) {
+            return Some((DTMF_FREQS[row], DTMF_FREQS[4 + col]));
+        }
+    }
+    None
+}
+
 fn goertzel_coeffs(sample_rate_hz: f32, freqs: [f32; 8]) -> [f32; TOTAL_BINS] {
     std::array::from_fn(|i| {
         let freq_hz = freqs[i];
@@ -0,0 +1,52 @@
+use crate::detect::dsp::key_frequencies;
+
+const RAMP_MS: f32 = 2.0;
+
+/// Synthesize a single DTMF key with equal row and column amplitude.
+///
+/// `level` is the peak amplitude of each tone. Short ramps at both ends avoid clicks.
+/// Returns `None` for characters that are not DTMF keys.
+pub fn generate_key(key: char, sample_rate_hz: f32, len: usize, level: f32) -> Option<Vec<f32>> {
+    let (row_hz, col_hz) = key_frequencies(key)?;
+    let ramp_len = ((sample_rate_hz * RAMP_MS / 1000.0).round() as usize).min(len / 2);
+    let row_inc = 2.0 * std::f64::consts::PI * row_hz as f64 / sample_rate_hz as f64;
+    let col_inc = 2.0 * std::f64::consts::PI * col_hz as f64 / sample_rate_hz as f64;
+    let mut out = Vec::with_capacity(len);
+    for i in 0..len {
+        let t = i as f64;
+        let mut amp = level;
+        if i < ramp_len {
+            amp *= i as f32 / ramp_len as f32;
+        } else if i + ramp_len > len {
+            amp *= (len - i) as f32 / ramp_len as f32;
+        }
+        let tone = (row_inc * t).sin() + (col_inc * t).sin();
+        out.push(amp * tone as f32);
+    }
+    Some(out)
+}
+
+/// Synthesize a key sequence with fixed tone and gap durations.
+///
+/// Characters that are not DTMF keys are skipped.
+pub fn generate_sequence(
+    keys: &str,
+    sample_rate_hz: f32,
+    tone_ms: f32,
+    gap_ms: f32,
+    level: f32,
+) -> Vec<f32> {
+    let tone_len = (sample_rate_hz * tone_ms / 1000.0).round() as usize;
+    let gap_len = (sample_rate_hz * gap_ms / 1000.0).round() as usize;
+    let mut out = Vec::new();
+    for key in keys.chars() {
+        let Some(tone) = generate_key(key, sample_rate_hz, tone_len, level) else {
+            continue;
+        };
+        if !out.is_empty() {
+            out.extend(std::iter::repeat_n(0.0, gap_len));
+        }
+        out.extend_from_slice(&tone);
+    }
+    out
+}
@@ -1,4 +1,8 @@
 pub mod detect;
+pub mod generate;
+pub mod tone_burst;
 
 pub use detect::{DtmfDebouncer, DtmfDebouncerBuilder};
-pub use detect::dsp::DtmfDetector;
+pub use detect::dsp::{key_frequencies, DtmfDetector};
+pub use generate::{generate_key, generate_sequence};
+pub use tone_burst::{ToneBurst, ToneBurstDetector, ToneBurstDetectorBuilder};
@@ -0,0 +1,340 @@
+const DEFAULT_FREQ_HZ: f32 = 1750.0;
+const DEFAULT_TOLERANCE_HZ: f32 = 25.0;
+const DEFAULT_MIN_DURATION_MS: f32 = 150.0;
+const DEFAULT_FRAME_MS: f32 = 10.0;
+const DEFAULT_MIN_PURITY: f32 = 0.6;
+const DEFAULT_MAX_GAP_FRAMES: usize = 2;
+
+/// A detected tone burst.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneBurst {
+    /// First sample of the burst, counted from the last reset.
+    pub start_sample: usize,
+    /// Last sample of the burst, counted from the last reset.
+    pub end_sample: usize,
+    /// Measured burst frequency in Hz.
+    pub freq_hz: f32,
+}
+
+/// Tone burst detector (e.g. 1750 Hz repeater access).
+///
+/// Each frame is correlated against the nominal frequency. A frame counts as tone when
+/// most of its energy falls in that bin and the phase advance since the previous frame
+/// puts the frequency within tolerance.
+pub struct ToneBurstDetector {
+    sample_rate_hz: f32,
+    freq_hz: f32,
+    tolerance_hz: f32,
+    min_duration_samples: usize,
+    min_purity: f32,
+    max_gap_frames: usize,
+    frame_len: usize,
+    phasor_step: (f64, f64),
+    phasor: (f64, f64),
+    acc: (f64, f64),
+    energy: f64,
+    samples_in_frame: usize,
+    position: usize,
+    prev_bin: Option<(f64, f64)>,
+    run: Option<Run>,
+}
+
+struct Run {
+    start: usize,
+    last: usize,
+    gap_frames: usize,
+    freq_sum: f32,
+    freq_count: usize,
+}
+
+impl ToneBurstDetector {
+    /// Create a builder with default settings.
+    pub fn builder(sample_rate_hz: f32) -> ToneBurstDetectorBuilder {
+        ToneBurstDetectorBuilder::new(sample_rate_hz)
+    }
+
+    /// Feed samples and return completed bursts.
+    pub fn push(&mut self, samples: &[f32]) -> Vec<ToneBurst> {
+        let mut bursts = Vec::new();
+        for &x in samples {
+            let x = x as f64;
+            self.acc.0 += x * self.phasor.0;
+            self.acc.1 -= x * self.phasor.1;
+            self.energy += x * x;
+            self.phasor = complex_mul(self.phasor, self.phasor_step);
+            self.samples_in_frame += 1;
+            self.position += 1;
+
+            if self.samples_in_frame == self.frame_len {
+                let frame_end = self.position - 1;
+                let frame_start = self.position - self.frame_len;
+                if let Some(burst) = self.consume_frame(frame_start, frame_end) {
+                    bursts.push(burst);
+                }
+                self.acc = (0.0, 0.0);
+                self.energy = 0.0;
+                self.samples_in_frame = 0;
+                // Keep the reference oscillator on the unit circle.
+                let mag = (self.phasor.0 * self.phasor.0 + self.phasor.1 * self.phasor.1).sqrt();
+                self.phasor = (self.phasor.0 / mag, self.phasor.1 / mag);
+            }
+        }
+        bursts
+    }
+
+    /// Flush a burst still in progress at the end of the input.
+    pub fn finish(&mut self) -> Option<ToneBurst> {
+        let run = self.run.take()?;
+        self.prev_bin = None;
+        self.accept(run)
+    }
+
+    /// Reset internal state and clear any pending detections.
+    pub fn reset(&mut self) {
+        self.phasor = (1.0, 0.0);
+        self.acc = (0.0, 0.0);
+        self.energy = 0.0;
+        self.samples_in_frame = 0;
+        self.position = 0;
+        self.prev_bin = None;
+        self.run = None;
+    }
+
+    fn consume_frame(&mut self, frame_start: usize, frame_end: usize) -> Option<ToneBurst> {
+        let bin = self.acc;
+        let bin_power = bin.0 * bin.0 + bin.1 * bin.1;
+        let purity = if self.energy > 0.0 {
+            (2.0 * bin_power / (self.frame_len as f64 * self.energy)) as f32
+        } else {
+            0.0
+        };
+
+        let mut is_tone = purity >= self.min_purity;
+        let mut offset_hz = None;
+        if is_tone {
+            if let Some(prev) = self.prev_bin {
+                // Phase advance between frames relative to the nominal frequency.
+                let rot = complex_mul(bin, (prev.0, -prev.1));
+                let dphi = rot.1.atan2(rot.0) as f32;
+                let offset = dphi * self.sample_rate_hz
+                    / (2.0 * std::f32::consts::PI * self.frame_len as f32);
+                if offset.abs() <= self.tolerance_hz {
+                    offset_hz = Some(offset);
+                } else {
+                    is_tone = false;
+                }
+            }
+        }
+        self.prev_bin = if purity >= self.min_purity { Some(bin) } else { None };
+
+        if is_tone {
+            let run = self.run.get_or_insert(Run {
+                start: frame_start,
+                last: frame_end,
+                gap_frames: 0,
+                freq_sum: 0.0,
+                freq_count: 0,
+            });
+            run.last = frame_end;
+            run.gap_frames = 0;
+            if let Some(offset) = offset_hz {
+                run.freq_sum += offset;
+                run.freq_count += 1;
+            }
+            return None;
+        }
+
+        let run = self.run.as_mut()?;
+        run.gap_frames += 1;
+        if run.gap_frames <= self.max_gap_frames {
+            return None;
+        }
+        let run = self.run.take()?;
+        self.accept(run)
+    }
+
+    fn accept(&self, run: Run) -> Option<ToneBurst> {
+        // A frequency estimate needs at least two consecutive tone frames.
+        if run.freq_count == 0 || run.last + 1 - run.start < self.min_duration_samples {
+            return None;
+        }
+        Some(ToneBurst {
+            start_sample: run.start,
+            end_sample: run.last,
+            freq_hz: self.freq_hz + run.freq_sum / run.freq_count as f32,
+        })
+    }
+}
+
+fn complex_mul(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+fn ms_to_samples(ms: f32, sample_rate_hz: f32) -> usize {
+    let len = (sample_rate_hz * (ms / 1000.0)).round() as usize;
+    len.max(1)
+}
+
+/// Builder for configuring a ToneBurstDetector.
+pub struct ToneBurstDetectorBuilder {
+    sample_rate_hz: f32,
+    freq_hz: f32,
+    tolerance_hz: f32,
+    min_duration_ms: f32,
+    frame_ms: f32,
+    min_purity: f32,
+    max_gap_frames: usize,
+}
+
+impl ToneBurstDetectorBuilder {
+    /// Create a builder with defaults (1750 Hz, +/-25 Hz, 150 ms) for the given sample rate.
+    pub fn new(sample_rate_hz: f32) -> Self {
+        Self {
+            sample_rate_hz,
+            freq_hz: DEFAULT_FREQ_HZ,
+            tolerance_hz: DEFAULT_TOLERANCE_HZ,
+            min_duration_ms: DEFAULT_MIN_DURATION_MS,
+            frame_ms: DEFAULT_FRAME_MS,
+            min_purity: DEFAULT_MIN_PURITY,
+            max_gap_frames: DEFAULT_MAX_GAP_FRAMES,
+        }
+    }
+
+    /// Set the nominal burst frequency in Hz.
+    pub fn freq_hz(mut self, freq_hz: f32) -> Self {
+        self.freq_hz = freq_hz;
+        self
+    }
+
+    /// Set the accepted frequency deviation in Hz.
+    pub fn tolerance_hz(mut self, tolerance_hz: f32) -> Self {
+        self.tolerance_hz = tolerance_hz.abs();
+        self
+    }
+
+    /// Set the minimum burst duration in milliseconds.
+    pub fn min_duration_ms(mut self, ms: f32) -> Self {
+        self.min_duration_ms = ms;
+        self
+    }
+
+    /// Set the analysis frame length in milliseconds.
+    ///
+    /// The frame is shortened if needed so the tolerance stays within the unambiguous
+    /// range of the frame-to-frame phase measurement.
+    pub fn frame_ms(mut self, frame_ms: f32) -> Self {
+        self.frame_ms = frame_ms;
+        self
+    }
+
+    /// Set the fraction of frame energy (0.0 - 1.0) that must fall on the tone.
+    pub fn min_purity(mut self, purity: f32) -> Self {
+        self.min_purity = purity;
+        self
+    }
+
+    /// Set the number of missed frames tolerated inside a burst.
+    pub fn max_gap_frames(mut self, frames: usize) -> Self {
+        self.max_gap_frames = frames;
+        self
+    }
+
+    /// Build the detector.
+    pub fn build(self) -> ToneBurstDetector {
+        let mut frame_len = ms_to_samples(self.frame_ms, self.sample_rate_hz);
+        if self.tolerance_hz > 0.0 {
+            let max_len = (self.sample_rate_hz / (4.0 * self.tolerance_hz)).floor() as usize;
+            frame_len = frame_len.min(max_len.max(1));
+        }
+        let omega = 2.0 * std::f64::consts::PI * self.freq_hz as f64 / self.sample_rate_hz as f64;
+
+        ToneBurstDetector {
+            sample_rate_hz: self.sample_rate_hz,
+            freq_hz: self.freq_hz,
+            tolerance_hz: self.tolerance_hz,
+            min_duration_samples: ms_to_samples(self.min_duration_ms, self.sample_rate_hz),
+            min_purity: self.min_purity,
+            max_gap_frames: self.max_gap_frames,
+            frame_len,
+            phasor_step: (omega.cos(), omega.sin()),
+            phasor: (1.0, 0.0),
+            acc: (0.0, 0.0),
+            energy: 0.0,
+            samples_in_frame: 0,
+            position: 0,
+            prev_bin: None,
+            run: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FS: f32 = 48_000.0;
+
+    /// 100 ms of silence, the tone, then `tail_ms` of silence.
+    fn burst(freq_hz: f32, ms: f32, tail_ms: f32) -> Vec<f32> {
+        let lead = ms_to_samples(100.0, FS);
+        let len = ms_to_samples(ms, FS);
+        let mut out = vec![0.0; lead];
+        out.extend(
+            (0..len).map(|n| 0.5 * (std::f32::consts::TAU * freq_hz * n as f32 / FS).sin()),
+        );
+        out.extend(vec![0.0; ms_to_samples(tail_ms, FS)]);
+        out
+    }
+
+    fn detect(samples: &[f32]) -> Vec<ToneBurst> {
+        let mut detector = ToneBurstDetector::builder(FS).build();
+        let mut bursts = detector.push(samples);
+        bursts.extend(detector.finish());
+        bursts
+    }
+
+    #[test]
+    fn accepts_within_tolerance_only() {
+        for freq in [1725.0, 1750.0, 1775.0] {
+            let bursts = detect(&burst(freq, 300.0, 100.0));
+            assert_eq!(bursts.len(), 1, "{} Hz", freq);
+            assert!((bursts[0].freq_hz - freq).abs() < 2.0, "{:?}", bursts[0]);
+        }
+        for freq in [1720.0, 1780.0] {
+            assert!(detect(&burst(freq, 300.0, 100.0)).is_empty(), "{} Hz", freq);
+        }
+    }
+
+    #[test]
+    fn rejects_short_bursts() {
+        assert!(detect(&burst(1750.0, 130.0, 100.0)).is_empty());
+        let bursts = detect(&burst(1750.0, 170.0, 100.0));
+        assert_eq!(bursts.len(), 1);
+        let start = ms_to_samples(100.0, FS);
+        assert!(bursts[0].start_sample.abs_diff(start) <= 480);
+    }
+
+    #[test]
+    fn chunking_does_not_change_result() {
+        let samples = burst(1760.0, 400.0, 100.0);
+        let whole = detect(&samples);
+        let mut detector = ToneBurstDetector::builder(FS).build();
+        let mut chunked = Vec::new();
+        for chunk in samples.chunks(37) {
+            chunked.extend(detector.push(chunk));
+        }
+        chunked.extend(detector.finish());
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole, chunked);
+    }
+
+    #[test]
+    fn finish_flushes_burst_at_end_of_input() {
+        let samples = burst(1750.0, 300.0, 0.0);
+        let mut detector = ToneBurstDetector::builder(FS).build();
+        assert!(detector.push(&samples).is_empty());
+        let flushed = detector.finish().expect("burst flushed");
+        assert!(flushed.end_sample + 1 >= samples.len() - 480);
+        assert!(detector.finish().is_none());
+    }
+}
@@ -0,0 +1,361 @@
+//! Synthetic DTMF receiver conformance tests for `DtmfDebouncer`.
+//!
+//! Modelled on the Bellcore (TR-TSY-000181 / LSSGR) and Mitel detector test procedures:
+//! frequency offset, twist, white-noise SNR, tone and gap duration, and talk-off. Every
+//! test prints its section of the report; run with
+//! `cargo test -p meshcq-dtmf --test conformance -- --nocapture --test-threads 1` to
+//! see the full report. The assertions are regression limits on the current detector,
+//! with the Bellcore requirement noted where it differs.
+
+use meshcq_dtmf::{key_frequencies, DtmfDebouncer};
+
+const SAMPLE_RATE_HZ: f32 = 48_000.0;
+const KEYS: [char; 16] = [
+    '1', '2', '3', 'A', '4', '5', '6', 'B', '7', '8', '9', 'C', '*', '0', '#', 'D',
+];
+const TONE_LEVEL: f32 = 0.25;
+const TONE_MS: f32 = 100.0;
+const LEAD_MS: f32 = 100.0;
+const TAIL_MS: f32 = 300.0;
+
+struct Tone {
+    key: char,
+    ms: f32,
+    row_offset: f32,
+    col_offset: f32,
+    row_db: f32,
+    col_db: f32,
+}
+
+impl Tone {
+    fn new(key: char) -> Self {
+        Self {
+            key,
+            ms: TONE_MS,
+            row_offset: 0.0,
+            col_offset: 0.0,
+            row_db: 0.0,
+            col_db: 0.0,
+        }
+    }
+
+    fn render(&self, out: &mut Vec<f32>) {
+        let (row_hz, col_hz) = key_frequencies(self.key).expect("dtmf key");
+        let row_hz = row_hz * (1.0 + self.row_offset);
+        let col_hz = col_hz * (1.0 + self.col_offset);
+        let row_amp = TONE_LEVEL * db_to_amp(self.row_db);
+        let col_amp = TONE_LEVEL * db_to_amp(self.col_db);
+        let len = ms_to_samples(self.ms);
+        for i in 0..len {
+            let t = i as f32 / SAMPLE_RATE_HZ;
+            out.push(
+                row_amp * (std::f32::consts::TAU * row_hz * t).sin()
+                    + col_amp * (std::f32::consts::TAU * col_hz * t).sin(),
+            );
+        }
+    }
+}
+
+struct Rng(u32);
+
+impl Rng {
+    fn next_f32(&mut self) -> f32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        (x as f32 + 1.0) / (u32::MAX as f32 + 2.0)
+    }
+
+    fn gaussian(&mut self) -> f32 {
+        let u1 = self.next_f32();
+        let u2 = self.next_f32();
+        (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos()
+    }
+}
+
+fn db_to_amp(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+fn ms_to_samples(ms: f32) -> usize {
+    (SAMPLE_RATE_HZ * ms / 1000.0).round() as usize
+}
+
+fn silence(out: &mut Vec<f32>, ms: f32) {
+    out.extend(std::iter::repeat_n(0.0, ms_to_samples(ms)));
+}
+
+/// Render tones separated by `gap_ms` with silence before and after.
+fn render(tones: &[Tone], gap_ms: f32) -> Vec<f32> {
+    let mut out = Vec::new();
+    silence(&mut out, LEAD_MS);
+    for (i, tone) in tones.iter().enumerate() {
+        if i > 0 {
+            silence(&mut out, gap_ms);
+        }
+        tone.render(&mut out);
+    }
+    silence(&mut out, TAIL_MS);
+    out
+}
+
+fn add_noise(samples: &mut [f32], rms: f32, rng: &mut Rng) {
+    for s in samples {
+        *s += rms * rng.gaussian();
+    }
+}
+
+fn detect(samples: &[f32]) -> String {
+    let mut dtmf = DtmfDebouncer::builder(SAMPLE_RATE_HZ).build();
+    dtmf.push(samples).into_iter().map(|(ch, _, _)| ch).collect()
+}
+
+/// Fraction of keys detected exactly once as the right key.
+fn accept_rate(mut tone_for: impl FnMut(char) -> Tone) -> f32 {
+    let hits = KEYS
+        .iter()
+        .filter(|&&key| detect(&render(&[tone_for(key)], 0.0)) == key.to_string())
+        .count();
+    hits as f32 / KEYS.len() as f32
+}
+
+fn print_header(title: &str) {
+    println!();
+    println!("== {} ==", title);
+}
+
+#[test]
+fn frequency_offset() {
+    print_header("frequency offset (both tones, 100 ms)");
+    println!("{:>9} {:>8}", "offset %", "accept");
+    let mut results = Vec::new();
+    for &pct in &[-5.0f32, -3.5, -2.5, -2.0, -1.5, 0.0, 1.5, 2.0, 2.5, 3.5, 5.0] {
+        let rate = accept_rate(|key| Tone {
+            row_offset: pct / 100.0,
+            col_offset: pct / 100.0,
+            ..Tone::new(key)
+        });
+        println!("{:>9.1} {:>8.2}", pct, rate);
+        results.push((pct, rate));
+    }
+    for (pct, rate) in results {
+        // Bellcore: accept within +/-1.5%, reject beyond +/-3.5%. The Goertzel bins are
+        // wide enough that a few keys still pass at 3.5%.
+        if pct.abs() <= 2.0 {
+            assert_eq!(rate, 1.0, "offset {}% must be accepted", pct);
+        }
+        if pct.abs() >= 3.5 {
+            assert!(rate <= 0.15, "offset {}% accepted at rate {}", pct, rate);
+        }
+    }
+}
+
+#[test]
+fn twist() {
+    print_header("twist (column level relative to row, 100 ms)");
+    println!("{:>9} {:>8}", "twist dB", "accept");
+    let mut results = Vec::new();
+    for &db in &[-16.0f32, -12.0, -10.0, -8.0, -4.0, 0.0, 4.0, 8.0, 10.0, 12.0, 16.0] {
+        let rate = accept_rate(|key| Tone {
+            col_db: db,
+            ..Tone::new(key)
+        });
+        println!("{:>9.1} {:>8.2}", db, rate);
+        results.push((db, rate));
+    }
+    for (db, rate) in results {
+        // Bellcore: accept 8 dB forward (column high) and 4 dB reverse twist.
+        if db.abs() <= 10.0 {
+            assert_eq!(rate, 1.0, "twist {} dB must be accepted", db);
+        }
+        if db.abs() >= 16.0 {
+            assert_eq!(rate, 0.0, "twist {} dB must be rejected", db);
+        }
+    }
+}
+
+#[test]
+fn white_noise_snr() {
+    print_header("white noise (SNR over the full 24 kHz band, 100 ms, 10 trials)");
+    println!("{:>7} {:>8} {:>8}", "SNR dB", "accept", "errors");
+    let signal_power = TONE_LEVEL * TONE_LEVEL;
+    let mut rng = Rng(0x5eed_1234);
+    let mut results = Vec::new();
+    for &snr_db in &[20.0f32, 10.0, 5.0, 0.0, -3.0, -6.0, -9.0, -12.0] {
+        let noise_rms = (signal_power / 10.0_f32.powf(snr_db / 10.0)).sqrt();
+        let mut hits = 0usize;
+        let mut errors = 0usize;
+        let mut total = 0usize;
+        for _ in 0..10 {
+            for &key in &KEYS {
+                let mut samples = render(&[Tone::new(key)], 0.0);
+                add_noise(&mut samples, noise_rms, &mut rng);
+                let detected = detect(&samples);
+                total += 1;
+                if detected == key.to_string() {
+                    hits += 1;
+                } else if detected.chars().any(|ch| ch != key) {
+                    errors += 1;
+                }
+            }
+        }
+        let rate = hits as f32 / total as f32;
+        println!("{:>7.1} {:>8.2} {:>8}", snr_db, rate, errors);
+        results.push((snr_db, rate, errors));
+    }
+    for (snr_db, rate, errors) in results {
+        if snr_db >= -9.0 {
+            assert_eq!(errors, 0, "wrong key detected at {} dB SNR", snr_db);
+        }
+        if snr_db >= -6.0 {
+            assert!(rate >= 0.99, "accept rate {} at {} dB SNR", rate, snr_db);
+        }
+    }
+}
+
+#[test]
+fn tone_duration() {
+    print_header("tone duration (single key)");
+    println!("{:>7} {:>8}", "ms", "accept");
+    let mut results = Vec::new();
+    for &ms in &[20.0f32, 30.0, 40.0, 50.0, 60.0, 70.0, 80.0, 100.0] {
+        let rate = accept_rate(|key| Tone {
+            ms,
+            ..Tone::new(key)
+        });
+        println!("{:>7.0} {:>8.2}", ms, rate);
+        results.push((ms, rate));
+    }
+    for (ms, rate) in results {
+        // Bellcore: accept 40 ms, reject below 23 ms.
+        if ms >= 30.0 {
+            assert_eq!(rate, 1.0, "{} ms tone must be accepted", ms);
+        }
+        if ms <= 20.0 {
+            assert_eq!(rate, 0.0, "{} ms tone must be rejected", ms);
+        }
+    }
+}
+
+#[test]
+fn gap_duration() {
+    print_header("inter-digit gap (two identical 100 ms keys)");
+    println!("{:>7} {:>10}", "gap ms", "resolved");
+    let mut results = Vec::new();
+    for &gap_ms in &[10.0f32, 20.0, 40.0, 60.0, 80.0, 100.0, 120.0, 150.0] {
+        let resolved = KEYS
+            .iter()
+            .filter(|&&key| {
+                let samples = render(&[Tone::new(key), Tone::new(key)], gap_ms);
+                detect(&samples) == format!("{}{}", key, key)
+            })
+            .count() as f32
+            / KEYS.len() as f32;
+        println!("{:>7.0} {:>10.2}", gap_ms, resolved);
+        results.push((gap_ms, resolved));
+    }
+    for (gap_ms, resolved) in results {
+        // Bellcore: resolve 40 ms gaps. The debouncer needs three empty frames, so
+        // gaps below about 90 ms merge. Short dropouts must never double a digit.
+        if gap_ms >= 100.0 {
+            assert_eq!(resolved, 1.0, "{} ms gap must split the digits", gap_ms);
+        }
+        if gap_ms <= 20.0 {
+            assert_eq!(resolved, 0.0, "{} ms gap must not split the digit", gap_ms);
+        }
+    }
+}
+
+/// Speech-like signal: a glottal pulse train with drifting pitch through drifting formant
+/// resonators, alternating with unvoiced noise segments.
+fn speech_like(secs: f32, rng: &mut Rng) -> Vec<f32> {
+    let len = (SAMPLE_RATE_HZ * secs) as usize;
+    let mut out = Vec::with_capacity(len);
+    let mut formants = [Resonator::default(), Resonator::default(), Resonator::default()];
+    let mut phase = 0.0f32;
+    let mut segment_left = 0usize;
+    let mut voiced = true;
+    let mut pitch_hz = 120.0f32;
+    let mut targets = [500.0f32, 1500.0, 2500.0];
+    let mut current = targets;
+    for _ in 0..len {
+        if segment_left == 0 {
+            // New syllable: pick voicing, pitch and vowel formants.
+            voiced = rng.next_f32() < 0.8;
+            pitch_hz = 90.0 + 160.0 * rng.next_f32();
+            targets = [
+                250.0 + 650.0 * rng.next_f32(),
+                800.0 + 1600.0 * rng.next_f32(),
+                2200.0 + 1000.0 * rng.next_f32(),
+            ];
+            segment_left = ms_to_samples(80.0 + 220.0 * rng.next_f32());
+        }
+        segment_left -= 1;
+        for (c, t) in current.iter_mut().zip(targets) {
+            *c += (t - *c) * 0.0005;
+        }
+        pitch_hz *= 1.0 + 0.00002 * (rng.next_f32() - 0.5);
+        let excitation = if voiced {
+            phase += pitch_hz / SAMPLE_RATE_HZ;
+            if phase >= 1.0 {
+                phase -= 1.0;
+                1.0
+            } else {
+                0.0
+            }
+        } else {
+            0.05 * rng.gaussian()
+        };
+        let mut y = 0.0;
+        for (res, &freq) in formants.iter_mut().zip(&current) {
+            y += res.process(excitation, freq, 80.0);
+        }
+        out.push(y);
+    }
+    let peak = out.iter().fold(0.0f32, |m, &x| m.max(x.abs())).max(1e-9);
+    for s in &mut out {
+        *s *= 0.5 / peak;
+    }
+    out
+}
+
+#[derive(Default)]
+struct Resonator {
+    y1: f32,
+    y2: f32,
+}
+
+impl Resonator {
+    fn process(&mut self, x: f32, freq_hz: f32, bandwidth_hz: f32) -> f32 {
+        let r = (-std::f32::consts::PI * bandwidth_hz / SAMPLE_RATE_HZ).exp();
+        let theta = std::f32::consts::TAU * freq_hz / SAMPLE_RATE_HZ;
+        let y = x + 2.0 * r * theta.cos() * self.y1 - r * r * self.y2;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+#[test]
+fn talk_off() {
+    print_header("talk-off (speech-like signal)");
+    let mut rng = Rng(0x7a1c_0ff5);
+    let secs = 120.0;
+    let speech = speech_like(secs, &mut rng);
+    let detected = detect(&speech);
+    println!(
+        "{} false detections in {:.0} s of speech-like signal: {:?}",
+        detected.len(),
+        secs,
+        detected
+    );
+    // Mitel-style receivers aim for a handful of hits per hour of speech. The detector
+    // only compares each group's peak with its runner-up, so talk-off is high today.
+    assert!(
+        detected.len() <= 60,
+        "talk-off regression: {} false detections",
+        detected.len()
+    );
+}
@@ -0,0 +1,299 @@
+//! Adaptive modulation and coding.
+//!
+//! Peers report the [`LinkQuality`] our frames arrived with; [`ModePolicy`] keeps a
+//! smoothed effective SNR per peer and picks the fastest mode it supports with some
+//! margin. Stepping up needs extra headroom (hysteresis) so a link hovering near a
+//! threshold does not flap; stepping down is immediate. Failed deliveries step down
+//! one mode at a time, and a peer with repeated failures or no recent report falls
+//! back to [`Mode::most_robust`]. The mode travels in every frame header, so the
+//! receiver never has to be told.
+
+use crate::frame::Mode;
+use crate::quality::LinkQuality;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Per-subcarrier SNR each mode needs on an AWGN channel, in [`Mode::ALL`] order.
+const REQUIRED_SNR_DB: [f32; 8] = [2.0, 5.5, 8.0, 11.0, 11.5, 14.0, 18.0, 21.0];
+const DEFAULT_MARGIN_DB: f32 = 1.0;
+const DEFAULT_HYSTERESIS_DB: f32 = 2.0;
+const DEFAULT_MAX_AGE_SECS: u64 = 300;
+const DEFAULT_MAX_FAILURES: u32 = 3;
+const SMOOTHING: f32 = 0.5;
+
+struct PeerState {
+    snr_db: f32,
+    mode: Mode,
+    updated: Instant,
+    failures: u32,
+}
+
+/// Chooses a mode for each destination from the link quality it reports back.
+pub struct ModePolicy {
+    required_snr_db: [f32; 8],
+    margin_db: f32,
+    hysteresis_db: f32,
+    max_age: Duration,
+    max_failures: u32,
+    peers: HashMap<String, PeerState>,
+}
+
+impl Default for ModePolicy {
+    fn default() -> Self {
+        Self {
+            required_snr_db: REQUIRED_SNR_DB,
+            margin_db: DEFAULT_MARGIN_DB,
+            hysteresis_db: DEFAULT_HYSTERESIS_DB,
+            max_age: Duration::from_secs(DEFAULT_MAX_AGE_SECS),
+            max_failures: DEFAULT_MAX_FAILURES,
+            peers: HashMap::new(),
+        }
+    }
+}
+
+impl ModePolicy {
+    /// Create a policy with the default thresholds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the SNR margin kept above each mode's requirement (default 1 dB).
+    pub fn with_margin_db(mut self, db: f32) -> Self {
+        self.margin_db = db;
+        self
+    }
+
+    /// Set the extra SNR needed before stepping up (default 2 dB).
+    pub fn with_hysteresis_db(mut self, db: f32) -> Self {
+        self.hysteresis_db = db.max(0.0);
+        self
+    }
+
+    /// Set how long a report stays valid (default 5 minutes).
+    pub fn with_max_age(mut self, age: Duration) -> Self {
+        self.max_age = age;
+        self
+    }
+
+    /// Set how many consecutive failures drop a peer to the most robust mode
+    /// (default 3).
+    pub fn with_max_failures(mut self, failures: u32) -> Self {
+        self.max_failures = failures.max(1);
+        self
+    }
+
+    /// Override the SNR `mode` needs on an AWGN channel.
+    pub fn with_required_snr_db(mut self, mode: Mode, db: f32) -> Self {
+        if let Some(id) = mode.id() {
+            self.required_snr_db[id as usize] = db;
+        }
+        self
+    }
+
+    /// Record the quality `peer` measured on a frame from us.
+    pub fn report(&mut self, peer: &str, quality: &LinkQuality, now: Instant) {
+        let key = peer.to_ascii_uppercase();
+        let measured = quality.effective_snr_db();
+        let (snr_db, mode) = match self.peers.get(&key) {
+            Some(state) => (
+                state.snr_db + SMOOTHING * (measured - state.snr_db),
+                state.mode,
+            ),
+            None => (measured, Mode::most_robust()),
+        };
+        let up = self.fastest(snr_db - self.margin_db - self.hysteresis_db);
+        let mode = if up.bits_per_carrier() > mode.bits_per_carrier() {
+            up
+        } else if snr_db < self.required(mode) + self.margin_db {
+            self.fastest(snr_db - self.margin_db)
+        } else {
+            mode
+        };
+        self.peers.insert(
+            key,
+            PeerState {
+                snr_db,
+                mode,
+                updated: now,
+                failures: 0,
+            },
+        );
+    }
+
+    /// Record that a frame to `peer` was not acknowledged.
+    pub fn report_failure(&mut self, peer: &str, now: Instant) {
+        let key = peer.to_ascii_uppercase();
+        let Some(state) = self.peers.get(&key) else {
+            return;
+        };
+        let failures = state.failures + 1;
+        let mode = if failures >= self.max_failures {
+            Mode::most_robust()
+        } else {
+            Mode::ALL[(state.mode.id().unwrap_or(0) as usize).saturating_sub(1)]
+        };
+        // Forget the SNR that led to the failed mode.
+        let snr_db = state.snr_db.min(self.required(mode) + self.margin_db);
+        self.peers.insert(
+            key,
+            PeerState {
+                snr_db,
+                mode,
+                updated: now,
+                failures,
+            },
+        );
+    }
+
+    /// Mode to use for the next frame to `peer`.
+    pub fn select(&self, peer: &str, now: Instant) -> Mode {
+        match self.peers.get(&peer.to_ascii_uppercase()) {
+            Some(state) if now.saturating_duration_since(state.updated) <= self.max_age => {
+                state.mode
+            }
+            _ => Mode::most_robust(),
+        }
+    }
+
+    /// Forget everything about `peer`.
+    pub fn forget(&mut self, peer: &str) {
+        self.peers.remove(&peer.to_ascii_uppercase());
+    }
+
+    fn required(&self, mode: Mode) -> f32 {
+        mode.id()
+            .map_or(f32::INFINITY, |id| self.required_snr_db[id as usize])
+    }
+
+    /// Fastest mode that works at `snr_db`; ties go to the more robust one.
+    fn fastest(&self, snr_db: f32) -> Mode {
+        Mode::ALL
+            .iter()
+            .copied()
+            .filter(|&m| self.required(m) <= snr_db)
+            .fold(Mode::most_robust(), |best, m| {
+                let faster = m.bits_per_carrier() > best.bits_per_carrier();
+                let same = m.bits_per_carrier() == best.bits_per_carrier();
+                if faster || (same && self.required(m) < self.required(best)) {
+                    m
+                } else {
+                    best
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Frame, Header, PilotPattern};
+    use rustfft::num_complex::Complex;
+
+    fn quality(snr_db: f32) -> LinkQuality {
+        LinkQuality {
+            snr_db,
+            carrier_snr_db: vec![snr_db; 104],
+            evm_db: -snr_db,
+            corrected_bits: 0,
+            coded_bits: 0,
+        }
+    }
+
+    #[test]
+    fn hysteresis_and_fallback() {
+        let now = Instant::now();
+        let mut policy = ModePolicy::new();
+        assert_eq!(policy.select("N0CALL", now), Mode::most_robust());
+
+        // Hovering around the QPSK 3/4 step-up point changes mode once, not every
+        // report.
+        let mut changes = 0;
+        let mut last = policy.select("N0CALL", now);
+        for i in 0..20 {
+            let snr = if i % 2 == 0 { 11.5 } else { 10.0 };
+            policy.report("n0call", &quality(snr), now);
+            let mode = policy.select("N0CALL", now);
+            changes += (mode != last) as usize;
+            last = mode;
+        }
+        assert!(changes <= 2, "{} changes", changes);
+        assert_eq!(last, Mode::ALL[2]);
+
+        policy.report_failure("N0CALL", now);
+        assert_eq!(policy.select("N0CALL", now), Mode::ALL[1]);
+        policy.report_failure("N0CALL", now);
+        policy.report_failure("N0CALL", now);
+        assert_eq!(policy.select("N0CALL", now), Mode::most_robust());
+
+        // Smoothing takes a few good reports to climb all the way back.
+        for _ in 0..4 {
+            policy.report("N0CALL", &quality(30.0), now);
+        }
+        assert_eq!(policy.select("N0CALL", now), Mode::ALL[7]);
+        let later = now + Duration::from_secs(DEFAULT_MAX_AGE_SECS + 1);
+        assert_eq!(policy.select("N0CALL", later), Mode::most_robust());
+    }
+
+    #[test]
+    fn adapts_to_simulated_channel() {
+        let pattern = PilotPattern::default();
+        let mut state = 0x1234_5678u32;
+        let mut uniform = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state as f32 / u32::MAX as f32).max(1e-9)
+        };
+        let now = Instant::now();
+        let mut rates = Vec::new();
+        for snr_db in [3.0f32, 9.0, 15.0, 24.0] {
+            let var = 10f32.powf(-snr_db / 10.0);
+            let mut policy = ModePolicy::new();
+            let mut delivered = 0;
+            let mut mode = Mode::most_robust();
+            for seq in 0..10u16 {
+                mode = policy.select("PEER", now);
+                let sent = Frame {
+                    header: Header {
+                        mode,
+                        destination: "PEER".into(),
+                        source: "N0CALL".into(),
+                        seq,
+                        flags: 0,
+                    },
+                    payload: (0..120).map(|i| (i * 7 + seq as usize) as u8).collect(),
+                };
+                let received: Vec<Vec<Complex<f32>>> = sent
+                    .encode(&pattern)
+                    .expect("encode")
+                    .into_iter()
+                    .map(|symbol| {
+                        symbol
+                            .into_iter()
+                            .map(|x| {
+                                let r = (-var * uniform().ln()).sqrt();
+                                x + Complex::from_polar(r, std::f32::consts::TAU * uniform())
+                            })
+                            .collect()
+                    })
+                    .collect();
+                match Frame::decode_with_quality(&received, &pattern) {
+                    Ok((frame, quality)) if frame == sent => {
+                        delivered += (seq >= 4) as usize;
+                        policy.report("PEER", &quality, now);
+                    }
+                    _ => policy.report_failure("PEER", now),
+                }
+            }
+            assert!(
+                delivered >= 5,
+                "{} dB: {} of 6 delivered",
+                snr_db,
+                delivered
+            );
+            rates.push(mode.bits_per_carrier());
+        }
+        assert_eq!(rates[0], Mode::most_robust().bits_per_carrier());
+        assert!(rates.windows(2).all(|w| w[1] > w[0]), "{:?}", rates);
+    }
+}
@@ -0,0 +1,233 @@
+//! Carrier frequency offset tracking.
+//!
+//! The preamble gives an initial offset estimate (see [`crate::SyncResult::cfo_hz`]),
+//! but SSB tuning drifts during a transmission. [`FrequencyTracker`] removes the offset
+//! with a numerically controlled oscillator and refines it from two measurements:
+//!
+//! - the cyclic prefix against the end of the symbol, once per symbol, which is coarse
+//!   but works before anything is demodulated (range ± half a subcarrier);
+//! - the phase advance of each scattered pilot since it was last sent on the same
+//!   subcarrier, which averages over many pilots and a longer baseline.
+
+use crate::config::OfdmConfig;
+use crate::pilots::PilotPattern;
+use rustfft::num_complex::Complex;
+use std::collections::VecDeque;
+use std::f64::consts::TAU;
+
+const DEFAULT_CP_GAIN: f32 = 0.1;
+const DEFAULT_PILOT_GAIN: f32 = 0.5;
+
+/// Frequency offset estimator and corrector for the symbols of one frame.
+pub struct FrequencyTracker {
+    sample_rate_hz: f64,
+    nfft: usize,
+    cp_len: usize,
+    pattern: PilotPattern,
+    cp_gain: f32,
+    pilot_gain: f32,
+    offset_hz: f64,
+    phase: f64,
+    symbol: usize,
+    history: VecDeque<Vec<Complex<f32>>>,
+}
+
+impl Default for FrequencyTracker {
+    fn default() -> Self {
+        Self::from_config(&OfdmConfig::default())
+    }
+}
+
+impl FrequencyTracker {
+    /// Create a tracker for `config`.
+    pub fn from_config(config: &OfdmConfig) -> Self {
+        Self {
+            sample_rate_hz: config.sample_rate_hz as f64,
+            nfft: config.nfft,
+            cp_len: config.cp_len,
+            pattern: config.pilot_pattern(),
+            cp_gain: DEFAULT_CP_GAIN,
+            pilot_gain: DEFAULT_PILOT_GAIN,
+            offset_hz: 0.0,
+            phase: 0.0,
+            symbol: 0,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Set the loop gains for the cyclic prefix and pilot estimates (0 disables one).
+    pub fn with_loop_gains(mut self, cp: f32, pilot: f32) -> Self {
+        self.cp_gain = cp.clamp(0.0, 1.0);
+        self.pilot_gain = pilot.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Start a new frame from the preamble estimate. The next symbol passed to
+    /// [`Self::correct`] is frame symbol 0.
+    pub fn start(&mut self, offset_hz: f32) {
+        self.offset_hz = offset_hz as f64;
+        self.phase = 0.0;
+        self.symbol = 0;
+        self.history.clear();
+    }
+
+    /// Current offset estimate in Hz: positive means the received signal is high.
+    pub fn offset_hz(&self) -> f32 {
+        self.offset_hz as f32
+    }
+
+    /// Remove the offset from the next symbol (with its cyclic prefix) in place and
+    /// update the estimate from its cyclic prefix. Symbols must be passed back to back.
+    pub fn correct(&mut self, symbol: &mut [Complex<f32>]) -> Result<(), String> {
+        if symbol.len() != self.nfft + self.cp_len {
+            return Err(format!(
+                "expected {} samples, got {}",
+                self.nfft + self.cp_len,
+                symbol.len()
+            ));
+        }
+        let step = TAU * self.offset_hz / self.sample_rate_hz;
+        for s in symbol.iter_mut() {
+            *s *= Complex::from_polar(1.0, -self.phase as f32);
+            self.phase = (self.phase + step).rem_euclid(TAU);
+        }
+
+        // Skip the start of the prefix, where multipath from the previous symbol lands.
+        let mut z = Complex::new(0.0f64, 0.0);
+        let mut energy = 0.0f64;
+        for n in self.cp_len / 4..self.cp_len {
+            let p = symbol[n].conj() * symbol[n + self.nfft];
+            z += Complex::new(p.re as f64, p.im as f64);
+            energy += p.norm() as f64;
+        }
+        if energy > 0.0 {
+            let residual = z.arg() * self.sample_rate_hz / (TAU * self.nfft as f64);
+            self.offset_hz += self.cp_gain as f64 * coherence(z, energy) * residual;
+        }
+        Ok(())
+    }
+
+    /// Update the estimate from the pilots of the symbol just corrected and
+    /// demodulated.
+    pub fn observe(&mut self, subcarriers: &[Complex<f32>]) -> Result<(), String> {
+        if subcarriers.len() != self.pattern.carriers() {
+            return Err(format!(
+                "expected {} subcarriers, got {}",
+                self.pattern.carriers(),
+                subcarriers.len()
+            ));
+        }
+        let period = self.pattern.period();
+        if self.history.len() == period {
+            // The pattern repeats every `period` symbols, so the oldest entry has its
+            // pilots on the same subcarriers. The known pilot values cancel.
+            let old = &self.history[0];
+            let mut z = Complex::new(0.0f64, 0.0);
+            let mut energy = 0.0f64;
+            for c in (0..subcarriers.len()).filter(|&c| self.pattern.is_pilot(self.symbol, c)) {
+                let p = subcarriers[c] * old[c].conj();
+                z += Complex::new(p.re as f64, p.im as f64);
+                energy += p.norm() as f64;
+            }
+            if energy > 0.0 {
+                let baseline = (period * (self.nfft + self.cp_len)) as f64;
+                let residual = z.arg() * self.sample_rate_hz / (TAU * baseline);
+                self.offset_hz += self.pilot_gain as f64 * coherence(z, energy) * residual;
+            }
+            self.history.pop_front();
+        }
+        self.history.push_back(subcarriers.to_vec());
+        self.symbol += 1;
+        Ok(())
+    }
+}
+
+/// Weight for an estimate from a correlation sum: near 1 when the terms agree in
+/// phase, small when noise scatters them, so noisy symbols barely move the loop.
+pub(crate) fn coherence(sum: Complex<f64>, energy: f64) -> f64 {
+    (sum.norm() / energy).powi(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Frame, Header, Mode, OfdmDemodulator, OfdmModulator, PreambleDetector};
+
+    /// A frame whose offset drifts linearly from `start_hz` to `end_hz`.
+    fn drifting(start_hz: f64, end_hz: f64) -> (Frame, Vec<Complex<f32>>, usize) {
+        let config = OfdmConfig::default();
+        let frame = Frame {
+            header: Header {
+                mode: Mode::ALL[1],
+                destination: "CQ".into(),
+                source: "N0CALL".into(),
+                seq: 9,
+                flags: 0,
+            },
+            payload: (0..400).map(|i| (i * 13) as u8).collect(),
+        };
+        let symbols = frame.encode(&config.pilot_pattern()).expect("encode");
+        let mut stream = vec![Complex::new(0.0, 0.0); 1500];
+        stream.extend(
+            OfdmModulator::new()
+                .modulate_frame(&symbols)
+                .expect("modulate"),
+        );
+        stream.extend(vec![Complex::new(0.0, 0.0); 2 * config.symbol_len()]);
+        let len = stream.len() as f64;
+        let mut phase = 0.0f64;
+        for (n, s) in stream.iter_mut().enumerate() {
+            let hz = start_hz + (end_hz - start_hz) * n as f64 / len;
+            *s *= Complex::from_polar(1.0, phase as f32);
+            phase += TAU * hz / 48_000.0;
+        }
+        (frame, stream, symbols.len())
+    }
+
+    fn receive(
+        tracker: Option<&mut FrequencyTracker>,
+        stream: &[Complex<f32>],
+        count: usize,
+    ) -> Vec<Vec<Complex<f32>>> {
+        let sync = PreambleDetector::new().push(stream);
+        assert_eq!(sync.len(), 1);
+        let demod = OfdmDemodulator::new();
+        let first = sync[0].start as usize + 2 * 2304;
+        let mut tracker = tracker;
+        if let Some(t) = tracker.as_deref_mut() {
+            t.start(sync[0].cfo_hz(48_000.0, 2048));
+        }
+        (0..count)
+            .map(|i| {
+                let start = first + i * 2304;
+                let mut symbol = stream[start..start + 2304].to_vec();
+                match tracker.as_deref_mut() {
+                    Some(t) => {
+                        t.correct(&mut symbol).expect("correct");
+                        let out = demod.demodulate(&symbol).expect("demodulate");
+                        t.observe(&out).expect("observe");
+                        out
+                    }
+                    None => demod.demodulate(&symbol).expect("demodulate"),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tracks_drifting_offset() {
+        let (frame, stream, count) = drifting(37.0, 45.0);
+        let pattern = PilotPattern::default();
+        assert!(Frame::decode(&receive(None, &stream, count), &pattern).is_err());
+
+        let mut tracker = FrequencyTracker::default();
+        let received = receive(Some(&mut tracker), &stream, count);
+        assert_eq!(Frame::decode(&received, &pattern).expect("decode"), frame);
+        // The last symbol ends a little before the end of the stream.
+        assert!(
+            (tracker.offset_hz() - 44.0).abs() < 1.0,
+            "{}",
+            tracker.offset_hz()
+        );
+    }
+}
@@ -0,0 +1,677 @@
+//! Selective-repeat ARQ sessions over half-duplex links.
+//!
+//! The session layer rides on [`Frame`]: the low nibble of the header flags holds the
+//! [`FrameKind`], data frames are numbered with the header sequence number, and an
+//! ACK frame's payload is the next sequence number expected in order (2 bytes) plus a
+//! bitmap of the 64 after it that have arrived (8 bytes, bit 0 first).
+//!
+//! Only one station talks at a time, so an ACK always follows the whole burst it
+//! answers: anything in that burst it does not cover was lost and is sent again right
+//! away. When no ACK arrives, the retransmission timer allows for both stations keying
+//! up and dropping (see [`ArqConfig::ack_timeout`]).
+//!
+//! [`ArqSession`] does no I/O. Call [`ArqSession::poll_transmit`] when it is our turn
+//! to talk, [`ArqSession::transmitted`] once the burst is off the air, and
+//! [`ArqSession::receive`] for every decoded frame.
+
+use crate::config::OfdmConfig;
+use crate::frame::{Frame, Header, Mode};
+use crate::sync::PREAMBLE_SYMBOLS;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Flag bits holding the frame kind.
+pub const KIND_MASK: u8 = 0x0f;
+/// Flag bit marking the last segment of a message.
+pub const FLAG_END_OF_MESSAGE: u8 = 0x10;
+/// Largest window the ACK bitmap can describe.
+pub const MAX_WINDOW: u16 = 64;
+
+const ACK_PAYLOAD_BYTES: usize = 10;
+
+/// Session frame types, carried in the header flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Data,
+    Ack,
+    Open,
+    OpenAck,
+    Close,
+    CloseAck,
+}
+
+impl FrameKind {
+    const ALL: [FrameKind; 6] = [
+        FrameKind::Data,
+        FrameKind::Ack,
+        FrameKind::Open,
+        FrameKind::OpenAck,
+        FrameKind::Close,
+        FrameKind::CloseAck,
+    ];
+
+    /// Kind encoded in `flags`.
+    pub fn from_flags(flags: u8) -> Option<Self> {
+        Self::ALL.get((flags & KIND_MASK) as usize).copied()
+    }
+
+    fn flags(self) -> u8 {
+        Self::ALL.iter().position(|&k| k == self).unwrap_or(0) as u8
+    }
+}
+
+/// Session state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    Closed,
+    Opening,
+    Open,
+    Closing,
+    /// Retries ran out; the link is gone.
+    Failed,
+}
+
+/// ARQ parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArqConfig {
+    /// Frames in flight before waiting for an ACK (at most [`MAX_WINDOW`]).
+    pub window: u16,
+    /// Largest payload per data frame in bytes.
+    pub max_payload: usize,
+    /// Retransmissions of one frame before the session fails.
+    pub max_retries: u32,
+    /// Time from keying up to the radio passing audio.
+    pub tx_lead: Duration,
+    /// Time the radio stays keyed after the last sample.
+    pub tx_hang: Duration,
+    /// Air time of an ACK, the longest control frame.
+    pub ack_airtime: Duration,
+}
+
+impl Default for ArqConfig {
+    fn default() -> Self {
+        Self::from_config(&OfdmConfig::default())
+    }
+}
+
+impl ArqConfig {
+    /// Default parameters, with the ACK air time worked out for `config`.
+    pub fn from_config(config: &OfdmConfig) -> Self {
+        let symbols = PREAMBLE_SYMBOLS
+            + Frame::symbol_count(
+                Mode::most_robust(),
+                ACK_PAYLOAD_BYTES,
+                &config.pilot_pattern(),
+            );
+        Self {
+            window: 16,
+            max_payload: 256,
+            max_retries: 5,
+            tx_lead: Duration::from_millis(200),
+            tx_hang: Duration::from_millis(1000),
+            ack_airtime: Duration::from_secs_f32(symbols as f32 * config.symbol_secs()),
+        }
+    }
+
+    /// How long to wait for an ACK after our burst is off the air: our own hang time,
+    /// the peer keying up, its ACK, and its hang time before we hear the channel free.
+    pub fn ack_timeout(&self) -> Duration {
+        2 * (self.tx_lead + self.tx_hang) + self.ack_airtime
+    }
+}
+
+struct Outgoing {
+    payload: Vec<u8>,
+    end_of_message: bool,
+    retries: u32,
+    /// `None` while waiting to be sent or until the burst is reported transmitted.
+    deadline: Option<Instant>,
+    queued: bool,
+}
+
+struct Control {
+    kind: FrameKind,
+    retries: u32,
+    deadline: Option<Instant>,
+    queued: bool,
+}
+
+/// One end of a reliable session with a single peer.
+pub struct ArqSession {
+    local: String,
+    remote: String,
+    config: ArqConfig,
+    mode: Mode,
+    state: SessionState,
+    segments: VecDeque<(Vec<u8>, bool)>,
+    outgoing: BTreeMap<u64, Outgoing>,
+    next_seq: u64,
+    control: Option<Control>,
+    replies: Vec<FrameKind>,
+    received: BTreeMap<u64, (Vec<u8>, bool)>,
+    rx_base: u64,
+    partial: Vec<u8>,
+    messages: VecDeque<Vec<u8>>,
+}
+
+impl ArqSession {
+    /// Create a closed session between `local` and `remote`.
+    pub fn new(local: &str, remote: &str, config: ArqConfig) -> Self {
+        let config = ArqConfig {
+            window: config.window.clamp(1, MAX_WINDOW),
+            max_payload: config.max_payload.max(1),
+            ..config
+        };
+        Self {
+            local: local.to_ascii_uppercase(),
+            remote: remote.to_ascii_uppercase(),
+            config,
+            mode: Mode::most_robust(),
+            state: SessionState::Closed,
+            segments: VecDeque::new(),
+            outgoing: BTreeMap::new(),
+            next_seq: 0,
+            control: None,
+            replies: Vec::new(),
+            received: BTreeMap::new(),
+            rx_base: 0,
+            partial: Vec::new(),
+            messages: VecDeque::new(),
+        }
+    }
+
+    /// Current state.
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// Set the mode for data frames (control frames always use the most robust one).
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// Start opening the session.
+    pub fn open(&mut self) {
+        self.state = SessionState::Opening;
+        self.control = Some(Control::new(FrameKind::Open));
+    }
+
+    /// Close the session once everything queued has been acknowledged.
+    pub fn close(&mut self) {
+        if matches!(self.state, SessionState::Open | SessionState::Opening) {
+            self.state = SessionState::Closing;
+            self.start_close();
+        }
+    }
+
+    /// Queue a message for delivery, split into frames of at most
+    /// [`ArqConfig::max_payload`] bytes.
+    pub fn send(&mut self, message: &[u8]) -> Result<(), String> {
+        if !matches!(self.state, SessionState::Open | SessionState::Opening) {
+            return Err(format!("session is {:?}", self.state));
+        }
+        if message.is_empty() {
+            self.segments.push_back((Vec::new(), true));
+            return Ok(());
+        }
+        let chunks = message.chunks(self.config.max_payload);
+        let last = chunks.len() - 1;
+        for (i, chunk) in chunks.enumerate() {
+            self.segments.push_back((chunk.to_vec(), i == last));
+        }
+        Ok(())
+    }
+
+    /// Next message delivered in order, if one is complete.
+    pub fn recv(&mut self) -> Option<Vec<u8>> {
+        self.messages.pop_front()
+    }
+
+    /// Frames sent or queued but not yet acknowledged.
+    pub fn pending(&self) -> usize {
+        self.outgoing.len() + self.segments.len()
+    }
+
+    /// Earliest retransmission deadline.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.outgoing
+            .values()
+            .filter_map(|o| o.deadline)
+            .chain(self.control.as_ref().and_then(|c| c.deadline))
+            .min()
+    }
+
+    /// Frames to send now, in order: replies and ACKs, retransmissions, then new data
+    /// within the window.
+    pub fn poll_transmit(&mut self, now: Instant) -> Vec<Frame> {
+        self.expire(now);
+        let replies = std::mem::take(&mut self.replies);
+        let mut frames: Vec<Frame> = replies
+            .into_iter()
+            .map(|kind| self.control_frame(kind))
+            .collect();
+        if let Some(control) = self.control.as_mut().filter(|c| c.queued) {
+            control.queued = false;
+            let kind = control.kind;
+            frames.push(self.control_frame(kind));
+        }
+        if self.state != SessionState::Open && self.state != SessionState::Closing {
+            return frames;
+        }
+        while self.next_seq < self.base() + self.config.window as u64 {
+            let Some((payload, end_of_message)) = self.segments.pop_front() else {
+                break;
+            };
+            self.outgoing.insert(
+                self.next_seq,
+                Outgoing {
+                    payload,
+                    end_of_message,
+                    retries: 0,
+                    deadline: None,
+                    queued: true,
+                },
+            );
+            self.next_seq += 1;
+        }
+        let mode = self.mode;
+        for (&seq, out) in self.outgoing.iter_mut().filter(|(_, o)| o.queued) {
+            out.queued = false;
+            let mut flags = FrameKind::Data.flags();
+            if out.end_of_message {
+                flags |= FLAG_END_OF_MESSAGE;
+            }
+            frames.push(Frame {
+                header: Header {
+                    mode,
+                    destination: self.remote.clone(),
+                    source: self.local.clone(),
+                    seq: seq as u16,
+                    flags,
+                },
+                payload: out.payload.clone(),
+            });
+        }
+        frames
+    }
+
+    /// The burst from the last [`Self::poll_transmit`] has finished; start its
+    /// retransmission timers.
+    pub fn transmitted(&mut self, now: Instant) {
+        let deadline = now + self.config.ack_timeout();
+        for out in self.outgoing.values_mut() {
+            if out.deadline.is_none() && !out.queued {
+                out.deadline = Some(deadline);
+            }
+        }
+        if let Some(control) = self.control.as_mut() {
+            if control.deadline.is_none() && !control.queued {
+                control.deadline = Some(deadline);
+            }
+        }
+    }
+
+    /// Handle a decoded frame. Frames for other stations are ignored.
+    pub fn receive(&mut self, frame: &Frame) {
+        let header = &frame.header;
+        if !header.destination.eq_ignore_ascii_case(&self.local)
+            || !header.source.eq_ignore_ascii_case(&self.remote)
+        {
+            return;
+        }
+        let Some(kind) = FrameKind::from_flags(header.flags) else {
+            return;
+        };
+        match kind {
+            FrameKind::Open => {
+                if self.state != SessionState::Open {
+                    self.reset_receiver();
+                }
+                self.state = SessionState::Open;
+                self.replies.push(FrameKind::OpenAck);
+            }
+            FrameKind::OpenAck => {
+                if self.state == SessionState::Opening {
+                    self.state = SessionState::Open;
+                    self.control = None;
+                } else if self.state == SessionState::Closing
+                    && self.control.as_ref().map(|c| c.kind) == Some(FrameKind::Open)
+                {
+                    self.control = None;
+                    self.start_close();
+                }
+            }
+            FrameKind::Close => {
+                self.state = SessionState::Closed;
+                self.control = None;
+                self.replies.push(FrameKind::CloseAck);
+            }
+            FrameKind::CloseAck => {
+                if self.state == SessionState::Closing {
+                    self.state = SessionState::Closed;
+                    self.control = None;
+                }
+            }
+            FrameKind::Data => self.receive_data(header, &frame.payload),
+            FrameKind::Ack => self.receive_ack(&frame.payload),
+        }
+    }
+
+    fn receive_data(&mut self, header: &Header, payload: &[u8]) {
+        if self.state != SessionState::Open && self.state != SessionState::Closing {
+            return;
+        }
+        // Re-ACK duplicates too: our last ACK may have been lost.
+        if !self.replies.contains(&FrameKind::Ack) {
+            self.replies.push(FrameKind::Ack);
+        }
+        let offset = header.seq.wrapping_sub(self.rx_base as u16);
+        if offset >= MAX_WINDOW {
+            return;
+        }
+        let end_of_message = header.flags & FLAG_END_OF_MESSAGE != 0;
+        self.received.insert(
+            self.rx_base + offset as u64,
+            (payload.to_vec(), end_of_message),
+        );
+        while let Some((segment, end)) = self.received.remove(&self.rx_base) {
+            self.partial.extend(segment);
+            if end {
+                self.messages.push_back(std::mem::take(&mut self.partial));
+            }
+            self.rx_base += 1;
+        }
+    }
+
+    fn receive_ack(&mut self, payload: &[u8]) {
+        if payload.len() != ACK_PAYLOAD_BYTES {
+            return;
+        }
+        let base = self.base();
+        let offset = u16::from_be_bytes([payload[0], payload[1]]).wrapping_sub(base as u16);
+        if base + offset as u64 > self.next_seq {
+            return;
+        }
+        let acked = base + offset as u64;
+        let mut bitmap = [0u8; 8];
+        bitmap.copy_from_slice(&payload[2..]);
+        let bitmap = u64::from_be_bytes(bitmap);
+
+        self.outgoing.retain(|&seq, _| {
+            seq >= acked && (seq - acked == 0 || bitmap & (1 << (seq - acked - 1)) == 0)
+        });
+        // The ACK answers our whole last burst, so anything sent that it does not
+        // cover is lost.
+        let mut failed = false;
+        for out in self.outgoing.values_mut().filter(|o| o.deadline.is_some()) {
+            out.deadline = None;
+            out.queued = true;
+            out.retries += 1;
+            failed |= out.retries > self.config.max_retries;
+        }
+        if failed {
+            self.fail();
+        } else if self.state == SessionState::Closing {
+            self.start_close();
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let mut failed = false;
+        for out in self.outgoing.values_mut() {
+            if out.deadline.is_some_and(|d| d <= now) {
+                out.deadline = None;
+                out.queued = true;
+                out.retries += 1;
+                failed |= out.retries > self.config.max_retries;
+            }
+        }
+        if let Some(control) = self.control.as_mut() {
+            if control.deadline.is_some_and(|d| d <= now) {
+                control.deadline = None;
+                control.queued = true;
+                control.retries += 1;
+                failed |= control.retries > self.config.max_retries;
+            }
+        }
+        if failed {
+            self.fail();
+        }
+    }
+
+    fn fail(&mut self) {
+        self.state = SessionState::Failed;
+        self.outgoing.clear();
+        self.segments.clear();
+        self.control = None;
+    }
+
+    /// Send the close request once all data is acknowledged.
+    fn start_close(&mut self) {
+        let opening = self.control.as_ref().map(|c| c.kind) == Some(FrameKind::Open);
+        if self.control.is_none() && !opening && self.pending() == 0 {
+            self.control = Some(Control::new(FrameKind::Close));
+        }
+    }
+
+    fn reset_receiver(&mut self) {
+        self.received.clear();
+        self.rx_base = 0;
+        self.partial.clear();
+    }
+
+    fn base(&self) -> u64 {
+        self.outgoing
+            .keys()
+            .next()
+            .copied()
+            .unwrap_or(self.next_seq)
+    }
+
+    fn control_frame(&self, kind: FrameKind) -> Frame {
+        let payload = if kind == FrameKind::Ack {
+            let mut bitmap = 0u64;
+            for &seq in self.received.keys() {
+                let offset = seq - self.rx_base - 1;
+                if offset < 64 {
+                    bitmap |= 1 << offset;
+                }
+            }
+            let mut payload = (self.rx_base as u16).to_be_bytes().to_vec();
+            payload.extend(bitmap.to_be_bytes());
+            payload
+        } else {
+            Vec::new()
+        };
+        Frame {
+            header: Header {
+                mode: Mode::most_robust(),
+                destination: self.remote.clone(),
+                source: self.local.clone(),
+                seq: 0,
+                flags: kind.flags(),
+            },
+            payload,
+        }
+    }
+}
+
+impl Control {
+    fn new(kind: FrameKind) -> Self {
+        Self {
+            kind,
+            retries: 0,
+            deadline: None,
+            queued: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OfdmModulator, PilotPattern};
+
+    const AIRTIME: Duration = Duration::from_millis(700);
+
+    /// One station's turn: poll, "transmit" and deliver the frames that survive.
+    fn over(
+        from: &mut ArqSession,
+        to: &mut ArqSession,
+        now: &mut Instant,
+        lost: &mut impl FnMut(&Frame) -> bool,
+    ) -> usize {
+        let frames = from.poll_transmit(*now);
+        *now += AIRTIME * frames.len() as u32;
+        from.transmitted(*now);
+        for frame in &frames {
+            if !lost(frame) {
+                to.receive(frame);
+            }
+        }
+        frames.len()
+    }
+
+    #[test]
+    fn delivers_messages_over_lossy_link() {
+        let config = ArqConfig {
+            window: 4,
+            ..ArqConfig::default()
+        };
+        let mut a = ArqSession::new("N0CALL", "W1AW", config.clone());
+        let mut b = ArqSession::new("W1AW", "N0CALL", config);
+        let messages: Vec<Vec<u8>> = vec![
+            b"hello".to_vec(),
+            (0..1000).map(|i| (i % 251) as u8).collect(),
+            Vec::new(),
+            b"73".to_vec(),
+        ];
+        a.open();
+        for m in &messages {
+            a.send(m).expect("send");
+        }
+        a.close();
+
+        // Lose every third data frame and the first two ACKs.
+        let mut data_count = 0;
+        let mut ack_count = 0;
+        let mut now = Instant::now();
+        for _ in 0..100 {
+            if a.state() == SessionState::Closed && b.state() == SessionState::Closed {
+                break;
+            }
+            let sent = over(&mut a, &mut b, &mut now, &mut |f| {
+                let data = FrameKind::from_flags(f.header.flags) == Some(FrameKind::Data);
+                data_count += data as usize;
+                data && data_count % 3 == 0
+            });
+            let answered = over(&mut b, &mut a, &mut now, &mut |f| {
+                let ack = FrameKind::from_flags(f.header.flags) == Some(FrameKind::Ack);
+                ack_count += ack as usize;
+                ack && ack_count <= 2
+            });
+            if sent == 0 && answered == 0 {
+                now = a.next_deadline().expect("waiting on a timer");
+            }
+        }
+        assert_eq!(a.state(), SessionState::Closed);
+        assert_eq!(b.state(), SessionState::Closed);
+        let received: Vec<Vec<u8>> = std::iter::from_fn(|| b.recv()).collect();
+        assert_eq!(received, messages);
+    }
+
+    #[test]
+    fn retransmits_after_turnaround_and_gives_up() {
+        let config = ArqConfig::default();
+        let timeout = config.ack_timeout();
+        assert!(timeout > config.tx_lead + config.tx_hang);
+        let mut a = ArqSession::new("N0CALL", "W1AW", config.clone());
+        a.open();
+        let mut now = Instant::now();
+        for _ in 0..=config.max_retries {
+            let frames = a.poll_transmit(now);
+            assert_eq!(frames.len(), 1);
+            assert_eq!(
+                FrameKind::from_flags(frames[0].header.flags),
+                Some(FrameKind::Open)
+            );
+            a.transmitted(now);
+            assert!(a
+                .poll_transmit(now + timeout - Duration::from_millis(1))
+                .is_empty());
+            now += timeout;
+        }
+        assert!(a.poll_transmit(now).is_empty());
+        assert_eq!(a.state(), SessionState::Failed);
+    }
+
+    #[test]
+    fn session_frames_survive_the_modem() {
+        let pattern = PilotPattern::default();
+        let mut a = ArqSession::new("N0CALL", "W1AW", ArqConfig::default());
+        let mut b = ArqSession::new("W1AW", "N0CALL", ArqConfig::default());
+        a.open();
+        a.send(b"over the air").expect("send");
+        let now = Instant::now();
+        let relay = |from: &mut ArqSession, to: &mut ArqSession| {
+            for frame in from.poll_transmit(now) {
+                let symbols = frame.encode(&pattern).expect("encode");
+                to.receive(&Frame::decode(&symbols, &pattern).expect("decode"));
+            }
+        };
+        // Open and its ACK, then the data and its ACK.
+        for _ in 0..2 {
+            relay(&mut a, &mut b);
+            relay(&mut b, &mut a);
+        }
+        assert_eq!(b.recv().as_deref(), Some(&b"over the air"[..]));
+        assert_eq!(a.state(), SessionState::Open);
+        assert_eq!(a.pending(), 0);
+    }
+
+    #[test]
+    fn real_ack_turnaround_does_not_retransmit() {
+        let ofdm = OfdmConfig::default();
+        let config = ArqConfig::from_config(&ofdm);
+        let mut a = ArqSession::new("N0CALL", "W1AW", config.clone());
+        let mut b = ArqSession::new("W1AW", "N0CALL", config.clone());
+        let mut now = Instant::now();
+        a.open();
+        for _ in 0..2 {
+            for frame in a.poll_transmit(now) {
+                b.receive(&frame);
+            }
+            for frame in b.poll_transmit(now) {
+                a.receive(&frame);
+            }
+        }
+        assert_eq!(a.state(), SessionState::Open);
+
+        a.send(b"are you there").expect("send");
+        let burst = a.poll_transmit(now);
+        a.transmitted(now);
+        for frame in &burst {
+            b.receive(frame);
+        }
+        let acks = b.poll_transmit(now);
+        assert_eq!(acks.len(), 1);
+        assert_eq!(
+            FrameKind::from_flags(acks[0].header.flags),
+            Some(FrameKind::Ack)
+        );
+
+        // Our hang, the peer keying up, the ACK as the modem sends it, and the peer's
+        // hang before we hear the channel free.
+        let symbols = acks[0].encode(&ofdm.pilot_pattern()).expect("encode");
+        let samples = OfdmModulator::new()
+            .modulate_frame(&symbols)
+            .expect("modulate")
+            .len();
+        let airtime = Duration::from_secs_f32(samples as f32 / ofdm.sample_rate_hz);
+        assert!(config.ack_airtime.abs_diff(airtime) < Duration::from_millis(1));
+        now += config.tx_hang + config.tx_lead + airtime + config.tx_hang;
+        assert!(a.poll_transmit(now).is_empty());
+        a.receive(&acks[0]);
+        assert_eq!(a.pending(), 0);
+    }
+}
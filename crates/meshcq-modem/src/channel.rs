@@ -0,0 +1,313 @@
+//! Pilot-based channel estimation and per-subcarrier equalisation.
+
+use crate::pilots::PilotPattern;
+use rustfft::num_complex::Complex;
+
+/// Channel estimate for a run of OFDM symbols.
+pub struct ChannelEstimate {
+    /// Complex gain per symbol and subcarrier.
+    pub gains: Vec<Vec<Complex<f32>>>,
+    /// Estimated noise variance per subcarrier (for unit-power pilots).
+    pub noise_var: f32,
+}
+
+impl ChannelEstimate {
+    /// Estimate the channel from the pilots in `symbols`.
+    ///
+    /// `symbols` holds the received subcarrier values of consecutive symbols, the first
+    /// of which is symbol 0 of `pattern`. Pilots are divided out (least squares), then
+    /// interpolated linearly across time on each pilot-bearing subcarrier, and finally
+    /// across frequency to fill the remaining subcarriers.
+    pub fn estimate(pattern: &PilotPattern, symbols: &[Vec<Complex<f32>>]) -> Result<Self, String> {
+        let carriers = pattern.carriers();
+        if symbols.is_empty() {
+            return Err("no symbols to estimate from".into());
+        }
+        if let Some(sym) = symbols.iter().find(|s| s.len() != carriers) {
+            return Err(format!(
+                "expected {} subcarriers, got {}",
+                carriers,
+                sym.len()
+            ));
+        }
+
+        let mut grid: Vec<Vec<Option<Complex<f32>>>> = symbols
+            .iter()
+            .enumerate()
+            .map(|(t, sym)| {
+                (0..carriers)
+                    .map(|c| pattern.is_pilot(t, c).then(|| sym[c] / pattern.value(c)))
+                    .collect()
+            })
+            .collect();
+        let noise_var = estimate_noise(pattern, &grid);
+
+        // Across time on each subcarrier that carries pilots.
+        for c in 0..carriers {
+            let known: Vec<(usize, Complex<f32>)> = grid
+                .iter()
+                .enumerate()
+                .filter_map(|(t, row)| row[c].map(|h| (t, h)))
+                .collect();
+            if known.is_empty() {
+                continue;
+            }
+            for (t, row) in grid.iter_mut().enumerate() {
+                if row[c].is_none() {
+                    row[c] = Some(interpolate(&known, t, false));
+                }
+            }
+        }
+
+        // Across frequency for the rest.
+        let gains = grid
+            .into_iter()
+            .map(|row| {
+                let known: Vec<(usize, Complex<f32>)> = row
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(c, h)| h.map(|h| (c, h)))
+                    .collect();
+                (0..carriers)
+                    .map(|c| row[c].unwrap_or_else(|| interpolate(&known, c, true)))
+                    .collect()
+            })
+            .collect();
+
+        Ok(Self { gains, noise_var })
+    }
+}
+
+/// Linear interpolation between known points sorted by position.
+///
+/// Outside the known range the nearest value is held, or the end segment is extended
+/// when `extrapolate` is set.
+fn interpolate(known: &[(usize, Complex<f32>)], x: usize, extrapolate: bool) -> Complex<f32> {
+    if known.len() == 1 {
+        return known[0].1;
+    }
+    let idx = known.partition_point(|&(k, _)| k < x);
+    let (a, b) = if idx == 0 {
+        if !extrapolate {
+            return known[0].1;
+        }
+        (known[0], known[1])
+    } else if idx == known.len() {
+        if !extrapolate {
+            return known[idx - 1].1;
+        }
+        (known[idx - 2], known[idx - 1])
+    } else {
+        (known[idx - 1], known[idx])
+    };
+    let frac = (x as f32 - a.0 as f32) / (b.0 as f32 - a.0 as f32);
+    a.1 + (b.1 - a.1) * frac
+}
+
+/// Noise variance from pilots repeated on the same subcarrier one pattern period
+/// apart, falling back to neighbouring pilots within a symbol for short runs.
+fn estimate_noise(pattern: &PilotPattern, grid: &[Vec<Option<Complex<f32>>>]) -> f32 {
+    let period = pattern.period();
+    let mut sum = 0.0f32;
+    let mut count = 0usize;
+    for t in 0..grid.len().saturating_sub(period) {
+        for (a, b) in grid[t].iter().zip(&grid[t + period]) {
+            if let (Some(a), Some(b)) = (a, b) {
+                sum += (a - b).norm_sqr();
+                count += 1;
+            }
+        }
+    }
+    if count == 0 {
+        for row in grid {
+            let pilots: Vec<Complex<f32>> = row.iter().flatten().copied().collect();
+            for pair in pilots.windows(2) {
+                sum += (pair[1] - pair[0]).norm_sqr();
+                count += 1;
+            }
+        }
+    }
+    if count == 0 {
+        0.0
+    } else {
+        // Each difference carries the noise of two estimates.
+        sum / (2 * count) as f32
+    }
+}
+
+/// Per-subcarrier equaliser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Equalizer {
+    /// Divide by the channel gain.
+    ZeroForcing,
+    /// Minimum mean-square error, assuming unit-power data.
+    Mmse,
+}
+
+/// Equalised subcarrier values with their per-subcarrier error variance.
+pub struct Equalized {
+    pub symbols: Vec<Complex<f32>>,
+    pub noise_var: Vec<f32>,
+}
+
+impl Equalizer {
+    /// Equalise one symbol given its channel gains and the noise variance.
+    ///
+    /// Zero-forcing output is unbiased but amplifies noise on faded subcarriers. MMSE
+    /// output is scaled by `|h|^2 / (|h|^2 + noise_var)`, trading that bias for less
+    /// noise; the reported variance is the mean-square error in either case.
+    pub fn equalize(
+        self,
+        symbol: &[Complex<f32>],
+        gains: &[Complex<f32>],
+        noise_var: f32,
+    ) -> Result<Equalized, String> {
+        if symbol.len() != gains.len() {
+            return Err(format!(
+                "expected {} subcarriers, got {}",
+                gains.len(),
+                symbol.len()
+            ));
+        }
+        let noise_var = noise_var.max(f32::MIN_POSITIVE);
+        let (symbols, noise_var) = symbol
+            .iter()
+            .zip(gains)
+            .map(|(&y, &h)| {
+                let power = h.norm_sqr();
+                match self {
+                    Self::ZeroForcing => {
+                        let power = power.max(f32::MIN_POSITIVE);
+                        (y * h.conj() / power, noise_var / power)
+                    }
+                    Self::Mmse => {
+                        let denom = power + noise_var;
+                        (y * h.conj() / denom, noise_var / denom)
+                    }
+                }
+            })
+            .unzip();
+        Ok(Equalized { symbols, noise_var })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Symbols = Vec<Vec<Complex<f32>>>;
+
+    struct Noise(u32);
+
+    impl Noise {
+        fn uniform(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 as f32 / u32::MAX as f32
+        }
+
+        fn complex(&mut self, var: f32) -> Complex<f32> {
+            // Box-Muller.
+            let u1 = self.uniform().max(1e-9);
+            let u2 = self.uniform();
+            let r = (-var * u1.ln()).sqrt();
+            let phi = 2.0 * std::f32::consts::PI * u2;
+            Complex::new(r * phi.cos(), r * phi.sin())
+        }
+    }
+
+    fn qpsk(noise: &mut Noise) -> Complex<f32> {
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        let re = if noise.uniform() < 0.5 { s } else { -s };
+        let im = if noise.uniform() < 0.5 { s } else { -s };
+        Complex::new(re, im)
+    }
+
+    /// Two-path channel with a slowly rotating echo.
+    fn channel(t: usize, c: usize) -> Complex<f32> {
+        let delay = 0.6 * c as f32 / 104.0 * 2.0 * std::f32::consts::PI;
+        let drift = 0.02 * t as f32;
+        Complex::new(1.0, 0.0) + Complex::from_polar(0.5, -delay + drift)
+    }
+
+    fn transmit(pattern: &PilotPattern, symbols: usize, noise_var: f32) -> (Symbols, Symbols) {
+        let mut noise = Noise(0x2468_ace1);
+        let mut sent = Vec::new();
+        let mut received = Vec::new();
+        for t in 0..symbols {
+            let data: Vec<_> = (0..pattern.data_carriers(t))
+                .map(|_| qpsk(&mut noise))
+                .collect();
+            let sym = pattern.assemble(t, &data).expect("assemble");
+            received.push(
+                sym.iter()
+                    .enumerate()
+                    .map(|(c, &x)| x * channel(t, c) + noise.complex(noise_var))
+                    .collect(),
+            );
+            sent.push(data);
+        }
+        (sent, received)
+    }
+
+    fn mean_error(pattern: &PilotPattern, equalizer: Equalizer, noise_var: f32) -> f32 {
+        let (sent, received) = transmit(pattern, 12, noise_var);
+        let est = ChannelEstimate::estimate(pattern, &received).expect("estimate");
+        let mut err = 0.0;
+        let mut count = 0;
+        for (t, (data, rx)) in sent.iter().zip(&received).enumerate() {
+            let eq = equalizer
+                .equalize(rx, &est.gains[t], est.noise_var)
+                .expect("equalize");
+            for (a, b) in pattern.extract(t, &eq.symbols).iter().zip(data) {
+                err += (a - b).norm_sqr();
+                count += 1;
+            }
+        }
+        err / count as f32
+    }
+
+    #[test]
+    fn pilots_leave_constant_data_carriers() {
+        let pattern = PilotPattern::default();
+        assert_eq!(pattern.period(), 4);
+        for t in 0..8 {
+            assert_eq!(pattern.data_carriers(t), 91);
+        }
+    }
+
+    #[test]
+    fn estimates_multipath_channel() {
+        let pattern = PilotPattern::default();
+        let (_, received) = transmit(&pattern, 12, 0.0);
+        let est = ChannelEstimate::estimate(&pattern, &received).expect("estimate");
+        for (t, row) in est.gains.iter().enumerate().skip(3).take(6) {
+            for (c, h) in row.iter().enumerate() {
+                assert!((h - channel(t, c)).norm() < 0.05, "t={} c={} h={}", t, c, h);
+            }
+        }
+    }
+
+    #[test]
+    fn estimates_noise_variance() {
+        let pattern = PilotPattern::default();
+        let (_, received) = transmit(&pattern, 40, 0.01);
+        let est = ChannelEstimate::estimate(&pattern, &received).expect("estimate");
+        assert!(
+            est.noise_var > 0.007 && est.noise_var < 0.014,
+            "{}",
+            est.noise_var
+        );
+    }
+
+    #[test]
+    fn equalizers_recover_data() {
+        let pattern = PilotPattern::default();
+        let zf = mean_error(&pattern, Equalizer::ZeroForcing, 0.01);
+        let mmse = mean_error(&pattern, Equalizer::Mmse, 0.01);
+        assert!(zf < 0.05, "zf {}", zf);
+        assert!(mmse < 0.05, "mmse {}", mmse);
+        assert!(mmse <= zf, "mmse {} zf {}", mmse, zf);
+    }
+}
@@ -0,0 +1,234 @@
+//! OFDM numerology and named link profiles.
+
+use crate::passband::Passband;
+use crate::pilots::PilotPattern;
+
+/// OFDM numerology: FFT size, subcarrier placement, cyclic prefix, pilot layout and
+/// where the signal sits in the radio's audio passband.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OfdmConfig {
+    /// Audio sample rate in Hz.
+    pub sample_rate_hz: f32,
+    /// FFT size in samples.
+    pub nfft: usize,
+    /// FFT bin of the first subcarrier. Bins below it (including DC) are guard bins.
+    pub first_bin: usize,
+    /// Number of subcarriers, on consecutive bins from `first_bin`.
+    pub carriers: usize,
+    /// Cyclic prefix length in samples.
+    pub cp_len: usize,
+    /// Distance between pilots within a symbol, in subcarriers.
+    pub pilot_spacing: usize,
+    /// Pilot offset added per symbol, in subcarriers.
+    pub pilot_shift: usize,
+    /// Audio frequency the occupied band is centred on.
+    pub carrier_hz: f32,
+    /// Lower edge of the radio's usable audio passband.
+    pub audio_low_hz: f32,
+    /// Upper edge of the radio's usable audio passband.
+    pub audio_high_hz: f32,
+}
+
+impl Default for OfdmConfig {
+    fn default() -> Self {
+        Profile::Ssb2400.config()
+    }
+}
+
+impl OfdmConfig {
+    /// Subcarrier spacing in Hz.
+    pub fn spacing_hz(&self) -> f32 {
+        self.sample_rate_hz / self.nfft as f32
+    }
+
+    /// Samples per OFDM symbol including the cyclic prefix.
+    pub fn symbol_len(&self) -> usize {
+        self.nfft + self.cp_len
+    }
+
+    /// Symbol duration in seconds including the cyclic prefix.
+    pub fn symbol_secs(&self) -> f32 {
+        self.symbol_len() as f32 / self.sample_rate_hz
+    }
+
+    /// Occupied bandwidth in Hz.
+    pub fn bandwidth_hz(&self) -> f32 {
+        self.carriers as f32 * self.spacing_hz()
+    }
+
+    /// Pilot pattern for this numerology.
+    pub fn pilot_pattern(&self) -> PilotPattern {
+        PilotPattern::new(self.carriers, self.pilot_spacing, self.pilot_shift)
+    }
+
+    /// Audio passband placement for this numerology.
+    pub fn passband(&self) -> Passband {
+        Passband::new(
+            self.sample_rate_hz,
+            self.nfft,
+            self.first_bin,
+            self.carriers,
+        )
+        .with_carrier_hz(self.carrier_hz)
+        .with_audio_band(self.audio_low_hz, self.audio_high_hz)
+    }
+
+    /// Check that the numerology is consistent and fits the audio passband.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.first_bin == 0 {
+            return Err("first_bin must leave DC unused".into());
+        }
+        if self.cp_len >= self.nfft {
+            return Err(format!(
+                "cyclic prefix of {} samples is not shorter than the {}-point FFT",
+                self.cp_len, self.nfft
+            ));
+        }
+        if self.pilot_spacing == 0 || self.pilot_spacing > self.carriers {
+            return Err(format!(
+                "pilot spacing {} does not fit {} subcarriers",
+                self.pilot_spacing, self.carriers
+            ));
+        }
+        self.passband().check()
+    }
+}
+
+/// Named link profiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Profile {
+    /// Narrow HF data channel: 40 subcarriers in about 470 Hz, long symbols and a
+    /// 10.7 ms cyclic prefix for skywave multipath.
+    Hf500,
+    /// SSB voice bandwidth: 104 subcarriers in about 2.4 kHz.
+    Ssb2400,
+    /// FM voice channel: 52 wider-spaced subcarriers in about 2.4 kHz with short
+    /// symbols, tolerating the faster fading of mobile VHF/UHF.
+    Fm,
+}
+
+impl Profile {
+    /// All profiles.
+    pub const ALL: [Profile; 3] = [Profile::Hf500, Profile::Ssb2400, Profile::Fm];
+
+    /// Short lowercase name.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Hf500 => "hf500",
+            Self::Ssb2400 => "ssb2400",
+            Self::Fm => "fm",
+        }
+    }
+
+    /// Look up a profile by [`Profile::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|p| p.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Numerology for this profile at 48 kHz.
+    pub fn config(self) -> OfdmConfig {
+        match self {
+            Self::Hf500 => OfdmConfig {
+                sample_rate_hz: 48_000.0,
+                nfft: 4096,
+                first_bin: 1,
+                carriers: 40,
+                cp_len: 512,
+                pilot_spacing: 4,
+                pilot_shift: 1,
+                carrier_hz: 1500.0,
+                audio_low_hz: 1250.0,
+                audio_high_hz: 1750.0,
+            },
+            Self::Ssb2400 => OfdmConfig {
+                sample_rate_hz: 48_000.0,
+                nfft: 2048,
+                first_bin: 1,
+                carriers: 104,
+                cp_len: 256,
+                pilot_spacing: 8,
+                pilot_shift: 2,
+                carrier_hz: 1650.0,
+                audio_low_hz: 300.0,
+                audio_high_hz: 3000.0,
+            },
+            Self::Fm => OfdmConfig {
+                sample_rate_hz: 48_000.0,
+                nfft: 1024,
+                first_bin: 1,
+                carriers: 52,
+                cp_len: 128,
+                pilot_spacing: 4,
+                pilot_shift: 1,
+                carrier_hz: 1650.0,
+                audio_low_hz: 300.0,
+                audio_high_hz: 3000.0,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Frame, Header, Mode, OfdmDemodulator, OfdmModulator, PreambleDetector};
+    use rustfft::num_complex::Complex;
+
+    #[test]
+    fn profiles_are_valid() {
+        for profile in Profile::ALL {
+            let config = profile.config();
+            assert!(config.validate().is_ok(), "{:?}", profile);
+            assert_eq!(Profile::from_name(profile.name()), Some(profile));
+        }
+        let hf = Profile::Hf500.config();
+        assert!(hf.bandwidth_hz() <= 500.0);
+    }
+
+    #[test]
+    fn frames_round_trip_in_every_profile() {
+        for profile in Profile::ALL {
+            let config = profile.config();
+            let pattern = config.pilot_pattern();
+            let sent = Frame {
+                header: Header {
+                    mode: Mode::ALL[1],
+                    destination: "CQ".into(),
+                    source: "N0CALL".into(),
+                    seq: 1,
+                    flags: 0,
+                },
+                payload: b"profile check".to_vec(),
+            };
+            let symbols = sent.encode(&pattern).expect("encode");
+            let mut stream = vec![Complex::new(0.0, 0.0); 1000];
+            stream.extend(
+                OfdmModulator::from_config(&config)
+                    .modulate_frame(&symbols)
+                    .expect("modulate"),
+            );
+            stream.extend(vec![Complex::new(0.0, 0.0); 2 * config.symbol_len()]);
+
+            let sync = PreambleDetector::from_config(&config).push(&stream);
+            assert_eq!(sync.len(), 1, "{:?}", profile);
+            let demod = OfdmDemodulator::from_config(&config);
+            let first = sync[0].start as usize + 2 * config.symbol_len();
+            let received: Vec<_> = (0..symbols.len())
+                .map(|i| {
+                    let start = first + i * config.symbol_len();
+                    demod
+                        .demodulate(&stream[start..start + config.symbol_len()])
+                        .expect("demodulate")
+                })
+                .collect();
+            assert_eq!(
+                Frame::decode(&received, &pattern).expect("decode"),
+                sent,
+                "{:?}",
+                profile
+            );
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Gray-coded constellation mapping and soft demapping.
+
+use rustfft::num_complex::Complex;
+
+/// Subcarrier modulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Modulation {
+    Bpsk,
+    Qpsk,
+    Psk8,
+    Qam16,
+    Qam64,
+}
+
+impl Modulation {
+    /// All modulations, most robust first.
+    pub const ALL: [Modulation; 5] = [
+        Modulation::Bpsk,
+        Modulation::Qpsk,
+        Modulation::Psk8,
+        Modulation::Qam16,
+        Modulation::Qam64,
+    ];
+
+    /// Number of bits carried by one subcarrier symbol.
+    pub fn bits_per_symbol(self) -> usize {
+        match self {
+            Self::Bpsk => 1,
+            Self::Qpsk => 2,
+            Self::Psk8 => 3,
+            Self::Qam16 => 4,
+            Self::Qam64 => 6,
+        }
+    }
+
+    /// Constellation points indexed by their bit label (first bit is the MSB).
+    ///
+    /// Points are normalised to unit average power.
+    pub fn points(self) -> Vec<Complex<f32>> {
+        let bits = self.bits_per_symbol();
+        match self {
+            Self::Bpsk => vec![Complex::new(1.0, 0.0), Complex::new(-1.0, 0.0)],
+            Self::Psk8 => (0..8u32)
+                .map(|label| {
+                    let index = gray_to_binary(label) as f32;
+                    let phase = std::f32::consts::PI / 8.0 * (2.0 * index + 1.0);
+                    Complex::from_polar(1.0, phase)
+                })
+                .collect(),
+            Self::Qpsk | Self::Qam16 | Self::Qam64 => {
+                let axis_bits = bits / 2;
+                let levels = 1u32 << axis_bits;
+                let mask = levels - 1;
+                // Mean power of a square QAM with levels at odd integers.
+                let scale = 1.0 / (2.0 * ((levels * levels) as f32 - 1.0) / 3.0).sqrt();
+                let level =
+                    |label: u32| (2.0 * gray_to_binary(label) as f32 - (levels - 1) as f32) * scale;
+                (0..1u32 << bits)
+                    .map(|label| Complex::new(level(label >> axis_bits), level(label & mask)))
+                    .collect()
+            }
+        }
+    }
+
+    /// Map bits (one per byte, 0 or 1) to symbols.
+    ///
+    /// A trailing partial symbol is padded with zero bits.
+    pub fn map(self, bits: &[u8]) -> Vec<Complex<f32>> {
+        let points = self.points();
+        bits.chunks(self.bits_per_symbol())
+            .map(|chunk| {
+                let label = (0..self.bits_per_symbol()).fold(0usize, |acc, i| {
+                    (acc << 1) | (chunk.get(i).copied().unwrap_or(0) & 1) as usize
+                });
+                points[label]
+            })
+            .collect()
+    }
+
+    /// Map bytes to symbols, MSB first.
+    pub fn map_bytes(self, bytes: &[u8]) -> Vec<Complex<f32>> {
+        self.map(&bytes_to_bits(bytes))
+    }
+
+    /// Max-log log-likelihood ratios for every bit of every symbol.
+    ///
+    /// `noise_var` holds the complex noise variance for each symbol (for example from
+    /// the equaliser). Positive values favour a 0 bit.
+    pub fn demap(self, symbols: &[Complex<f32>], noise_var: &[f32]) -> Result<Vec<f32>, String> {
+        if symbols.len() != noise_var.len() {
+            return Err(format!(
+                "expected {} noise estimates, got {}",
+                symbols.len(),
+                noise_var.len()
+            ));
+        }
+        let bits = self.bits_per_symbol();
+        let points = self.points();
+        let mut distances = vec![0.0f32; points.len()];
+        let mut llrs = Vec::with_capacity(symbols.len() * bits);
+        for (&y, &var) in symbols.iter().zip(noise_var) {
+            for (d, p) in distances.iter_mut().zip(&points) {
+                *d = (y - p).norm_sqr();
+            }
+            let var = var.max(1e-9);
+            for bit in (0..bits).rev() {
+                let mut zero = f32::INFINITY;
+                let mut one = f32::INFINITY;
+                for (label, &d) in distances.iter().enumerate() {
+                    if label >> bit & 1 == 0 {
+                        zero = zero.min(d);
+                    } else {
+                        one = one.min(d);
+                    }
+                }
+                llrs.push((one - zero) / var);
+            }
+        }
+        Ok(llrs)
+    }
+}
+
+/// Expand bytes into bits, MSB first.
+pub fn bytes_to_bits(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .flat_map(|&b| (0..8).rev().map(move |i| b >> i & 1))
+        .collect()
+}
+
+/// Pack bits (MSB first) into bytes, dropping any trailing partial byte.
+pub fn bits_to_bytes(bits: &[u8]) -> Vec<u8> {
+    bits.chunks_exact(8)
+        .map(|chunk| chunk.iter().fold(0u8, |acc, &b| (acc << 1) | (b & 1)))
+        .collect()
+}
+
+/// Hard decisions from LLRs.
+pub fn hard_decision(llrs: &[f32]) -> Vec<u8> {
+    llrs.iter().map(|&l| u8::from(l < 0.0)).collect()
+}
+
+fn gray_to_binary(mut gray: u32) -> u32 {
+    let mut shift = gray >> 1;
+    while shift != 0 {
+        gray ^= shift;
+        shift >>= 1;
+    }
+    gray
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constellations_have_unit_power() {
+        for m in Modulation::ALL {
+            let points = m.points();
+            assert_eq!(points.len(), 1 << m.bits_per_symbol());
+            let power: f32 = points.iter().map(|p| p.norm_sqr()).sum::<f32>() / points.len() as f32;
+            assert!((power - 1.0).abs() < 1e-5, "{:?} {}", m, power);
+        }
+    }
+
+    #[test]
+    fn nearest_neighbours_differ_by_one_bit() {
+        for m in Modulation::ALL {
+            let points = m.points();
+            for (a, pa) in points.iter().enumerate() {
+                let nearest = points
+                    .iter()
+                    .enumerate()
+                    .filter(|&(b, _)| b != a)
+                    .map(|(_, pb)| (pa - pb).norm())
+                    .fold(f32::INFINITY, f32::min);
+                for (b, pb) in points.iter().enumerate() {
+                    if b != a && (pa - pb).norm() < nearest + 1e-4 {
+                        assert_eq!((a ^ b).count_ones(), 1, "{:?} {} {}", m, a, b);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn demap_recovers_bytes() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for m in Modulation::ALL {
+            let symbols = m.map_bytes(&bytes);
+            let llrs = m.demap(&symbols, &vec![0.1; symbols.len()]).expect("demap");
+            let bits = hard_decision(&llrs);
+            assert_eq!(&bits_to_bytes(&bits)[..bytes.len()], &bytes[..], "{:?}", m);
+        }
+    }
+
+    #[test]
+    fn llr_magnitude_scales_with_noise() {
+        let symbols = Modulation::Qpsk.map(&[0, 1]);
+        let quiet = Modulation::Qpsk.demap(&symbols, &[0.1]).expect("demap");
+        let noisy = Modulation::Qpsk.demap(&symbols, &[1.0]).expect("demap");
+        assert!(quiet[0] > 0.0 && quiet[1] < 0.0);
+        assert!((quiet[0] / noisy[0] - 10.0).abs() < 1e-3);
+    }
+}
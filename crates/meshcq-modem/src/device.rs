@@ -1,232 +1,320 @@
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use regex::Regex;
-use ringbuf::HeapRb;
-use std::collections::VecDeque;
-use std::sync::{mpsc::Receiver, mpsc::Sender};
-
-const ENERGY_BLOCK: usize = 1024;
-const CONCAT_BLOCKS: usize = 3;
+//! Audio input and output.
+//!
+//! Everything above this module sees 48 kHz mono audio: receive audio arrives as
+//! [`TimedChunk`] messages on a channel and transmit audio is sent as `Vec<f32>` bursts.
+//! An [`AudioSource`] or [`AudioSink`] connects those channels to a sound card, a WAV or
+//! raw PCM file, stdin/stdout or an in-memory [`loopback`], so the modem and the
+//! repeater can run headless. File sources produce the same messages and timestamps as
+//! a live input, so recordings can be replayed through the receive path.
+
+pub mod loopback;
+pub mod raw;
+pub mod sound_card;
+pub mod wav;
+
+pub use loopback::{loopback, LoopbackSink, LoopbackSource};
+pub use raw::{RawFormat, RawSink, RawSource};
+pub use sound_card::{SoundCardInput, SoundCardOutput};
+pub use wav::{WavSink, WavSource};
+
+use crate::config::OfdmConfig;
+use crate::passband::Downconverter;
+use crate::sync::PreambleDetector;
+use rustfft::num_complex::Complex;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+pub(crate) const SAMPLE_RATE_HZ: u32 = 48_000;
+pub(crate) const ENERGY_BLOCK: usize = 1024;
 const ENERGY_THRESHOLD: f32 = 1.0e-4;
-const OUTPUT_RING_CAP: usize = 48_000 * 4;
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
 
+/// A captured message and the input sample count just after it was received.
 pub struct TimedChunk {
     pub samples: Vec<f32>,
     pub end_sample: u64,
 }
 
-pub fn start_default_input(
-    left_tx: Sender<TimedChunk>,
-    device_regex: Option<&str>,
-) -> Result<cpal::Stream, Box<dyn std::error::Error>> {
-    let host = cpal::default_host();
-    let device = select_input_device(&host, device_regex)?;
-    let default_config = device.default_input_config()?;
-    let mut sample_format = default_config.sample_format();
-    let mut config = default_config;
-    if sample_format == cpal::SampleFormat::F32 {
-        if let Ok(mut supported) = device.supported_input_configs() {
-            if let Some(best) = supported.find(|cfg| {
-                cfg.sample_format() == cpal::SampleFormat::F32
-                    && cfg.min_sample_rate().0 <= 48_000
-                    && cfg.max_sample_rate().0 >= 48_000
-            }) {
-                config = best.with_sample_rate(cpal::SampleRate(48_000));
-                sample_format = config.sample_format();
+/// Somewhere receive audio comes from.
+pub trait AudioSource {
+    /// Start capturing, sending each message on `tx` until the handle is dropped or
+    /// the source runs out.
+    fn start(self: Box<Self>, tx: Sender<TimedChunk>) -> Result<AudioHandle, Box<dyn Error>>;
+}
+
+/// Somewhere transmit audio goes.
+pub trait AudioSink {
+    /// Start playing every burst received on `rx` until the handle is dropped.
+    fn start(self: Box<Self>, rx: Receiver<Vec<f32>>) -> Result<AudioHandle, Box<dyn Error>>;
+}
+
+/// Keeps a running source or sink alive.
+///
+/// Dropping the handle stops it. Sinks first play out whatever is already queued and
+/// finish their output (so a WAV file gets a valid header); sources reading a blocking
+/// stream are left to exit on their own.
+pub struct AudioHandle {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    join_on_drop: bool,
+    _stream: Option<cpal::Stream>,
+}
+
+impl AudioHandle {
+    pub(crate) fn from_stream(stream: cpal::Stream) -> Self {
+        Self {
+            stop: Arc::new(AtomicBool::new(false)),
+            thread: None,
+            join_on_drop: false,
+            _stream: Some(stream),
+        }
+    }
+
+    /// Also keep `stream` running until the handle is dropped.
+    pub(crate) fn with_stream(mut self, stream: cpal::Stream) -> Self {
+        self._stream = Some(stream);
+        self
+    }
+
+    /// Run `body` on its own thread. It should return soon after the flag it is
+    /// given is set.
+    pub(crate) fn spawn(
+        join_on_drop: bool,
+        body: impl FnOnce(Arc<AtomicBool>) + Send + 'static,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        Self {
+            stop,
+            thread: Some(std::thread::spawn(move || body(flag))),
+            join_on_drop,
+            _stream: None,
+        }
+    }
+
+    /// Whether a finite source has been read to the end (or a sink's input closed).
+    /// Sound cards never finish.
+    pub fn is_finished(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| thread.is_finished())
+    }
+
+    /// Block until the source or sink finishes by itself.
+    pub fn wait(mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for AudioHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            if self.join_on_drop {
+                let _ = thread.join();
             }
         }
     }
-    let config: cpal::StreamConfig = config.into();
-    assert!(
-        config.sample_rate.0 == 48_000,
-        "expected 48 kHz sample rate, got {} Hz",
-        config.sample_rate.0
-    );
-    let channels = config.channels as usize;
-    let mut block: Vec<f32> = Vec::with_capacity(ENERGY_BLOCK);
-    let mut block_queue: VecDeque<Vec<f32>> = VecDeque::with_capacity(CONCAT_BLOCKS);
-    let mut capture: Vec<f32> = Vec::with_capacity(ENERGY_BLOCK * CONCAT_BLOCKS);
-    let mut message: Vec<f32> = Vec::new();
-
-    let err_fn = |err| eprintln!("audio stream error: {}", err);
-    let mut sample_cursor: u64 = 0;
-
-    let stream = match sample_format {
-        cpal::SampleFormat::F32 => device.build_input_stream(
-            &config,
-            move |data: &[f32], _info| {
-                let frames = data.len() / channels;
-                sample_cursor = sample_cursor.saturating_add(frames as u64);
-                let buffer_end = sample_cursor;
-
-                let mut process_block = |block: &mut Vec<f32>| {
-                    let energy = block.iter().map(|x| x * x).sum::<f32>()
-                        / ENERGY_BLOCK as f32;
-
-                    block_queue.push_back(block.clone());
-                    if block_queue.len() > CONCAT_BLOCKS {
-                        block_queue.pop_front();
-                    }
-
-                    if energy > ENERGY_THRESHOLD && block_queue.len() == CONCAT_BLOCKS {
-                        capture.clear();
-                        for queued in block_queue.iter() {
-                            capture.extend_from_slice(queued);
-                        }
-                        // TODO: process capture (len = ENERGY_BLOCK * CONCAT_BLOCKS).
-                    }
-
-                    if energy > ENERGY_THRESHOLD {
-                        message.extend_from_slice(block);
-                    } else if !message.is_empty() {
-                        let to_send = std::mem::take(&mut message);
-                        eprintln!("audio input: message captured ({} samples)", to_send.len());
-                        let _ = left_tx.send(TimedChunk {
-                            samples: to_send,
-                            end_sample: buffer_end,
-                        });
-                    }
-
-                    block.clear();
-                };
-
-                if channels == 1 {
-                    let mut offset = 0;
-                    while offset < data.len() {
-                        let need = ENERGY_BLOCK - block.len();
-                        let take = need.min(data.len() - offset);
-                        block.extend_from_slice(&data[offset..offset + take]);
-                        offset += take;
-                        if block.len() >= ENERGY_BLOCK {
-                            process_block(&mut block);
-                        }
-                    }
-                } else {
-                    for frame in data.chunks(channels) {
-                        block.push(frame[0]);
-                        if block.len() >= ENERGY_BLOCK {
-                            process_block(&mut block);
-                        }
-                    }
-                }
-            },
-            err_fn,
-            None,
-        )?,
-        _ => return Err("unsupported sample format (expected f32)".into()),
+}
+
+/// Open an input by name: `sound` for the sound card picked by `device_regex`,
+/// `wav:PATH` for a WAV file, `f32:PATH` or `s16:PATH` for a raw PCM file, with `-` as
+/// the path (or the whole name) for stdin. Files are read as fast as possible unless
+/// `real_time` is set.
+pub fn open_source(
+    spec: &str,
+    device_regex: Option<&str>,
+    real_time: bool,
+) -> Result<Box<dyn AudioSource>, Box<dyn Error>> {
+    let raw = |path: &str, format| -> Result<Box<dyn AudioSource>, Box<dyn Error>> {
+        let source = match path {
+            "-" => RawSource::stdin(format),
+            path => RawSource::open(path, format)?,
+        };
+        Ok(Box::new(source.with_real_time(real_time)))
     };
+    match spec.split_once(':') {
+        _ if spec == "sound" => Ok(Box::new(SoundCardInput::new(device_regex))),
+        _ if spec == "-" => raw("-", RawFormat::F32),
+        Some(("wav", path)) => Ok(Box::new(WavSource::open(path)?.with_real_time(real_time))),
+        Some(("f32", path)) => raw(path, RawFormat::F32),
+        Some(("s16", path)) => raw(path, RawFormat::S16),
+        _ => Err(format!("unknown audio input '{}'", spec).into()),
+    }
+}
 
-    stream.play()?;
-    Ok(stream)
+/// Open an output by name, as for [`open_source`]. Samples are scaled by `level`.
+pub fn open_sink(
+    spec: &str,
+    level: f32,
+    device_regex: Option<&str>,
+) -> Result<Box<dyn AudioSink>, Box<dyn Error>> {
+    let raw = |path: &str, format| -> Result<Box<dyn AudioSink>, Box<dyn Error>> {
+        match path {
+            "-" => Ok(Box::new(RawSink::stdout(format, level))),
+            path => Ok(Box::new(RawSink::create(path, format, level)?)),
+        }
+    };
+    match spec.split_once(':') {
+        _ if spec == "sound" => Ok(Box::new(SoundCardOutput::new(level, device_regex))),
+        _ if spec == "-" => raw("-", RawFormat::F32),
+        Some(("wav", path)) => Ok(Box::new(WavSink::create(path, level)?)),
+        Some(("f32", path)) => raw(path, RawFormat::F32),
+        Some(("s16", path)) => raw(path, RawFormat::S16),
+        _ => Err(format!("unknown audio output '{}'", spec).into()),
+    }
+}
+
+pub fn start_default_input(
+    left_tx: Sender<TimedChunk>,
+    device_regex: Option<&str>,
+) -> Result<AudioHandle, Box<dyn Error>> {
+    Box::new(SoundCardInput::new(device_regex)).start(left_tx)
 }
 
 pub fn start_default_output(
     left_rx: Receiver<Vec<f32>>,
     output_level: f32,
     device_regex: Option<&str>,
-) -> Result<cpal::Stream, Box<dyn std::error::Error>> {
-    let host = cpal::default_host();
-    let device = select_output_device(&host, device_regex)?;
-    let config = device.default_output_config()?;
-    let sample_format = config.sample_format();
-    let config: cpal::StreamConfig = config.into();
-    let channels = config.channels as usize;
-    if channels < 2 {
-        return Err("output device must support at least 2 channels".into());
-    }
-
-    let ring = HeapRb::<f32>::new(OUTPUT_RING_CAP);
-    let (mut producer, mut consumer) = ring.split();
-    let mut phase: f32 = 0.0;
-    let phase_inc: f32 = std::f32::consts::TAU * 1000.0 / 48_000.0;
-
-    let err_fn = |err| eprintln!("audio stream error: {}", err);
-
-    let mut pending: VecDeque<f32> = VecDeque::new();
-
-    let stream = match sample_format {
-        cpal::SampleFormat::F32 => device.build_output_stream(
-            &config,
-            move |data: &mut [f32], _| {
-                while let Some(sample) = pending.front().copied() {
-                    if producer.push(sample).is_ok() {
-                        pending.pop_front();
-                    } else {
-                        break;
-                    }
-                }
-
-                while let Ok(chunk) = left_rx.try_recv() {
-                    eprintln!("audio output: received {} samples", chunk.len());
-                    for sample in chunk {
-                        pending.push_back(sample);
-                    }
-                }
-
-                for frame in data.chunks_mut(channels) {
-                    let left_opt = consumer.pop();
-                    let left = left_opt.unwrap_or(0.0) * output_level;
-                    let right = if left_opt.is_some() {
-                        let tone = phase.sin();
-                        phase += phase_inc;
-                        if phase >= std::f32::consts::TAU {
-                            phase -= std::f32::consts::TAU;
-                        }
-                        tone
-                    } else {
-                        0.0
-                    };
-
-                    frame[0] = left;
-                    frame[1] = right;
-                    for chan in frame.iter_mut().skip(2) {
-                        *chan = 0.0;
-                    }
-                }
-            },
-            err_fn,
-            None,
-        )?,
-        _ => return Err("unsupported sample format (expected f32)".into()),
-    };
+) -> Result<AudioHandle, Box<dyn Error>> {
+    Box::new(SoundCardOutput::new(output_level, device_regex)).start(left_rx)
+}
+
+/// Wait for the next transmit burst, giving up once `stop` is set and nothing is
+/// queued or the sender has gone.
+pub(crate) fn next_burst(rx: &Receiver<Vec<f32>>, stop: &AtomicBool) -> Option<Vec<f32>> {
+    loop {
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(burst) => return Some(burst),
+            Err(RecvTimeoutError::Timeout) if !stop.load(Ordering::Relaxed) => {}
+            Err(_) => return None,
+        }
+    }
+}
 
-    stream.play()?;
-    Ok(stream)
+/// Holds a file source back to real time.
+pub(crate) struct Pacer {
+    start: Option<Instant>,
+    samples: u64,
 }
 
-fn select_input_device(
-    host: &cpal::Host,
-    device_regex: Option<&str>,
-) -> Result<cpal::Device, Box<dyn std::error::Error>> {
-    if let Some(pattern) = device_regex {
-        let re = Regex::new(pattern)?;
-        for dev in host.input_devices()? {
-            let name = dev.name().unwrap_or_else(|_| "<unknown>".to_string());
-            if re.is_match(&name) {
-                return Ok(dev);
-            }
+impl Pacer {
+    pub(crate) fn new(real_time: bool) -> Self {
+        Self {
+            start: real_time.then(Instant::now),
+            samples: 0,
         }
-        return Err("no input device matched regex".into());
     }
 
-    host.default_input_device()
-        .ok_or_else(|| "no default input device available".into())
+    /// Wait until `samples` more would have arrived from a live input.
+    pub(crate) fn wait(&mut self, samples: usize) {
+        self.samples += samples as u64;
+        if let Some(start) = self.start {
+            let due = start + Duration::from_secs_f64(self.samples as f64 / SAMPLE_RATE_HZ as f64);
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+    }
 }
 
-fn select_output_device(
-    host: &cpal::Host,
-    device_regex: Option<&str>,
-) -> Result<cpal::Device, Box<dyn std::error::Error>> {
-    if let Some(pattern) = device_regex {
-        let re = Regex::new(pattern)?;
-        for dev in host.output_devices()? {
-            let name = dev.name().unwrap_or_else(|_| "<unknown>".to_string());
-            if re.is_match(&name) {
-                return Ok(dev);
+/// Splits a 48 kHz input stream into messages on energy, logging any preambles seen.
+pub(crate) struct MessageCapture {
+    tx: Sender<TimedChunk>,
+    config: OfdmConfig,
+    block: Vec<f32>,
+    detector: PreambleDetector,
+    downconverter: Downconverter,
+    filter_delay: u64,
+    baseband: Vec<Complex<f32>>,
+    message: Vec<f32>,
+    cursor: u64,
+}
+
+impl MessageCapture {
+    pub(crate) fn new(tx: Sender<TimedChunk>) -> Result<Self, Box<dyn Error>> {
+        Self::from_config(tx, &OfdmConfig::default())
+    }
+
+    /// Log preambles of the `config` numerology.
+    pub(crate) fn from_config(
+        tx: Sender<TimedChunk>,
+        config: &OfdmConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        let downconverter = config.passband().downconverter()?;
+        Ok(Self {
+            tx,
+            config: *config,
+            block: Vec::with_capacity(ENERGY_BLOCK),
+            detector: PreambleDetector::from_config(config),
+            filter_delay: downconverter.delay() as u64,
+            downconverter,
+            baseband: Vec::with_capacity(ENERGY_BLOCK),
+            message: Vec::new(),
+            cursor: 0,
+        })
+    }
+
+    /// Feed mono samples.
+    pub(crate) fn push(&mut self, samples: &[f32]) {
+        self.push_frames(samples, 1);
+    }
+
+    /// Feed interleaved frames of `channels` samples, keeping the first channel.
+    pub(crate) fn push_frames(&mut self, data: &[f32], channels: usize) {
+        self.cursor = self.cursor.saturating_add((data.len() / channels) as u64);
+        let buffer_end = self.cursor;
+        for frame in data.chunks_exact(channels) {
+            self.block.push(frame[0]);
+            if self.block.len() >= ENERGY_BLOCK {
+                self.process_block(buffer_end);
             }
         }
-        return Err("no output device matched regex".into());
     }
 
-    host.default_output_device()
-        .ok_or_else(|| "no default output device available".into())
+    /// Send whatever message is in progress, for when the input ends.
+    pub(crate) fn finish(&mut self) {
+        if !self.block.is_empty() {
+            self.process_block(self.cursor);
+        }
+        self.send(self.cursor);
+    }
+
+    fn process_block(&mut self, buffer_end: u64) {
+        let energy = self.block.iter().map(|x| x * x).sum::<f32>() / ENERGY_BLOCK as f32;
+
+        self.baseband.clear();
+        self.downconverter.process(&self.block, &mut self.baseband);
+        for sync in self.detector.push(&self.baseband) {
+            eprintln!(
+                "audio input: preamble at sample {} (metric {:.2}, offset {:+.1} Hz)",
+                sync.start.saturating_sub(self.filter_delay),
+                sync.metric,
+                sync.cfo_hz(self.config.sample_rate_hz, self.config.nfft)
+            );
+        }
+
+        if energy > ENERGY_THRESHOLD {
+            self.message.extend_from_slice(&self.block);
+        } else {
+            self.send(buffer_end);
+        }
+        self.block.clear();
+    }
+
+    fn send(&mut self, end_sample: u64) {
+        if self.message.is_empty() {
+            return;
+        }
+        let samples = std::mem::take(&mut self.message);
+        eprintln!("audio input: message captured ({} samples)", samples.len());
+        let _ = self.tx.send(TimedChunk {
+            samples,
+            end_sample,
+        });
+    }
 }
@@ -0,0 +1,215 @@
+//! Raw 48 kHz mono PCM, as little-endian `f32` or `i16` samples, in files or on
+//! stdin and stdout.
+
+use super::{next_burst, AudioHandle, AudioSink, AudioSource, MessageCapture, Pacer, TimedChunk};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{Receiver, Sender};
+
+const READ_BYTES: usize = 4096;
+
+/// Sample encoding of a raw PCM stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawFormat {
+    /// 32-bit float, full scale ±1.
+    F32,
+    /// 16-bit signed integer.
+    S16,
+}
+
+impl RawFormat {
+    fn bytes(self) -> usize {
+        match self {
+            RawFormat::F32 => 4,
+            RawFormat::S16 => 2,
+        }
+    }
+
+    fn decode(self, b: &[u8]) -> f32 {
+        match self {
+            RawFormat::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            RawFormat::S16 => i16::from_le_bytes([b[0], b[1]]) as f32 / 32_768.0,
+        }
+    }
+
+    fn encode(self, x: f32, out: &mut Vec<u8>) {
+        match self {
+            RawFormat::F32 => out.extend_from_slice(&x.to_le_bytes()),
+            RawFormat::S16 => {
+                let x = (x * 32_768.0).round().clamp(-32_768.0, 32_767.0) as i16;
+                out.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Read samples from a file or stdin until it ends.
+pub struct RawSource {
+    reader: Box<dyn Read + Send>,
+    format: RawFormat,
+    real_time: bool,
+}
+
+impl RawSource {
+    pub fn stdin(format: RawFormat) -> Self {
+        Self {
+            reader: Box::new(std::io::stdin()),
+            format,
+            real_time: false,
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>, format: RawFormat) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(Self {
+            reader: Box::new(file),
+            format,
+            real_time: false,
+        })
+    }
+
+    /// Deliver samples no faster than real time, as a sound card would, instead of as
+    /// fast as they can be read.
+    pub fn with_real_time(mut self, real_time: bool) -> Self {
+        self.real_time = real_time;
+        self
+    }
+}
+
+impl AudioSource for RawSource {
+    fn start(self: Box<Self>, tx: Sender<TimedChunk>) -> Result<AudioHandle, Box<dyn Error>> {
+        let Self {
+            mut reader,
+            format,
+            real_time,
+        } = *self;
+        let mut capture = MessageCapture::new(tx)?;
+        Ok(AudioHandle::spawn(false, move |stop| {
+            let width = format.bytes();
+            let mut pacer = Pacer::new(real_time);
+            let mut bytes = vec![0u8; READ_BYTES];
+            let mut filled = 0;
+            let mut samples = Vec::with_capacity(READ_BYTES / width);
+            while !stop.load(Ordering::Relaxed) {
+                let read = match reader.read(&mut bytes[filled..]) {
+                    Ok(0) => break,
+                    Ok(read) => read,
+                    Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(err) => {
+                        eprintln!("audio input: {}", err);
+                        break;
+                    }
+                };
+                filled += read;
+                let whole = filled - filled % width;
+                samples.clear();
+                samples.extend(bytes[..whole].chunks_exact(width).map(|b| format.decode(b)));
+                pacer.wait(samples.len());
+                capture.push(&samples);
+                bytes.copy_within(whole..filled, 0);
+                filled -= whole;
+            }
+            capture.finish();
+        }))
+    }
+}
+
+/// Write samples to a file or stdout, one burst after another.
+pub struct RawSink {
+    writer: Box<dyn Write + Send>,
+    format: RawFormat,
+    level: f32,
+}
+
+impl RawSink {
+    /// Scale samples by `level` on the way out.
+    pub fn stdout(format: RawFormat, level: f32) -> Self {
+        Self {
+            writer: Box::new(std::io::stdout()),
+            format,
+            level,
+        }
+    }
+
+    /// Create (or truncate) `path`, scaling samples by `level` on the way out.
+    pub fn create(
+        path: impl AsRef<Path>,
+        format: RawFormat,
+        level: f32,
+    ) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(Self {
+            writer: Box::new(BufWriter::new(file)),
+            format,
+            level,
+        })
+    }
+}
+
+impl AudioSink for RawSink {
+    fn start(self: Box<Self>, rx: Receiver<Vec<f32>>) -> Result<AudioHandle, Box<dyn Error>> {
+        let Self {
+            mut writer,
+            format,
+            level,
+        } = *self;
+        Ok(AudioHandle::spawn(true, move |stop| {
+            let mut bytes = Vec::new();
+            while let Some(burst) = next_burst(&rx, &stop) {
+                bytes.clear();
+                for x in burst {
+                    format.encode(x * level, &mut bytes);
+                }
+                if let Err(err) = writer.write_all(&bytes).and_then(|_| writer.flush()) {
+                    eprintln!("audio output: {}", err);
+                    break;
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn s16_file_replays_with_timestamps() {
+        let path = std::env::temp_dir().join(format!("meshcq-raw-{}.s16", std::process::id()));
+        let burst =
+            |len: usize| -> Vec<f32> { (0..len).map(|n| (n as f32 * 0.05).sin() * 0.5).collect() };
+        let mut audio = vec![0.0; 10_240];
+        audio.extend(burst(20_480));
+        audio.extend(vec![0.0; 30_720]);
+        audio.extend(burst(5_120));
+
+        let (out_tx, out_rx) = mpsc::channel();
+        let sink = Box::new(RawSink::create(&path, RawFormat::S16, 1.0).expect("create"));
+        let handle = sink.start(out_rx).expect("start sink");
+        out_tx.send(audio).unwrap();
+        drop(out_tx);
+        handle.wait();
+
+        let (in_tx, in_rx) = mpsc::channel();
+        let source = Box::new(RawSource::open(&path, RawFormat::S16).expect("open"));
+        source.start(in_tx).expect("start source").wait();
+        let chunks: Vec<TimedChunk> = in_rx.iter().collect();
+        std::fs::remove_file(&path).ok();
+
+        // The first message ends with the block of silence after it, the second with
+        // the end of the file.
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].samples.len(), 20_480);
+        assert!(chunks[0].end_sample >= 30_720 && chunks[0].end_sample <= 30_720 + 4096);
+        assert_eq!(chunks[1].samples.len(), 5_120);
+        assert_eq!(chunks[1].end_sample, 66_560);
+        let start = chunks[1].end_sample - chunks[1].samples.len() as u64;
+        assert_eq!(start, 61_440);
+    }
+}
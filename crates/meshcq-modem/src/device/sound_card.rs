@@ -0,0 +1,268 @@
+//! Sound card input and output through cpal.
+//!
+//! Devices that cannot run at 48 kHz are used at their own rate, with audio resampled
+//! on the way in and out.
+
+use super::{
+    next_burst, AudioHandle, AudioSink, AudioSource, MessageCapture, TimedChunk, SAMPLE_RATE_HZ,
+};
+use crate::resample::Resampler;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use regex::Regex;
+use ringbuf::HeapRb;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+const OUTPUT_RING_CAP: usize = SAMPLE_RATE_HZ as usize * 4;
+const KEY_TONE_HZ: f32 = 1000.0;
+
+/// Capture from the first channel of an input device.
+pub struct SoundCardInput {
+    device_regex: Option<String>,
+}
+
+impl SoundCardInput {
+    /// Use the first input device whose name matches `device_regex`, or the default.
+    pub fn new(device_regex: Option<&str>) -> Self {
+        Self {
+            device_regex: device_regex.map(str::to_string),
+        }
+    }
+}
+
+impl AudioSource for SoundCardInput {
+    fn start(self: Box<Self>, tx: Sender<TimedChunk>) -> Result<AudioHandle, Box<dyn Error>> {
+        let host = cpal::default_host();
+        let device = select_input_device(&host, self.device_regex.as_deref())?;
+        let config = choose_config(
+            device.supported_input_configs()?,
+            device.default_input_config()?,
+            1,
+        )?;
+        let sample_format = config.sample_format();
+        let config: cpal::StreamConfig = config.into();
+        let channels = config.channels as usize;
+        let mut capture = MessageCapture::new(tx)?;
+        let mut resampler = resampler_for(config.sample_rate.0, SAMPLE_RATE_HZ)?;
+        let mut mono: Vec<f32> = Vec::new();
+        let mut resampled: Vec<f32> = Vec::new();
+
+        let err_fn = |err| eprintln!("audio stream error: {}", err);
+
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => device.build_input_stream(
+                &config,
+                move |data: &[f32], _info| match &mut resampler {
+                    None => capture.push_frames(data, channels),
+                    Some(resampler) => {
+                        mono.clear();
+                        mono.extend(data.chunks_exact(channels).map(|frame| frame[0]));
+                        resampled.clear();
+                        resampler.process(&mono, &mut resampled);
+                        capture.push(&resampled);
+                    }
+                },
+                err_fn,
+                None,
+            )?,
+            _ => return Err("unsupported sample format (expected f32)".into()),
+        };
+
+        stream.play()?;
+        Ok(AudioHandle::from_stream(stream))
+    }
+}
+
+/// Play on the left channel of an output device, with a keying tone on the right
+/// channel while audio plays.
+pub struct SoundCardOutput {
+    level: f32,
+    device_regex: Option<String>,
+}
+
+impl SoundCardOutput {
+    /// Use the first output device whose name matches `device_regex`, or the default,
+    /// scaling samples by `level`.
+    pub fn new(level: f32, device_regex: Option<&str>) -> Self {
+        Self {
+            level,
+            device_regex: device_regex.map(str::to_string),
+        }
+    }
+}
+
+impl AudioSink for SoundCardOutput {
+    fn start(self: Box<Self>, rx: Receiver<Vec<f32>>) -> Result<AudioHandle, Box<dyn Error>> {
+        let host = cpal::default_host();
+        let device = select_output_device(&host, self.device_regex.as_deref())?;
+        let config = choose_config(
+            device.supported_output_configs()?,
+            device.default_output_config()?,
+            2,
+        )?;
+        let sample_format = config.sample_format();
+        let config: cpal::StreamConfig = config.into();
+        let channels = config.channels as usize;
+        if channels < 2 {
+            return Err("output device must support at least 2 channels".into());
+        }
+        let device_rate = config.sample_rate.0;
+
+        // Resample whole bursts off the audio thread, flushing each one out of the filter.
+        let mut handle = None;
+        let rx = match resampler_for(SAMPLE_RATE_HZ, device_rate)? {
+            None => rx,
+            Some(mut resampler) => {
+                let (resampled_tx, resampled_rx) = mpsc::channel();
+                handle = Some(AudioHandle::spawn(false, move |stop| {
+                    while let Some(mut burst) = next_burst(&rx, &stop) {
+                        burst.resize(burst.len() + resampler.latency() + 1, 0.0);
+                        let mut out = Vec::new();
+                        resampler.reset();
+                        resampler.process(&burst, &mut out);
+                        if resampled_tx.send(out).is_err() {
+                            break;
+                        }
+                    }
+                }));
+                resampled_rx
+            }
+        };
+
+        let output_level = self.level;
+        let ring = HeapRb::<f32>::new(OUTPUT_RING_CAP);
+        let (mut producer, mut consumer) = ring.split();
+        let mut phase: f32 = 0.0;
+        let phase_inc: f32 = std::f32::consts::TAU * KEY_TONE_HZ / device_rate as f32;
+
+        let err_fn = |err| eprintln!("audio stream error: {}", err);
+
+        let mut pending: VecDeque<f32> = VecDeque::new();
+
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => device.build_output_stream(
+                &config,
+                move |data: &mut [f32], _| {
+                    while let Some(sample) = pending.front().copied() {
+                        if producer.push(sample).is_ok() {
+                            pending.pop_front();
+                        } else {
+                            break;
+                        }
+                    }
+
+                    while let Ok(chunk) = rx.try_recv() {
+                        eprintln!("audio output: received {} samples", chunk.len());
+                        for sample in chunk {
+                            pending.push_back(sample);
+                        }
+                    }
+
+                    for frame in data.chunks_mut(channels) {
+                        let left_opt = consumer.pop();
+                        let left = left_opt.unwrap_or(0.0) * output_level;
+                        let right = if left_opt.is_some() {
+                            let tone = phase.sin();
+                            phase += phase_inc;
+                            if phase >= std::f32::consts::TAU {
+                                phase -= std::f32::consts::TAU;
+                            }
+                            tone
+                        } else {
+                            0.0
+                        };
+
+                        frame[0] = left;
+                        frame[1] = right;
+                        for chan in frame.iter_mut().skip(2) {
+                            *chan = 0.0;
+                        }
+                    }
+                },
+                err_fn,
+                None,
+            )?,
+            _ => return Err("unsupported sample format (expected f32)".into()),
+        };
+
+        stream.play()?;
+        Ok(match handle {
+            Some(handle) => handle.with_stream(stream),
+            None => AudioHandle::from_stream(stream),
+        })
+    }
+}
+
+/// Pick an f32 configuration, at 48 kHz if the device can run there and otherwise at
+/// its own rate, with at least `min_channels` if possible.
+fn choose_config(
+    supported: impl Iterator<Item = cpal::SupportedStreamConfigRange>,
+    default: cpal::SupportedStreamConfig,
+    min_channels: u16,
+) -> Result<cpal::SupportedStreamConfig, Box<dyn Error>> {
+    let usable: Vec<_> = supported
+        .filter(|cfg| cfg.sample_format() == cpal::SampleFormat::F32)
+        .filter(|cfg| cfg.channels() >= min_channels)
+        .collect();
+    if let Some(best) = usable.iter().find(|cfg| {
+        cfg.min_sample_rate().0 <= SAMPLE_RATE_HZ && cfg.max_sample_rate().0 >= SAMPLE_RATE_HZ
+    }) {
+        return Ok((*best).with_sample_rate(cpal::SampleRate(SAMPLE_RATE_HZ)));
+    }
+    if default.sample_format() == cpal::SampleFormat::F32 && default.channels() >= min_channels {
+        return Ok(default);
+    }
+    usable
+        .into_iter()
+        .max_by_key(|cfg| cfg.max_sample_rate().0)
+        .map(|cfg| cfg.with_max_sample_rate())
+        .ok_or_else(|| "unsupported sample format (expected f32)".into())
+}
+
+/// A resampler between two rates, or none if they match.
+fn resampler_for(from_hz: u32, to_hz: u32) -> Result<Option<Resampler<f32>>, Box<dyn Error>> {
+    if from_hz == to_hz {
+        return Ok(None);
+    }
+    eprintln!("audio: resampling {} Hz to {} Hz", from_hz, to_hz);
+    Ok(Some(Resampler::new(from_hz as f32, to_hz as f32)?))
+}
+
+fn select_input_device(
+    host: &cpal::Host,
+    device_regex: Option<&str>,
+) -> Result<cpal::Device, Box<dyn Error>> {
+    if let Some(pattern) = device_regex {
+        let re = Regex::new(pattern)?;
+        for dev in host.input_devices()? {
+            let name = dev.name().unwrap_or_else(|_| "<unknown>".to_string());
+            if re.is_match(&name) {
+                return Ok(dev);
+            }
+        }
+        return Err("no input device matched regex".into());
+    }
+
+    host.default_input_device()
+        .ok_or_else(|| "no default input device available".into())
+}
+
+fn select_output_device(
+    host: &cpal::Host,
+    device_regex: Option<&str>,
+) -> Result<cpal::Device, Box<dyn Error>> {
+    if let Some(pattern) = device_regex {
+        let re = Regex::new(pattern)?;
+        for dev in host.output_devices()? {
+            let name = dev.name().unwrap_or_else(|_| "<unknown>".to_string());
+            if re.is_match(&name) {
+                return Ok(dev);
+            }
+        }
+        return Err("no output device matched regex".into());
+    }
+
+    host.default_output_device()
+        .ok_or_else(|| "no default output device available".into())
+}
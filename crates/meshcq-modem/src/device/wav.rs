@@ -0,0 +1,226 @@
+//! WAV file input and output.
+
+use super::{
+    next_burst, AudioHandle, AudioSink, AudioSource, MessageCapture, Pacer, TimedChunk,
+    ENERGY_BLOCK, SAMPLE_RATE_HZ,
+};
+use crate::resample::Resampler;
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{Receiver, Sender};
+
+/// Read the first channel of a WAV file, resampled to 48 kHz if need be.
+pub struct WavSource {
+    reader: WavReader<BufReader<File>>,
+    real_time: bool,
+}
+
+impl WavSource {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let reader = WavReader::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(Self {
+            reader,
+            real_time: false,
+        })
+    }
+
+    /// Deliver samples no faster than real time, as a sound card would, instead of as
+    /// fast as they decode.
+    pub fn with_real_time(mut self, real_time: bool) -> Self {
+        self.real_time = real_time;
+        self
+    }
+}
+
+impl AudioSource for WavSource {
+    fn start(self: Box<Self>, tx: Sender<TimedChunk>) -> Result<AudioHandle, Box<dyn Error>> {
+        let Self {
+            mut reader,
+            real_time,
+        } = *self;
+        let spec = reader.spec();
+        let channels = spec.channels as usize;
+        let mut capture = MessageCapture::new(tx)?;
+        let mut resampler = match spec.sample_rate {
+            SAMPLE_RATE_HZ => None,
+            rate => Some(Resampler::new(rate as f32, SAMPLE_RATE_HZ as f32)?),
+        };
+        Ok(AudioHandle::spawn(false, move |stop| {
+            let samples: Box<dyn Iterator<Item = hound::Result<f32>>> = match spec.sample_format {
+                SampleFormat::Float => Box::new(reader.samples::<f32>()),
+                SampleFormat::Int => {
+                    let scale = 1.0 / (1u32 << (spec.bits_per_sample - 1)) as f32;
+                    Box::new(
+                        reader
+                            .samples::<i32>()
+                            .map(move |x| x.map(|x| x as f32 * scale)),
+                    )
+                }
+            };
+            let mut pacer = Pacer::new(real_time);
+            let mut mono = Vec::with_capacity(ENERGY_BLOCK);
+            let mut resampled = Vec::new();
+            let mut deliver = |frames: &[f32], end: bool| {
+                mono.clear();
+                mono.extend(frames.chunks_exact(channels).map(|frame| frame[0]));
+                let samples = match &mut resampler {
+                    None => &mono,
+                    Some(resampler) => {
+                        if end {
+                            // Flush the tail of the file out of the filter.
+                            mono.resize(mono.len() + resampler.latency() + 1, 0.0);
+                        }
+                        resampled.clear();
+                        resampler.process(&mono, &mut resampled);
+                        &resampled
+                    }
+                };
+                pacer.wait(samples.len());
+                capture.push(samples);
+            };
+            let block_len = ENERGY_BLOCK * channels;
+            let mut frames = Vec::with_capacity(block_len);
+            for sample in samples {
+                match sample {
+                    Ok(x) => frames.push(x),
+                    Err(err) => {
+                        eprintln!("audio input: {}", err);
+                        break;
+                    }
+                }
+                if frames.len() == block_len {
+                    deliver(&frames, false);
+                    frames.clear();
+                    if stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                }
+            }
+            deliver(&frames, true);
+            capture.finish();
+        }))
+    }
+}
+
+/// Write transmit audio to a mono 48 kHz float WAV file, one burst after another.
+pub struct WavSink {
+    writer: WavWriter<BufWriter<File>>,
+    level: f32,
+}
+
+impl WavSink {
+    /// Create (or truncate) `path`, scaling samples by `level` on the way out.
+    pub fn create(path: impl AsRef<Path>, level: f32) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE_HZ,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let writer =
+            WavWriter::create(path, spec).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(Self { writer, level })
+    }
+}
+
+impl AudioSink for WavSink {
+    fn start(self: Box<Self>, rx: Receiver<Vec<f32>>) -> Result<AudioHandle, Box<dyn Error>> {
+        let Self { mut writer, level } = *self;
+        Ok(AudioHandle::spawn(true, move |stop| {
+            'bursts: while let Some(burst) = next_burst(&rx, &stop) {
+                for x in burst {
+                    if let Err(err) = writer.write_sample(x * level) {
+                        eprintln!("audio output: {}", err);
+                        break 'bursts;
+                    }
+                }
+            }
+            if let Err(err) = writer.finalize() {
+                eprintln!("audio output: {}", err);
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn wav_round_trip_captures_message() {
+        let path = std::env::temp_dir().join(format!("meshcq-wav-{}.wav", std::process::id()));
+        let tone: Vec<f32> = (0..9600).map(|n| (n as f32 * 0.1).sin() * 0.5).collect();
+
+        let (out_tx, out_rx) = mpsc::channel();
+        let sink = Box::new(WavSink::create(&path, 0.5).expect("create"));
+        let handle = sink.start(out_rx).expect("start sink");
+        out_tx.send(vec![0.0; 4800]).unwrap();
+        out_tx.send(tone.clone()).unwrap();
+        out_tx.send(vec![0.0; 4800]).unwrap();
+        drop(out_tx);
+        handle.wait();
+
+        let (in_tx, in_rx) = mpsc::channel();
+        let source = Box::new(WavSource::open(&path).expect("open"));
+        source.start(in_tx).expect("start source").wait();
+        let chunks: Vec<TimedChunk> = in_rx.iter().collect();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(chunks.len(), 1);
+        let message = &chunks[0];
+        // Energy blocks are 1024 samples, so the message is the tone rounded out to
+        // whole blocks.
+        assert!(message.samples.len() >= tone.len() - 1024);
+        assert!(message.samples.len() <= tone.len() + 2048);
+        let peak = message.samples.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+        assert!((peak - 0.25).abs() < 1e-3);
+        assert!(message.end_sample >= 14_400 && message.end_sample <= 14_400 + 2048);
+    }
+
+    #[test]
+    fn other_rates_are_resampled_to_48k() {
+        let path = std::env::temp_dir().join(format!("meshcq-wav44-{}.wav", std::process::id()));
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 44_100,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).expect("create");
+        for n in 0..35_280 {
+            let t = n as f32 / 44_100.0;
+            let x = if (0.1..0.5).contains(&t) {
+                (std::f32::consts::TAU * 1000.0 * t).sin() * 0.5
+            } else {
+                0.0
+            };
+            writer.write_sample((x * 32_767.0) as i16).unwrap();
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let (in_tx, in_rx) = mpsc::channel();
+        let source = Box::new(WavSource::open(&path).expect("open"));
+        source.start(in_tx).expect("start source").wait();
+        let chunks: Vec<TimedChunk> = in_rx.iter().collect();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(chunks.len(), 1);
+        let message = &chunks[0];
+        assert!(message.samples.len().abs_diff(19_200) <= 2048);
+        assert!(message.end_sample >= 24_000 && message.end_sample <= 24_000 + 2048);
+        // A 1 kHz tone at 48 kHz crosses zero every 24 samples.
+        let crossings = message.samples[2048..2048 + 9600]
+            .windows(2)
+            .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+            .count();
+        assert!(crossings.abs_diff(400) <= 1, "{} crossings", crossings);
+    }
+}
@@ -0,0 +1,133 @@
+//! Forward error correction.
+//!
+//! Bits are carried one per byte (0 or 1) and soft inputs are log-likelihood ratios
+//! where positive values favour a 0 bit, matching [`crate::constellation`].
+
+pub mod conv;
+pub mod interleave;
+pub mod ldpc;
+
+pub use conv::ConvCode;
+pub use interleave::Interleaver;
+pub use ldpc::{CodeRate, LdpcCode};
+
+/// Result of decoding one or more codewords.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoded {
+    /// Decoded information bits.
+    pub bits: Vec<u8>,
+    /// Coded bits whose hard decision the decoder changed.
+    pub corrected: usize,
+    /// Whether every codeword passed the code's own check. Codes without one (such as
+    /// the convolutional code) always report true.
+    pub converged: bool,
+}
+
+/// A block code taking `info_len` bits to `coded_len` bits.
+pub trait Codec {
+    /// Information bits per codeword.
+    fn info_len(&self) -> usize;
+
+    /// Coded bits per codeword.
+    fn coded_len(&self) -> usize;
+
+    /// Encode exactly one codeword worth of information bits.
+    fn encode(&self, bits: &[u8]) -> Result<Vec<u8>, String>;
+
+    /// Decode one codeword from soft channel values.
+    fn decode(&self, llrs: &[f32]) -> Result<Decoded, String>;
+
+    /// Code rate.
+    fn rate(&self) -> f32 {
+        self.info_len() as f32 / self.coded_len() as f32
+    }
+
+    /// Number of codewords needed for `bits` information bits.
+    fn blocks_for(&self, bits: usize) -> usize {
+        bits.div_ceil(self.info_len()).max(1)
+    }
+
+    /// Encode any number of bits, zero-padding the last codeword.
+    fn encode_blocks(&self, bits: &[u8]) -> Result<Vec<u8>, String> {
+        let k = self.info_len();
+        let blocks = self.blocks_for(bits.len());
+        let mut out = Vec::with_capacity(blocks * self.coded_len());
+        let mut block = vec![0u8; k];
+        for i in 0..blocks {
+            let chunk = &bits[(i * k).min(bits.len())..((i + 1) * k).min(bits.len())];
+            block[..chunk.len()].copy_from_slice(chunk);
+            block[chunk.len()..].fill(0);
+            out.extend(self.encode(&block)?);
+        }
+        Ok(out)
+    }
+
+    /// Decode consecutive codewords; `llrs` must hold a whole number of them.
+    fn decode_blocks(&self, llrs: &[f32]) -> Result<Decoded, String> {
+        let n = self.coded_len();
+        if llrs.is_empty() || !llrs.len().is_multiple_of(n) {
+            return Err(format!(
+                "expected a multiple of {} soft bits, got {}",
+                n,
+                llrs.len()
+            ));
+        }
+        let mut out = Decoded {
+            bits: Vec::with_capacity(llrs.len() / n * self.info_len()),
+            corrected: 0,
+            converged: true,
+        };
+        for chunk in llrs.chunks_exact(n) {
+            let block = self.decode(chunk)?;
+            out.bits.extend(block.bits);
+            out.corrected += block.corrected;
+            out.converged &= block.converged;
+        }
+        Ok(out)
+    }
+}
+
+/// Count coded bits whose hard decision differs from the channel's.
+pub(crate) fn count_corrections(codeword: &[u8], llrs: &[f32]) -> usize {
+    codeword
+        .iter()
+        .zip(llrs)
+        .filter(|&(&bit, &llr)| (bit == 1) != (llr < 0.0))
+        .count()
+}
+
+#[cfg(test)]
+pub(crate) mod test_util {
+    /// BPSK over AWGN, returning channel LLRs.
+    pub fn bpsk_awgn(bits: &[u8], ebn0_db: f32, rate: f32, seed: u32) -> Vec<f32> {
+        let mut state = seed.max(1);
+        let mut uniform = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state as f32 / u32::MAX as f32).max(1e-9)
+        };
+        let ebn0 = 10f32.powf(ebn0_db / 10.0);
+        let sigma2 = 1.0 / (2.0 * rate * ebn0);
+        bits.iter()
+            .map(|&b| {
+                let x = if b == 0 { 1.0 } else { -1.0 };
+                let n = (-2.0 * sigma2 * uniform().ln()).sqrt()
+                    * (2.0 * std::f32::consts::PI * uniform()).cos();
+                2.0 * (x + n) / sigma2
+            })
+            .collect()
+    }
+
+    pub fn random_bits(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed.max(1);
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> 16) as u8 & 1
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,129 @@
+//! Rate 1/2, K=7 convolutional code with soft-decision Viterbi decoding.
+
+use super::{count_corrections, Codec, Decoded};
+
+const CONSTRAINT_LEN: usize = 7;
+const STATES: usize = 1 << (CONSTRAINT_LEN - 1);
+const TAIL: usize = CONSTRAINT_LEN - 1;
+/// Generator polynomials 133 and 171 (octal).
+const POLYS: [u32; 2] = [0o133, 0o171];
+
+/// Terminated convolutional code for short blocks such as frame headers.
+pub struct ConvCode {
+    info_len: usize,
+}
+
+impl ConvCode {
+    /// Create a code for blocks of `info_len` bits. Six zero tail bits are appended.
+    pub fn new(info_len: usize) -> Self {
+        Self { info_len }
+    }
+}
+
+fn outputs(state: usize, bit: u8) -> [u8; 2] {
+    let reg = ((bit as u32) << (CONSTRAINT_LEN - 1)) | state as u32;
+    POLYS.map(|p| ((reg & p).count_ones() & 1) as u8)
+}
+
+impl Codec for ConvCode {
+    fn info_len(&self) -> usize {
+        self.info_len
+    }
+
+    fn coded_len(&self) -> usize {
+        2 * (self.info_len + TAIL)
+    }
+
+    fn encode(&self, bits: &[u8]) -> Result<Vec<u8>, String> {
+        if bits.len() != self.info_len {
+            return Err(format!(
+                "expected {} information bits, got {}",
+                self.info_len,
+                bits.len()
+            ));
+        }
+        let mut state = 0usize;
+        let mut out = Vec::with_capacity(self.coded_len());
+        for &bit in bits.iter().chain(&[0; TAIL]) {
+            let bit = bit & 1;
+            out.extend(outputs(state, bit));
+            state = (state >> 1) | ((bit as usize) << (CONSTRAINT_LEN - 2));
+        }
+        Ok(out)
+    }
+
+    fn decode(&self, llrs: &[f32]) -> Result<Decoded, String> {
+        if llrs.len() != self.coded_len() {
+            return Err(format!(
+                "expected {} soft bits, got {}",
+                self.coded_len(),
+                llrs.len()
+            ));
+        }
+        let steps = self.info_len + TAIL;
+        let mut metrics = vec![f32::NEG_INFINITY; STATES];
+        metrics[0] = 0.0;
+        let mut next = vec![f32::NEG_INFINITY; STATES];
+        // Surviving predecessor state for each step and state.
+        let mut history = vec![0u8; steps * STATES];
+
+        for (step, pair) in llrs.chunks_exact(2).enumerate() {
+            next.fill(f32::NEG_INFINITY);
+            let input_bits: &[u8] = if step < self.info_len { &[0, 1] } else { &[0] };
+            for (state, &metric) in metrics.iter().enumerate() {
+                if metric == f32::NEG_INFINITY {
+                    continue;
+                }
+                for &bit in input_bits {
+                    let out = outputs(state, bit);
+                    let branch: f32 = out
+                        .iter()
+                        .zip(pair)
+                        .map(|(&b, &l)| if b == 0 { l } else { -l })
+                        .sum();
+                    let to = (state >> 1) | ((bit as usize) << (CONSTRAINT_LEN - 2));
+                    let candidate = metric + 0.5 * branch;
+                    if candidate > next[to] {
+                        next[to] = candidate;
+                        history[step * STATES + to] = state as u8;
+                    }
+                }
+            }
+            std::mem::swap(&mut metrics, &mut next);
+        }
+
+        // Terminated: trace back from the zero state.
+        let mut bits = vec![0u8; steps];
+        let mut state = 0usize;
+        for step in (0..steps).rev() {
+            bits[step] = (state >> (CONSTRAINT_LEN - 2)) as u8 & 1;
+            state = history[step * STATES + state] as usize;
+        }
+        bits.truncate(self.info_len);
+        let codeword = self.encode(&bits)?;
+        Ok(Decoded {
+            corrected: count_corrections(&codeword, llrs),
+            bits,
+            converged: true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fec::test_util::{bpsk_awgn, random_bits};
+
+    #[test]
+    fn decodes_through_noise() {
+        let code = ConvCode::new(120);
+        assert_eq!(code.coded_len(), 252);
+        for seed in 1..10 {
+            let bits = random_bits(120, seed);
+            let word = code.encode(&bits).expect("encode");
+            let llrs = bpsk_awgn(&word, 4.0, code.rate(), seed * 17);
+            let decoded = code.decode(&llrs).expect("decode");
+            assert_eq!(decoded.bits, bits, "seed {}", seed);
+        }
+    }
+}
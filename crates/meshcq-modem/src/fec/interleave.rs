@@ -0,0 +1,113 @@
+//! Bit interleaving.
+
+/// Pseudo-random bit interleaver.
+///
+/// Coded bits are spread over the whole frame, so a faded subcarrier or a burst of
+/// impulse noise lands on bits far apart in the codeword. The permutation depends
+/// only on the length, so transmitter and receiver agree without signalling.
+pub struct Interleaver {
+    permutation: Vec<usize>,
+}
+
+impl Interleaver {
+    /// Create an interleaver for `len` bits.
+    pub fn new(len: usize) -> Self {
+        let mut permutation: Vec<usize> = (0..len).collect();
+        let mut state = 0x6d2b_79f5u32 ^ len as u32;
+        for i in (1..len).rev() {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            permutation.swap(i, state as usize % (i + 1));
+        }
+        Self { permutation }
+    }
+
+    /// Number of bits the interleaver covers.
+    pub fn len(&self) -> usize {
+        self.permutation.len()
+    }
+
+    /// Whether the interleaver covers no bits.
+    pub fn is_empty(&self) -> bool {
+        self.permutation.is_empty()
+    }
+
+    /// Reorder `input` for transmission.
+    pub fn interleave<T: Copy>(&self, input: &[T]) -> Result<Vec<T>, String> {
+        self.check(input.len())?;
+        Ok(self.permutation.iter().map(|&i| input[i]).collect())
+    }
+
+    /// Undo [`Interleaver::interleave`], typically on soft bits.
+    pub fn deinterleave<T: Copy + Default>(&self, input: &[T]) -> Result<Vec<T>, String> {
+        self.check(input.len())?;
+        let mut out = vec![T::default(); input.len()];
+        for (&i, &v) in self.permutation.iter().zip(input) {
+            out[i] = v;
+        }
+        Ok(out)
+    }
+
+    fn check(&self, len: usize) -> Result<(), String> {
+        if len != self.permutation.len() {
+            return Err(format!(
+                "expected {} bits, got {}",
+                self.permutation.len(),
+                len
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fec::ldpc::CODEWORD_LEN;
+
+    #[test]
+    fn round_trip_restores_order() {
+        for len in [0, 1, 7, CODEWORD_LEN, 4 * CODEWORD_LEN] {
+            let il = Interleaver::new(len);
+            let input: Vec<usize> = (0..len).collect();
+            let sent = il.interleave(&input).expect("interleave");
+            let mut sorted = sent.clone();
+            sorted.sort_unstable();
+            assert_eq!(sorted, input, "not a permutation of {}", len);
+            if len > 7 {
+                assert_ne!(sent, input);
+            }
+            assert_eq!(il.deinterleave(&sent).expect("deinterleave"), input);
+        }
+        assert!(Interleaver::new(8).interleave(&[0u8; 7]).is_err());
+        assert!(Interleaver::new(8).deinterleave(&[0u8; 9]).is_err());
+    }
+
+    #[test]
+    fn spreads_bursts_across_codewords() {
+        // Without interleaving, a burst of 96 channel bits lands in one or two
+        // codewords as a single run.
+        let il = Interleaver::new(4 * CODEWORD_LEN);
+        for start in (0..il.len() - 96).step_by(7) {
+            let mut burst = vec![0u8; il.len()];
+            burst[start..start + 96].fill(1);
+            let errors = il.deinterleave(&burst).expect("deinterleave");
+            for (i, word) in errors.chunks(CODEWORD_LEN).enumerate() {
+                let count = word.iter().filter(|&&b| b == 1).count();
+                assert!(
+                    count <= 40,
+                    "burst at {}: {} errors in codeword {}",
+                    start,
+                    count,
+                    i
+                );
+            }
+            let mut run = 0;
+            for &b in &errors {
+                run = if b == 1 { run + 1 } else { 0 };
+                assert!(run <= 3, "burst at {} left a run of {}", start, run);
+            }
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Irregular repeat-accumulate LDPC codes.
+
+use super::{count_corrections, Codec, Decoded};
+use std::sync::OnceLock;
+
+/// Coded bits per LDPC codeword.
+pub const CODEWORD_LEN: usize = 960;
+const COLUMN_WEIGHT: usize = 3;
+const MAX_ITERATIONS: usize = 50;
+const MIN_SUM_SCALE: f32 = 0.75;
+
+/// LDPC code rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CodeRate {
+    R1_2,
+    R2_3,
+    R3_4,
+    R5_6,
+}
+
+impl CodeRate {
+    /// All rates, most robust first.
+    pub const ALL: [CodeRate; 4] = [
+        CodeRate::R1_2,
+        CodeRate::R2_3,
+        CodeRate::R3_4,
+        CodeRate::R5_6,
+    ];
+
+    /// Information bits per 960-bit codeword.
+    pub fn info_len(self) -> usize {
+        let (num, den) = self.fraction();
+        CODEWORD_LEN * num / den
+    }
+
+    fn fraction(self) -> (usize, usize) {
+        match self {
+            Self::R1_2 => (1, 2),
+            Self::R2_3 => (2, 3),
+            Self::R3_4 => (3, 4),
+            Self::R5_6 => (5, 6),
+        }
+    }
+}
+
+/// Systematic LDPC code with 960-bit codewords.
+///
+/// The parity-check matrix is `[Hs | Hp]`, where `Hs` has column weight 3 and `Hp` is
+/// dual-diagonal, so encoding is a running XOR. `Hs` is built deterministically with
+/// balanced row weights and no 4-cycles where possible. Decoding is layered
+/// normalised min-sum belief propagation.
+pub struct LdpcCode {
+    rate: CodeRate,
+    k: usize,
+    n: usize,
+    /// Systematic columns of each check row.
+    info_rows: Vec<Vec<usize>>,
+    /// All columns of each check row (systematic and parity).
+    rows: Vec<Vec<usize>>,
+}
+
+impl LdpcCode {
+    /// Build the code for `rate`.
+    pub fn new(rate: CodeRate) -> Self {
+        let n = CODEWORD_LEN;
+        let k = rate.info_len();
+        let m = n - k;
+        let info_rows = build_info_rows(k, m);
+        let rows = info_rows
+            .iter()
+            .enumerate()
+            .map(|(r, cols)| {
+                let mut row = cols.clone();
+                if r > 0 {
+                    row.push(k + r - 1);
+                }
+                row.push(k + r);
+                row
+            })
+            .collect();
+        Self {
+            rate,
+            k,
+            n,
+            info_rows,
+            rows,
+        }
+    }
+
+    /// A lazily built code for `rate`, shared by all callers.
+    pub fn shared(rate: CodeRate) -> &'static LdpcCode {
+        static CODES: [OnceLock<LdpcCode>; 4] = [const { OnceLock::new() }; 4];
+        let index = CodeRate::ALL.iter().position(|&r| r == rate).unwrap_or(0);
+        CODES[index].get_or_init(|| LdpcCode::new(rate))
+    }
+
+    /// The code rate.
+    pub fn code_rate(&self) -> CodeRate {
+        self.rate
+    }
+
+    fn syndrome_ok(&self, bits: &[u8]) -> bool {
+        self.rows
+            .iter()
+            .all(|row| row.iter().fold(0u8, |acc, &c| acc ^ bits[c]) == 0)
+    }
+}
+
+impl Codec for LdpcCode {
+    fn info_len(&self) -> usize {
+        self.k
+    }
+
+    fn coded_len(&self) -> usize {
+        self.n
+    }
+
+    fn encode(&self, bits: &[u8]) -> Result<Vec<u8>, String> {
+        if bits.len() != self.k {
+            return Err(format!(
+                "expected {} information bits, got {}",
+                self.k,
+                bits.len()
+            ));
+        }
+        let mut out = Vec::with_capacity(self.n);
+        out.extend(bits.iter().map(|b| b & 1));
+        let mut parity = 0u8;
+        for row in &self.info_rows {
+            parity ^= row.iter().fold(0u8, |acc, &c| acc ^ out[c]);
+            out.push(parity);
+        }
+        Ok(out)
+    }
+
+    fn decode(&self, llrs: &[f32]) -> Result<Decoded, String> {
+        if llrs.len() != self.n {
+            return Err(format!("expected {} soft bits, got {}", self.n, llrs.len()));
+        }
+        let mut posterior = llrs.to_vec();
+        let mut messages: Vec<Vec<f32>> = self.rows.iter().map(|r| vec![0.0; r.len()]).collect();
+        let mut incoming = Vec::new();
+        let mut hard = hard_bits(&posterior);
+        let mut converged = self.syndrome_ok(&hard);
+
+        for _ in 0..MAX_ITERATIONS {
+            if converged {
+                break;
+            }
+            for (row, msgs) in self.rows.iter().zip(messages.iter_mut()) {
+                incoming.clear();
+                incoming.extend(row.iter().zip(msgs.iter()).map(|(&c, &m)| posterior[c] - m));
+
+                let mut min1 = f32::INFINITY;
+                let mut min2 = f32::INFINITY;
+                let mut min_idx = 0;
+                let mut negative = false;
+                for (i, &v) in incoming.iter().enumerate() {
+                    let mag = v.abs();
+                    if mag < min1 {
+                        min2 = min1;
+                        min1 = mag;
+                        min_idx = i;
+                    } else if mag < min2 {
+                        min2 = mag;
+                    }
+                    negative ^= v < 0.0;
+                }
+
+                for (i, (&c, m)) in row.iter().zip(msgs.iter_mut()).enumerate() {
+                    let v = incoming[i];
+                    let mag = if i == min_idx { min2 } else { min1 } * MIN_SUM_SCALE;
+                    let sign_negative = negative ^ (v < 0.0);
+                    *m = if sign_negative { -mag } else { mag };
+                    posterior[c] = v + *m;
+                }
+            }
+            hard = hard_bits(&posterior);
+            converged = self.syndrome_ok(&hard);
+        }
+
+        Ok(Decoded {
+            corrected: count_corrections(&hard, llrs),
+            bits: hard[..self.k].to_vec(),
+            converged,
+        })
+    }
+}
+
+fn hard_bits(llrs: &[f32]) -> Vec<u8> {
+    llrs.iter().map(|&l| u8::from(l < 0.0)).collect()
+}
+
+/// Place `COLUMN_WEIGHT` ones in each systematic column across `m` check rows.
+fn build_info_rows(k: usize, m: usize) -> Vec<Vec<usize>> {
+    let mut rows: Vec<Vec<usize>> = vec![Vec::new(); m];
+    // Rows sharing a column with each row, to avoid 4-cycles.
+    let mut linked = vec![vec![false; m]; m];
+    let mut state = 0x9e37_79b9u32 ^ (k as u32) << 8 ^ m as u32;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+
+    // An information bit alone sets the parity bits between its first two rows, so
+    // keeping rows apart bounds the weight of such codewords from below.
+    let min_spacing = (m / 8).max(1);
+
+    for col in 0..k {
+        let mut chosen: Vec<usize> = Vec::with_capacity(COLUMN_WEIGHT);
+        for _ in 0..COLUMN_WEIGHT.min(m) {
+            let offset = next() as usize % m;
+            let candidate = |no_cycles: bool, spaced: bool| {
+                (0..m)
+                    .map(|i| (i + offset) % m)
+                    .filter(|r| !chosen.contains(r))
+                    .filter(|&r| !no_cycles || chosen.iter().all(|&c| !linked[r][c]))
+                    .filter(|&r| !spaced || chosen.iter().all(|&c| r.abs_diff(c) >= min_spacing))
+                    .min_by_key(|&r| rows[r].len())
+            };
+            let row = candidate(true, true)
+                .or_else(|| candidate(false, true))
+                .or_else(|| candidate(false, false))
+                .expect("m >= column weight");
+            chosen.push(row);
+        }
+        for &a in &chosen {
+            rows[a].push(col);
+            for &b in &chosen {
+                linked[a][b] = true;
+            }
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fec::test_util::{bpsk_awgn, random_bits};
+
+    #[test]
+    fn encoded_words_satisfy_parity_checks() {
+        for rate in CodeRate::ALL {
+            let code = LdpcCode::new(rate);
+            let word = code
+                .encode(&random_bits(code.info_len(), 7))
+                .expect("encode");
+            assert_eq!(word.len(), 960);
+            assert!(code.syndrome_ok(&word), "{:?}", rate);
+        }
+    }
+
+    #[test]
+    fn decodes_through_noise() {
+        // Comfortably above each rate's threshold, far below where uncoded BPSK is clean.
+        for (rate, ebn0_db) in [
+            (CodeRate::R1_2, 3.5),
+            (CodeRate::R2_3, 4.0),
+            (CodeRate::R3_4, 4.5),
+            (CodeRate::R5_6, 5.0),
+        ] {
+            let code = LdpcCode::new(rate);
+            for seed in 1..6 {
+                let bits = random_bits(code.info_len(), seed);
+                let word = code.encode(&bits).expect("encode");
+                let llrs = bpsk_awgn(&word, ebn0_db, code.rate(), seed * 31);
+                let decoded = code.decode(&llrs).expect("decode");
+                assert!(decoded.converged, "{:?} seed {}", rate, seed);
+                assert!(decoded.corrected > 0);
+                assert_eq!(decoded.bits, bits, "{:?} seed {}", rate, seed);
+            }
+        }
+    }
+}
@@ -0,0 +1,502 @@
+//! Link-layer frame format.
+//!
+//! A frame on air is the sync preamble followed by OFDM symbols carrying scattered
+//! pilots. The first [`Frame::header_symbols`] symbols hold the header: version, mode,
+//! payload length, destination and source callsigns, sequence number, flags and a
+//! CRC-16, protected by the convolutional code on BPSK. The payload and its CRC-32
+//! follow, LDPC-coded, interleaved and mapped with the modulation named in the header.
+
+use crate::channel::{ChannelEstimate, Equalizer};
+use crate::constellation::{bits_to_bytes, bytes_to_bits, Modulation};
+use crate::fec::ldpc::CODEWORD_LEN;
+use crate::fec::{CodeRate, Codec, ConvCode, Interleaver, LdpcCode};
+use crate::pilots::PilotPattern;
+use crate::quality::{evm_db, LinkQuality};
+use rustfft::num_complex::Complex;
+
+/// Frame format version carried in every header.
+pub const FRAME_VERSION: u8 = 1;
+/// Maximum callsign length.
+pub const MAX_CALLSIGN_LEN: usize = 9;
+
+const HEADER_BYTES: usize = 20;
+const CALLSIGN_BYTES: usize = 6;
+const CALLSIGN_ALPHABET: &[u8; 40] = b" 0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ/-.";
+
+/// Modulation and coding scheme of a frame payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mode {
+    pub modulation: Modulation,
+    pub rate: CodeRate,
+}
+
+impl Mode {
+    /// All modes in header id order, most robust first.
+    pub const ALL: [Mode; 8] = [
+        Mode::new(Modulation::Bpsk, CodeRate::R1_2),
+        Mode::new(Modulation::Qpsk, CodeRate::R1_2),
+        Mode::new(Modulation::Qpsk, CodeRate::R3_4),
+        Mode::new(Modulation::Psk8, CodeRate::R2_3),
+        Mode::new(Modulation::Qam16, CodeRate::R1_2),
+        Mode::new(Modulation::Qam16, CodeRate::R3_4),
+        Mode::new(Modulation::Qam64, CodeRate::R2_3),
+        Mode::new(Modulation::Qam64, CodeRate::R5_6),
+    ];
+
+    pub const fn new(modulation: Modulation, rate: CodeRate) -> Self {
+        Self { modulation, rate }
+    }
+
+    /// The most robust mode.
+    pub fn most_robust() -> Self {
+        Self::ALL[0]
+    }
+
+    /// Information bits carried per data subcarrier.
+    pub fn bits_per_carrier(self) -> f32 {
+        self.modulation.bits_per_symbol() as f32 * self.rate.info_len() as f32
+            / CODEWORD_LEN as f32
+    }
+
+    /// Header id of this mode, if it is one of [`Mode::ALL`].
+    pub fn id(self) -> Option<u8> {
+        Self::ALL.iter().position(|&m| m == self).map(|i| i as u8)
+    }
+
+    /// Look up a mode by header id.
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+}
+
+/// Frame header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub mode: Mode,
+    pub destination: String,
+    pub source: String,
+    pub seq: u16,
+    pub flags: u8,
+}
+
+/// A link-layer frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub header: Header,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Number of OFDM symbols (after the preamble) that carry the header: four with
+    /// the default 104 subcarriers, more for narrower profiles.
+    pub fn header_symbols(pattern: &PilotPattern) -> usize {
+        let coded = ConvCode::new(HEADER_BYTES * 8).coded_len();
+        let mut symbols = 0;
+        let mut carried = 0;
+        while carried < coded {
+            carried += pattern.data_carriers(symbols);
+            symbols += 1;
+        }
+        symbols
+    }
+
+    /// Coded bits for a payload of `len` bytes: payload plus CRC-32, in whole LDPC
+    /// codewords.
+    pub fn payload_coded_len(mode: Mode, len: usize) -> usize {
+        ((len + 4) * 8).div_ceil(mode.rate.info_len()).max(1) * CODEWORD_LEN
+    }
+
+    /// Number of OFDM symbols after the preamble for a payload of `len` bytes.
+    pub fn symbol_count(mode: Mode, len: usize, pattern: &PilotPattern) -> usize {
+        let coded = Self::payload_coded_len(mode, len);
+        let mut symbols = Self::header_symbols(pattern);
+        let mut carried = 0;
+        while carried < coded {
+            carried += pattern.data_carriers(symbols) * mode.modulation.bits_per_symbol();
+            symbols += 1;
+        }
+        symbols
+    }
+
+    /// Encode the frame into subcarrier symbols (pilots included) for
+    /// [`crate::OfdmModulator::modulate_frame`].
+    pub fn encode(&self, pattern: &PilotPattern) -> Result<Vec<Vec<Complex<f32>>>, String> {
+        let header = &self.header;
+        let mode_id = header
+            .mode
+            .id()
+            .ok_or_else(|| format!("unsupported mode {:?}", header.mode))?;
+        let len = u16::try_from(self.payload.len())
+            .map_err(|_| format!("payload of {} bytes is too long", self.payload.len()))?;
+
+        let mut header_bytes = Vec::with_capacity(HEADER_BYTES);
+        header_bytes.push(FRAME_VERSION << 4 | mode_id);
+        header_bytes.extend(len.to_be_bytes());
+        header_bytes.extend(encode_callsign(&header.destination)?);
+        header_bytes.extend(encode_callsign(&header.source)?);
+        header_bytes.extend(header.seq.to_be_bytes());
+        header_bytes.push(header.flags);
+        header_bytes.extend(crc16(&header_bytes).to_be_bytes());
+
+        let header_code = ConvCode::new(HEADER_BYTES * 8);
+        let coded = header_code.encode(&bytes_to_bits(&header_bytes))?;
+        let coded = Interleaver::new(coded.len()).interleave(&coded)?;
+        let header_points = Modulation::Bpsk.map(&coded);
+
+        let mut payload = self.payload.clone();
+        payload.extend(crc32(&self.payload).to_be_bytes());
+        let code = LdpcCode::shared(header.mode.rate);
+        let coded = code.encode_blocks(&bytes_to_bits(&payload))?;
+        let coded = Interleaver::new(coded.len()).interleave(&coded)?;
+        let payload_points = header.mode.modulation.map(&coded);
+
+        let total = Self::symbol_count(header.mode, self.payload.len(), pattern);
+        let header_symbols = Self::header_symbols(pattern);
+        let mut header_points = header_points.into_iter();
+        let mut payload_points = payload_points.into_iter();
+        let filler = Modulation::Bpsk.points()[0];
+        (0..total)
+            .map(|t| {
+                let points = if t < header_symbols {
+                    &mut header_points
+                } else {
+                    &mut payload_points
+                };
+                let data: Vec<_> = (0..pattern.data_carriers(t))
+                    .map(|_| points.next().unwrap_or(filler))
+                    .collect();
+                pattern.assemble(t, &data)
+            })
+            .collect()
+    }
+
+    /// Decode the header from the first [`Frame::header_symbols`] symbols after the
+    /// preamble.
+    ///
+    /// Use [`Frame::symbol_count`] with the result to find out how many symbols the
+    /// whole frame needs.
+    pub fn decode_header(
+        symbols: &[Vec<Complex<f32>>],
+        pattern: &PilotPattern,
+    ) -> Result<(Header, usize), String> {
+        let header_symbols = Self::header_symbols(pattern);
+        if symbols.len() < header_symbols {
+            return Err(format!(
+                "need {} header symbols, got {}",
+                header_symbols,
+                symbols.len()
+            ));
+        }
+        let equalized = equalize(&symbols[..header_symbols], pattern)?;
+        let (header, len, _) = decode_header_points(&equalized.points, &equalized.noise)?;
+        Ok((header, len))
+    }
+
+    /// Decode a whole frame from the symbols that follow the preamble.
+    pub fn decode(symbols: &[Vec<Complex<f32>>], pattern: &PilotPattern) -> Result<Frame, String> {
+        Self::decode_with_quality(symbols, pattern).map(|(frame, _)| frame)
+    }
+
+    /// Decode a whole frame and measure the link it arrived over.
+    pub fn decode_with_quality(
+        symbols: &[Vec<Complex<f32>>],
+        pattern: &PilotPattern,
+    ) -> Result<(Frame, LinkQuality), String> {
+        let header_symbols = Self::header_symbols(pattern);
+        if symbols.len() < header_symbols {
+            return Err(format!(
+                "need {} header symbols, got {}",
+                header_symbols,
+                symbols.len()
+            ));
+        }
+        let equalized = equalize(symbols, pattern)?;
+        let (points, noise) = (&equalized.points, &equalized.noise);
+        let (header, len, header_corrected) = decode_header_points(points, noise)?;
+
+        let total = Self::symbol_count(header.mode, len, pattern);
+        if symbols.len() < total {
+            return Err(format!(
+                "frame needs {} symbols, got {}",
+                total,
+                symbols.len()
+            ));
+        }
+        let header_carriers: usize = (0..header_symbols).map(|t| pattern.data_carriers(t)).sum();
+        let code = LdpcCode::shared(header.mode.rate);
+        let coded_len = Self::payload_coded_len(header.mode, len);
+        let bps = header.mode.modulation.bits_per_symbol();
+        let used = coded_len.div_ceil(bps);
+        let range = header_carriers..header_carriers + used;
+        let llrs = header
+            .mode
+            .modulation
+            .demap(&points[range.clone()], &noise[range.clone()])?;
+        let interleaver = Interleaver::new(coded_len);
+        let llrs = interleaver.deinterleave(&llrs[..coded_len])?;
+        let decoded = code.decode_blocks(&llrs)?;
+        let bytes = bits_to_bytes(&decoded.bits);
+
+        let (payload, crc) = bytes[..len + 4].split_at(len);
+        if crc32(payload).to_be_bytes() != crc {
+            return Err("payload CRC mismatch".into());
+        }
+
+        // With the payload known, re-encode it for a data-aided EVM.
+        let reference = interleaver.interleave(&code.encode_blocks(&decoded.bits)?)?;
+        let reference = header.mode.modulation.map(&reference);
+        let mut quality = LinkQuality::from_estimate(&equalized.estimate);
+        quality.evm_db = evm_db(&points[range], &reference);
+        quality.corrected_bits = header_corrected + decoded.corrected;
+        quality.coded_bits = ConvCode::new(HEADER_BYTES * 8).coded_len() + coded_len;
+        Ok((
+            Frame {
+                header,
+                payload: payload.to_vec(),
+            },
+            quality,
+        ))
+    }
+}
+
+/// Equalised data carriers with their noise, and the channel they were taken from.
+struct EqualizedFrame {
+    points: Vec<Complex<f32>>,
+    noise: Vec<f32>,
+    estimate: ChannelEstimate,
+}
+
+/// Estimate the channel and return the equalised data carriers with their noise.
+fn equalize(
+    symbols: &[Vec<Complex<f32>>],
+    pattern: &PilotPattern,
+) -> Result<EqualizedFrame, String> {
+    let estimate = ChannelEstimate::estimate(pattern, symbols)?;
+    let mut points = Vec::new();
+    let mut noise = Vec::new();
+    for (t, (symbol, gains)) in symbols.iter().zip(&estimate.gains).enumerate() {
+        let eq = Equalizer::ZeroForcing.equalize(symbol, gains, estimate.noise_var)?;
+        points.extend(pattern.extract(t, &eq.symbols));
+        noise.extend(pattern.extract(t, &eq.noise_var));
+    }
+    Ok(EqualizedFrame {
+        points,
+        noise,
+        estimate,
+    })
+}
+
+/// Decode the header, returning it with the payload length and the coded bits the
+/// decoder corrected.
+fn decode_header_points(
+    points: &[Complex<f32>],
+    noise: &[f32],
+) -> Result<(Header, usize, usize), String> {
+    let code = ConvCode::new(HEADER_BYTES * 8);
+    let n = code.coded_len();
+    let llrs = Modulation::Bpsk.demap(&points[..n], &noise[..n])?;
+    let llrs = Interleaver::new(n).deinterleave(&llrs)?;
+    let decoded = code.decode(&llrs)?;
+    let bytes = bits_to_bytes(&decoded.bits);
+
+    let (body, crc) = bytes.split_at(HEADER_BYTES - 2);
+    if crc16(body).to_be_bytes() != crc {
+        return Err("header CRC mismatch".into());
+    }
+    let version = body[0] >> 4;
+    if version != FRAME_VERSION {
+        return Err(format!("unsupported frame version {}", version));
+    }
+    let mode = Mode::from_id(body[0] & 0x0f)
+        .ok_or_else(|| format!("unknown mode id {}", body[0] & 0x0f))?;
+    let len = u16::from_be_bytes([body[1], body[2]]) as usize;
+    let destination = decode_callsign(&body[3..3 + CALLSIGN_BYTES])?;
+    let source = decode_callsign(&body[9..9 + CALLSIGN_BYTES])?;
+    let header = Header {
+        mode,
+        destination,
+        source,
+        seq: u16::from_be_bytes([body[15], body[16]]),
+        flags: body[17],
+    };
+    Ok((header, len, decoded.corrected))
+}
+
+/// Pack a callsign of up to nine characters into six bytes (base 40).
+fn encode_callsign(callsign: &str) -> Result<[u8; CALLSIGN_BYTES], String> {
+    let callsign = callsign.trim().to_ascii_uppercase();
+    if callsign.is_empty() || callsign.len() > MAX_CALLSIGN_LEN {
+        return Err(format!(
+            "callsign '{}' must be 1 to {} characters",
+            callsign, MAX_CALLSIGN_LEN
+        ));
+    }
+    let mut value = 0u64;
+    for ch in callsign.bytes().rev() {
+        let digit = CALLSIGN_ALPHABET
+            .iter()
+            .position(|&c| c == ch && c != b' ')
+            .ok_or_else(|| format!("callsign '{}' has invalid character", callsign))?;
+        value = value * 40 + digit as u64;
+    }
+    let mut out = [0u8; CALLSIGN_BYTES];
+    out.copy_from_slice(&value.to_be_bytes()[2..]);
+    Ok(out)
+}
+
+fn decode_callsign(bytes: &[u8]) -> Result<String, String> {
+    let mut raw = [0u8; 8];
+    raw[2..].copy_from_slice(bytes);
+    let mut value = u64::from_be_bytes(raw);
+    let mut out = String::new();
+    while value > 0 {
+        out.push(CALLSIGN_ALPHABET[(value % 40) as usize] as char);
+        value /= 40;
+    }
+    if out.is_empty() || out.len() > MAX_CALLSIGN_LEN || out.contains(' ') {
+        return Err("invalid callsign in header".into());
+    }
+    Ok(out)
+}
+
+/// CRC-16/CCITT-FALSE.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// CRC-32 (IEEE 802.3).
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OfdmDemodulator, OfdmModulator, PreambleDetector};
+
+    fn frame(mode: Mode, payload: &[u8]) -> Frame {
+        Frame {
+            header: Header {
+                mode,
+                destination: "CQ".into(),
+                source: "KD2ABC/P".into(),
+                seq: 513,
+                flags: 0x81,
+            },
+            payload: payload.to_vec(),
+        }
+    }
+
+    #[test]
+    fn crcs_match_reference_values() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn callsigns_round_trip() {
+        for call in ["W1AW", "KD2ABC/P", "VE3-XYZ.9", "Q"] {
+            let packed = encode_callsign(call).expect("encode");
+            assert_eq!(decode_callsign(&packed).expect("decode"), call);
+        }
+        assert!(encode_callsign("TOOLONGCALL").is_err());
+        assert!(encode_callsign("K1 AB").is_err());
+    }
+
+    #[test]
+    fn frames_round_trip_in_every_mode() {
+        let pattern = PilotPattern::default();
+        let payload: Vec<u8> = (0..300).map(|i| (i * 7) as u8).collect();
+        for mode in Mode::ALL {
+            let sent = frame(mode, &payload);
+            let symbols = sent.encode(&pattern).expect("encode");
+            assert_eq!(
+                symbols.len(),
+                Frame::symbol_count(mode, payload.len(), &pattern)
+            );
+            let (header, len) = Frame::decode_header(&symbols, &pattern).expect("header");
+            assert_eq!(header, sent.header);
+            assert_eq!(len, payload.len());
+            assert_eq!(Frame::decode(&symbols, &pattern).expect("decode"), sent);
+        }
+    }
+
+    #[test]
+    fn decodes_from_sample_stream() {
+        let pattern = PilotPattern::default();
+        let sent = frame(Mode::ALL[2], b"hello from the mesh");
+        let symbols = sent.encode(&pattern).expect("encode");
+        let samples = OfdmModulator::new()
+            .modulate_frame(&symbols)
+            .expect("modulate");
+
+        // Leading silence, a carrier phase rotation and a little noise.
+        let rotation = Complex::from_polar(0.5, 1.1);
+        let mut state = 0x1357_9bdfu32;
+        let mut stream = vec![Complex::new(0.0, 0.0); 3000];
+        stream.extend(samples.iter().map(|&s| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let noise = (state as f32 / u32::MAX as f32 - 0.5) * 2e-4;
+            s * rotation + Complex::new(noise, -noise)
+        }));
+        stream.extend(vec![Complex::new(0.0, 0.0); 5000]);
+
+        let sync = PreambleDetector::default().push(&stream);
+        assert_eq!(sync.len(), 1);
+        let demod = OfdmDemodulator::new();
+        let first = sync[0].start as usize + 2 * 2304;
+        let received: Vec<_> = (0..symbols.len())
+            .map(|i| {
+                let start = first + i * 2304;
+                demod
+                    .demodulate(&stream[start..start + 2304])
+                    .expect("demodulate")
+            })
+            .collect();
+        assert_eq!(Frame::decode(&received, &pattern).expect("decode"), sent);
+    }
+
+    #[test]
+    fn rejects_corrupted_payload() {
+        let pattern = PilotPattern::default();
+        let mut symbols = frame(Mode::ALL[7], &[0x55; 64])
+            .encode(&pattern)
+            .expect("encode");
+        let last = symbols.len() - 1;
+        for (c, v) in symbols[last].iter_mut().enumerate() {
+            if !pattern.is_pilot(last, c) {
+                *v = -*v;
+            }
+        }
+        for (c, v) in symbols[last - 1].iter_mut().enumerate() {
+            if !pattern.is_pilot(last - 1, c) {
+                *v = -*v;
+            }
+        }
+        assert!(Frame::decode(&symbols, &pattern).is_err());
+    }
+}
@@ -2,5 +2,41 @@
 
 pub mod ofdm;
 pub mod device;
+pub mod sync;
+pub mod pilots;
+pub mod channel;
+pub mod constellation;
+pub mod fec;
+pub mod frame;
+pub mod passband;
+pub mod config;
+pub mod shaping;
+pub mod spectrum;
+pub mod papr;
+pub mod afc;
+pub mod resample;
+pub mod sfo;
+pub mod quality;
+pub mod adaptive;
+pub mod arq;
+pub mod receiver;
 
-pub use ofdm::OfdmModulator;
+pub use adaptive::ModePolicy;
+pub use afc::FrequencyTracker;
+pub use arq::{ArqConfig, ArqSession, FrameKind, SessionState};
+pub use channel::{ChannelEstimate, Equalized, Equalizer};
+pub use config::{OfdmConfig, Profile};
+pub use constellation::Modulation;
+pub use fec::{CodeRate, Codec, ConvCode, Interleaver, LdpcCode};
+pub use frame::{Frame, Header, Mode};
+pub use ofdm::{OfdmDemodulator, OfdmModulator};
+pub use papr::{PaprBurst, PaprMethod, PaprReducer, PaprReport};
+pub use passband::{Downconverter, Passband, Upconverter};
+pub use pilots::PilotPattern;
+pub use quality::LinkQuality;
+pub use receiver::FrameReceiver;
+pub use resample::Resampler;
+pub use sfo::ClockTracker;
+pub use shaping::TxShaper;
+pub use spectrum::SpectrumReport;
+pub use sync::{Preamble, PreambleDetector, SyncResult, PREAMBLE_SYMBOLS};
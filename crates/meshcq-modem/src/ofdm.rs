@@ -1,22 +1,29 @@
 //! OFDM modulation utilities.
 
+use crate::config::OfdmConfig;
+use crate::sync::Preamble;
 use rustfft::num_complex::Complex;
-use rustfft::FftPlanner;
+use rustfft::{Fft, FftPlanner};
+use std::sync::Arc;
 
-/// OFDM modulator using a 2048-point IFFT with 104 active subcarriers.
+/// OFDM modulator. The default numerology is a 2048-point IFFT with 104 active
+/// subcarriers on bins 1..=104 and a 256-sample cyclic prefix.
+///
+/// The IFFT plan is created once. The `_into` methods also reuse the modulator's
+/// scratch space and write into caller-provided buffers, so they do not allocate.
 pub struct OfdmModulator {
     nfft: usize,
+    first_bin: usize,
     active_bins: usize,
     cp_len: usize,
+    ifft: Arc<dyn Fft<f32>>,
+    scratch: Vec<Complex<f32>>,
+    preamble: Preamble,
 }
 
 impl Default for OfdmModulator {
     fn default() -> Self {
-        Self {
-            nfft: 2048,
-            active_bins: 104,
-            cp_len: 256,
-        }
+        Self::from_config(&OfdmConfig::default())
     }
 }
 
@@ -26,40 +33,246 @@ impl OfdmModulator {
         Self::default()
     }
 
+    /// Create a modulator for `config`.
+    pub fn from_config(config: &OfdmConfig) -> Self {
+        let ifft = FftPlanner::<f32>::new().plan_fft_inverse(config.nfft);
+        let scratch = vec![Complex::new(0.0, 0.0); ifft.get_inplace_scratch_len()];
+        Self {
+            nfft: config.nfft,
+            first_bin: config.first_bin,
+            active_bins: config.carriers,
+            cp_len: config.cp_len,
+            ifft,
+            scratch,
+            preamble: Preamble::new(config.first_bin, config.carriers),
+        }
+    }
+
+    /// Samples per symbol including the cyclic prefix.
+    pub fn symbol_len(&self) -> usize {
+        self.nfft + self.cp_len
+    }
+
     /// Modulate one OFDM symbol.
     ///
-    /// `data` must contain exactly 104 complex subcarrier symbols. These are mapped to
-    /// the lowest positive-frequency bins (1..=104), leaving DC (bin 0) unused.
-    /// The remaining bins are zeroed. The output is the 2048-sample time-domain
-    /// complex baseband symbol with a 256-sample cyclic prefix prepended.
+    /// `data` must contain one complex symbol per subcarrier (104 by default). These are
+    /// mapped to consecutive bins from the first active bin (bins 1..=104 by default),
+    /// leaving DC (bin 0) unused. The remaining bins are zeroed. The output is the
+    /// time-domain complex baseband symbol with the cyclic prefix prepended.
     pub fn modulate(&self, data: &[Complex<f32>]) -> Result<Vec<Complex<f32>>, String> {
-        if data.len() != self.active_bins {
+        let mut out = vec![Complex::new(0.0, 0.0); self.symbol_len()];
+        let mut scratch = vec![Complex::new(0.0, 0.0); self.scratch.len()];
+        self.modulate_with(data, &mut out, &mut scratch)?;
+        Ok(out)
+    }
+
+    /// Modulate one OFDM symbol into `out`, which must hold exactly one symbol
+    /// ([`OfdmModulator::symbol_len`] samples).
+    pub fn modulate_into(
+        &mut self,
+        data: &[Complex<f32>],
+        out: &mut [Complex<f32>],
+    ) -> Result<(), String> {
+        let mut scratch = std::mem::take(&mut self.scratch);
+        let result = self.modulate_with(data, out, &mut scratch);
+        self.scratch = scratch;
+        result
+    }
+
+    /// Modulate consecutive symbols into `out`.
+    ///
+    /// `data` holds the subcarrier values of each symbol back to back, and `out` must
+    /// hold exactly that many symbols. Returns the number of symbols written.
+    pub fn modulate_burst_into(
+        &mut self,
+        data: &[Complex<f32>],
+        out: &mut [Complex<f32>],
+    ) -> Result<usize, String> {
+        if !data.len().is_multiple_of(self.active_bins) {
             return Err(format!(
-                "expected {} subcarriers, got {}",
+                "expected a multiple of {} subcarriers, got {}",
                 self.active_bins,
                 data.len()
             ));
         }
+        let count = data.len() / self.active_bins;
+        self.check_output(count, out.len())?;
+        let symbol_len = self.symbol_len();
+        for (sym, chunk) in data
+            .chunks_exact(self.active_bins)
+            .zip(out.chunks_exact_mut(symbol_len))
+        {
+            self.modulate_into(sym, chunk)?;
+        }
+        Ok(count)
+    }
+
+    /// Modulate a frame: the two-symbol sync preamble followed by `symbols`.
+    ///
+    /// Each entry of `symbols` is one OFDM symbol of subcarrier values, as for
+    /// [`OfdmModulator::modulate`].
+    pub fn modulate_frame(
+        &self,
+        symbols: &[Vec<Complex<f32>>],
+    ) -> Result<Vec<Complex<f32>>, String> {
+        let symbol_len = self.symbol_len();
+        let mut out = vec![Complex::new(0.0, 0.0); (symbols.len() + 2) * symbol_len];
+        let mut scratch = vec![Complex::new(0.0, 0.0); self.scratch.len()];
+        for (sym, chunk) in self
+            .preamble
+            .symbols()
+            .iter()
+            .chain(symbols)
+            .zip(out.chunks_exact_mut(symbol_len))
+        {
+            self.modulate_with(sym, chunk, &mut scratch)?;
+        }
+        Ok(out)
+    }
+
+    /// Modulate a frame (preamble plus `symbols`) into `out`, which must hold exactly
+    /// `symbols.len() + 2` symbols.
+    pub fn modulate_frame_into(
+        &mut self,
+        symbols: &[Vec<Complex<f32>>],
+        out: &mut [Complex<f32>],
+    ) -> Result<(), String> {
+        self.check_output(symbols.len() + 2, out.len())?;
+        let symbol_len = self.symbol_len();
+        let mut scratch = std::mem::take(&mut self.scratch);
+        let result = self
+            .preamble
+            .symbols()
+            .iter()
+            .chain(symbols)
+            .zip(out.chunks_exact_mut(symbol_len))
+            .try_for_each(|(sym, chunk)| self.modulate_with(sym, chunk, &mut scratch));
+        self.scratch = scratch;
+        result
+    }
+
+    fn check_output(&self, symbols: usize, len: usize) -> Result<(), String> {
+        let expected = symbols * self.symbol_len();
+        if len != expected {
+            return Err(format!(
+                "output holds {} samples, {} symbols need {}",
+                len, symbols, expected
+            ));
+        }
+        Ok(())
+    }
 
-        let mut freq_bins = vec![Complex::new(0.0, 0.0); self.nfft];
-        for (i, sym) in data.iter().enumerate() {
-            freq_bins[i + 1] = *sym;
+    fn modulate_with(
+        &self,
+        data: &[Complex<f32>],
+        out: &mut [Complex<f32>],
+        scratch: &mut [Complex<f32>],
+    ) -> Result<(), String> {
+        if data.len() != self.active_bins {
+            return Err(format!(
+                "expected {} subcarriers, got {}",
+                self.active_bins,
+                data.len()
+            ));
         }
+        self.check_output(1, out.len())?;
 
-        let mut planner = FftPlanner::<f32>::new();
-        let ifft = planner.plan_fft_inverse(self.nfft);
-        ifft.process(&mut freq_bins);
+        // Build the spectrum in place after the prefix, then copy the prefix from the tail.
+        let (prefix, body) = out.split_at_mut(self.cp_len);
+        body.fill(Complex::new(0.0, 0.0));
+        body[self.first_bin..self.first_bin + self.active_bins].copy_from_slice(data);
+        self.ifft.process_with_scratch(body, scratch);
 
         // Normalize: rustfft's inverse is unscaled.
         let scale = 1.0 / self.nfft as f32;
-        for bin in &mut freq_bins {
+        for bin in body.iter_mut() {
             *bin *= scale;
         }
+        prefix.copy_from_slice(&body[self.nfft - self.cp_len..]);
+        Ok(())
+    }
+}
+
+/// OFDM demodulator matching [`OfdmModulator`].
+pub struct OfdmDemodulator {
+    nfft: usize,
+    first_bin: usize,
+    active_bins: usize,
+    cp_len: usize,
+    fft: Arc<dyn Fft<f32>>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Default for OfdmDemodulator {
+    fn default() -> Self {
+        Self::from_config(&OfdmConfig::default())
+    }
+}
+
+impl OfdmDemodulator {
+    /// Create a demodulator with the default 2048-point FFT and 104 active subcarriers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a demodulator for `config`.
+    pub fn from_config(config: &OfdmConfig) -> Self {
+        let fft = FftPlanner::<f32>::new().plan_fft_forward(config.nfft);
+        let scratch = vec![Complex::new(0.0, 0.0); fft.get_inplace_scratch_len()];
+        Self {
+            nfft: config.nfft,
+            first_bin: config.first_bin,
+            active_bins: config.carriers,
+            cp_len: config.cp_len,
+            fft,
+            buffer: vec![Complex::new(0.0, 0.0); config.nfft],
+            scratch,
+        }
+    }
 
-        let mut with_cp = Vec::with_capacity(self.nfft + self.cp_len);
-        with_cp.extend_from_slice(&freq_bins[self.nfft - self.cp_len..]);
-        with_cp.extend_from_slice(&freq_bins);
-        Ok(with_cp)
+    /// Demodulate one OFDM symbol.
+    ///
+    /// `samples` must be one symbol-aligned time-domain symbol (2304 samples by default),
+    /// starting with the cyclic prefix. The prefix is discarded, the remaining samples
+    /// are transformed, and the subcarrier symbols from the active bins are returned.
+    pub fn demodulate(&self, samples: &[Complex<f32>]) -> Result<Vec<Complex<f32>>, String> {
+        self.check_input(samples.len())?;
+        let mut bins = samples[self.cp_len..].to_vec();
+        let mut scratch = vec![Complex::new(0.0, 0.0); self.scratch.len()];
+        self.fft.process_with_scratch(&mut bins, &mut scratch);
+
+        // The modulator already applied the 1/N scale, so the forward FFT is left unscaled.
+        Ok(bins[self.first_bin..self.first_bin + self.active_bins].to_vec())
+    }
+
+    /// Demodulate one OFDM symbol into `out`, which must hold one value per subcarrier.
+    pub fn demodulate_into(
+        &mut self,
+        samples: &[Complex<f32>],
+        out: &mut [Complex<f32>],
+    ) -> Result<(), String> {
+        self.check_input(samples.len())?;
+        if out.len() != self.active_bins {
+            return Err(format!(
+                "output holds {} subcarriers, expected {}",
+                out.len(),
+                self.active_bins
+            ));
+        }
+        self.buffer.copy_from_slice(&samples[self.cp_len..]);
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+        out.copy_from_slice(&self.buffer[self.first_bin..self.first_bin + self.active_bins]);
+        Ok(())
+    }
+
+    fn check_input(&self, len: usize) -> Result<(), String> {
+        let symbol_len = self.nfft + self.cp_len;
+        if len != symbol_len {
+            return Err(format!("expected {} samples, got {}", symbol_len, len));
+        }
+        Ok(())
     }
 }
 
@@ -82,4 +295,86 @@ mod tests {
         assert_eq!(out.len(), 2048 + 256);
         assert!(out.iter().all(|v| v.re == 0.0 && v.im == 0.0));
     }
+
+    #[test]
+    fn demodulator_rejects_wrong_length() {
+        let demod = OfdmDemodulator::new();
+        let samples = vec![Complex::new(0.0, 0.0); 2048];
+        assert!(demod.demodulate(&samples).is_err());
+    }
+
+    #[test]
+    fn round_trip_recovers_subcarriers() {
+        let modem = OfdmModulator::new();
+        let demod = OfdmDemodulator::new();
+        let points = [
+            Complex::new(1.0, 1.0),
+            Complex::new(-1.0, 1.0),
+            Complex::new(-1.0, -1.0),
+            Complex::new(1.0, -1.0),
+        ];
+        let data: Vec<_> = (0..104).map(|i| points[(i * 7 + i / 3) % 4]).collect();
+        let symbol = modem.modulate(&data).expect("modulate");
+        let out = demod.demodulate(&symbol).expect("demodulate");
+        assert_eq!(out.len(), 104);
+        for (a, b) in data.iter().zip(&out) {
+            assert!((a - b).norm() < 1e-4, "expected {}, got {}", a, b);
+        }
+    }
+
+    #[test]
+    fn cyclic_prefix_absorbs_early_timing() {
+        let modem = OfdmModulator::new();
+        let demod = OfdmDemodulator::new();
+        let data: Vec<_> = (0..104)
+            .map(|i| Complex::from_polar(1.0, i as f32 * 0.3))
+            .collect();
+        let symbol = modem.modulate(&data).expect("modulate");
+
+        // Starting the window 16 samples into the prefix only rotates each bin.
+        let shift = 16;
+        let mut early = vec![Complex::new(0.0, 0.0); shift];
+        early.extend_from_slice(&symbol[..symbol.len() - shift]);
+        let out = demod.demodulate(&early).expect("demodulate");
+        for (k, (a, b)) in data.iter().zip(&out).enumerate() {
+            let bin = (k + 1) as f32;
+            let rot = Complex::from_polar(1.0, -std::f32::consts::TAU * bin * shift as f32 / 2048.0);
+            assert!((a * rot - b).norm() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn burst_matches_single_symbols() {
+        let mut modem = OfdmModulator::new();
+        let data: Vec<_> = (0..3 * 104)
+            .map(|i| Complex::from_polar(1.0, i as f32 * 1.3))
+            .collect();
+        let mut burst = vec![Complex::new(0.0, 0.0); 3 * modem.symbol_len()];
+        assert_eq!(
+            modem.modulate_burst_into(&data, &mut burst).expect("burst"),
+            3
+        );
+        for (sym, chunk) in data.chunks(104).zip(burst.chunks(modem.symbol_len())) {
+            assert_eq!(modem.modulate(sym).expect("modulate"), chunk);
+        }
+
+        let mut demod = OfdmDemodulator::new();
+        let mut out = vec![Complex::new(0.0, 0.0); 104];
+        demod
+            .demodulate_into(&burst[..modem.symbol_len()], &mut out)
+            .expect("demodulate");
+        for (a, b) in data.iter().zip(&out) {
+            assert!((a - b).norm() < 1e-4);
+        }
+
+        let frame = modem
+            .modulate_frame(&[data[..104].to_vec()])
+            .expect("frame");
+        let mut into = vec![Complex::new(0.0, 0.0); 3 * modem.symbol_len()];
+        modem
+            .modulate_frame_into(&[data[..104].to_vec()], &mut into)
+            .expect("frame into");
+        assert_eq!(frame, into);
+        assert!(modem.modulate_burst_into(&data, &mut into[1..]).is_err());
+    }
 }
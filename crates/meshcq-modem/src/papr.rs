@@ -0,0 +1,337 @@
+//! Peak-to-average power reduction for OFDM transmit.
+//!
+//! Two methods are available:
+//!
+//! - Clipping and filtering: clip the envelope, remove the out-of-band products and
+//!   repeat. Cheap and transparent to the receiver, at the cost of in-band distortion.
+//! - Tone reservation: spare bins that fall inside the radio passband but outside the
+//!   data subcarriers carry a peak-cancelling signal. The receiver ignores them, so
+//!   data is untouched.
+
+use crate::config::OfdmConfig;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::sync::Arc;
+
+const DEFAULT_CLIP_RATIO_DB: f32 = 5.0;
+const DEFAULT_ITERATIONS: usize = 4;
+const DEFAULT_TR_ITERATIONS: usize = 12;
+
+/// PAPR reduction method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaprMethod {
+    None,
+    ClipFilter,
+    ToneReservation,
+}
+
+/// One modulated symbol with its PAPR figures.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaprSymbol {
+    /// Time-domain symbol with its cyclic prefix.
+    pub samples: Vec<Complex<f32>>,
+    /// Crest factor of the plain symbol in dB.
+    pub crest_before_db: f32,
+    /// Crest factor after reduction in dB.
+    pub crest_after_db: f32,
+}
+
+/// Crest factors of a whole burst.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PaprReport {
+    /// Crest factor of the burst without reduction, in dB.
+    pub before_db: f32,
+    /// Crest factor of the burst with reduction, in dB.
+    pub after_db: f32,
+}
+
+/// A modulated burst with its crest factors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaprBurst {
+    /// Time-domain symbols, each with its cyclic prefix.
+    pub samples: Vec<Complex<f32>>,
+    /// Crest factor of the burst before and after reduction.
+    pub report: PaprReport,
+}
+
+/// Modulates symbols with PAPR reduction.
+pub struct PaprReducer {
+    method: PaprMethod,
+    nfft: usize,
+    first_bin: usize,
+    carriers: usize,
+    cp_len: usize,
+    clip_ratio_db: f32,
+    iterations: usize,
+    reserved: Vec<usize>,
+    fft: Arc<dyn Fft<f32>>,
+    ifft: Arc<dyn Fft<f32>>,
+}
+
+impl PaprReducer {
+    /// Create a reducer for `config` using `method`.
+    pub fn from_config(config: &OfdmConfig, method: PaprMethod) -> Self {
+        let mut planner = FftPlanner::<f32>::new();
+        Self {
+            method,
+            nfft: config.nfft,
+            first_bin: config.first_bin,
+            carriers: config.carriers,
+            cp_len: config.cp_len,
+            clip_ratio_db: DEFAULT_CLIP_RATIO_DB,
+            iterations: if method == PaprMethod::ToneReservation {
+                DEFAULT_TR_ITERATIONS
+            } else {
+                DEFAULT_ITERATIONS
+            },
+            reserved: reserved_bins(config),
+            fft: planner.plan_fft_forward(config.nfft),
+            ifft: planner.plan_fft_inverse(config.nfft),
+        }
+    }
+
+    /// Set the clipping level above the RMS envelope, in dB (default 5).
+    pub fn with_clip_ratio_db(mut self, db: f32) -> Self {
+        self.clip_ratio_db = db;
+        self
+    }
+
+    /// Set the number of clipping or tone reservation iterations.
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations.max(1);
+        self
+    }
+
+    /// FFT bins available for tone reservation.
+    pub fn reserved_bins(&self) -> &[usize] {
+        &self.reserved
+    }
+
+    /// Modulate one symbol of subcarrier values.
+    pub fn modulate(&self, data: &[Complex<f32>]) -> Result<PaprSymbol, String> {
+        if data.len() != self.carriers {
+            return Err(format!(
+                "expected {} subcarriers, got {}",
+                self.carriers,
+                data.len()
+            ));
+        }
+        let plain = self.to_time(&self.to_bins(data));
+        let crest_before_db = crest_factor_db(&plain);
+
+        let body = match self.method {
+            PaprMethod::None => plain,
+            PaprMethod::ClipFilter => self.clip_filter(plain),
+            PaprMethod::ToneReservation => self.tone_reservation(plain),
+        };
+        let crest_after_db = crest_factor_db(&body);
+
+        let mut samples = Vec::with_capacity(self.nfft + self.cp_len);
+        samples.extend_from_slice(&body[self.nfft - self.cp_len..]);
+        samples.extend_from_slice(&body);
+        Ok(PaprSymbol {
+            samples,
+            crest_before_db,
+            crest_after_db,
+        })
+    }
+
+    /// Modulate consecutive symbols into one burst.
+    pub fn modulate_burst(&self, symbols: &[Vec<Complex<f32>>]) -> Result<PaprBurst, String> {
+        let mut samples = Vec::with_capacity(symbols.len() * (self.nfft + self.cp_len));
+        let mut plain = Vec::with_capacity(samples.capacity());
+        for data in symbols {
+            let symbol = self.modulate(data)?;
+            let body = self.to_time(&self.to_bins(data));
+            plain.extend_from_slice(&body[self.nfft - self.cp_len..]);
+            plain.extend_from_slice(&body);
+            samples.extend(symbol.samples);
+        }
+        let report = PaprReport {
+            before_db: crest_factor_db(&plain),
+            after_db: crest_factor_db(&samples),
+        };
+        Ok(PaprBurst { samples, report })
+    }
+
+    fn to_bins(&self, data: &[Complex<f32>]) -> Vec<Complex<f32>> {
+        let mut bins = vec![Complex::new(0.0, 0.0); self.nfft];
+        bins[self.first_bin..self.first_bin + self.carriers].copy_from_slice(data);
+        bins
+    }
+
+    fn to_time(&self, bins: &[Complex<f32>]) -> Vec<Complex<f32>> {
+        let mut time = bins.to_vec();
+        self.ifft.process(&mut time);
+        let scale = 1.0 / self.nfft as f32;
+        for s in &mut time {
+            *s *= scale;
+        }
+        time
+    }
+
+    fn to_bins_from_time(&self, time: &[Complex<f32>]) -> Vec<Complex<f32>> {
+        let mut bins = time.to_vec();
+        self.fft.process(&mut bins);
+        bins
+    }
+
+    fn clip_level(&self, time: &[Complex<f32>]) -> f32 {
+        let rms = (time.iter().map(|s| s.norm_sqr()).sum::<f32>() / time.len() as f32).sqrt();
+        rms * 10f32.powf(self.clip_ratio_db / 20.0)
+    }
+
+    fn clip_filter(&self, mut time: Vec<Complex<f32>>) -> Vec<Complex<f32>> {
+        let level = self.clip_level(&time);
+        let active = self.first_bin..self.first_bin + self.carriers;
+        for _ in 0..self.iterations {
+            for s in &mut time {
+                let mag = s.norm();
+                if mag > level {
+                    *s *= level / mag;
+                }
+            }
+            // Clipping spreads energy over every bin; keep only the data subcarriers.
+            let mut bins = self.to_bins_from_time(&time);
+            for (k, b) in bins.iter_mut().enumerate() {
+                if !active.contains(&k) {
+                    *b = Complex::new(0.0, 0.0);
+                }
+            }
+            time = self.to_time(&bins);
+        }
+        time
+    }
+
+    fn tone_reservation(&self, mut time: Vec<Complex<f32>>) -> Vec<Complex<f32>> {
+        if self.reserved.is_empty() {
+            return time;
+        }
+        let level = self.clip_level(&time);
+        let mut best = time.clone();
+        let mut best_peak = peak(&time);
+        for _ in 0..self.iterations {
+            // The clipping noise, projected onto the reserved bins, cancels the peaks
+            // without touching the data subcarriers.
+            let noise: Vec<Complex<f32>> = time
+                .iter()
+                .map(|&s| {
+                    let mag = s.norm();
+                    if mag > level {
+                        s * (level / mag - 1.0)
+                    } else {
+                        Complex::new(0.0, 0.0)
+                    }
+                })
+                .collect();
+            let noise_bins = self.to_bins_from_time(&noise);
+            let mut correction = vec![Complex::new(0.0, 0.0); self.nfft];
+            for &k in &self.reserved {
+                correction[k] = noise_bins[k];
+            }
+            // The projection keeps only a fraction of the clipping energy; scale it up
+            // so each step removes a useful part of the peak.
+            let gain = self.nfft as f32 / self.reserved.len() as f32;
+            for (s, c) in time.iter_mut().zip(self.to_time(&correction)) {
+                *s += c * gain.sqrt();
+            }
+            let p = peak(&time);
+            if p < best_peak {
+                best_peak = p;
+                best.copy_from_slice(&time);
+            }
+        }
+        best
+    }
+}
+
+/// FFT bins that fall inside the radio passband but carry no data subcarrier.
+///
+/// With the default numerology these are a few bins either side of the occupied band
+/// (the lower ones wrap to the top of the FFT).
+pub fn reserved_bins(config: &OfdmConfig) -> Vec<usize> {
+    let passband = config.passband();
+    let spacing = config.spacing_hz();
+    let shift = passband.carrier_hz() - passband.baseband_centre_hz();
+    let half = config.nfft as isize / 2;
+    let low = config.audio_low_hz + spacing / 2.0;
+    let high = config.audio_high_hz - spacing / 2.0;
+    let active = config.first_bin as isize..(config.first_bin + config.carriers) as isize;
+    (-half..half)
+        .filter(|k| !active.contains(k))
+        .filter(|&k| {
+            let audio_hz = k as f32 * spacing + shift;
+            audio_hz >= low && audio_hz <= high
+        })
+        .map(|k| k.rem_euclid(config.nfft as isize) as usize)
+        .collect()
+}
+
+/// Crest factor (peak over RMS) of a complex envelope, in dB.
+pub fn crest_factor_db(samples: &[Complex<f32>]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let mean = samples.iter().map(|s| s.norm_sqr()).sum::<f32>() / samples.len() as f32;
+    10.0 * (peak(samples) / mean.max(f32::MIN_POSITIVE)).log10()
+}
+
+fn peak(samples: &[Complex<f32>]) -> f32 {
+    samples.iter().fold(0.0f32, |m, s| m.max(s.norm_sqr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Modulation, OfdmDemodulator};
+
+    fn symbols() -> Vec<Vec<Complex<f32>>> {
+        let bytes: Vec<u8> = (0..26 * 20).map(|i| (i * 91 + i / 7) as u8).collect();
+        Modulation::Qpsk
+            .map_bytes(&bytes)
+            .chunks(104)
+            .map(|c| c.to_vec())
+            .collect()
+    }
+
+    fn mean_error(method: PaprMethod) -> (PaprReport, f32) {
+        let config = OfdmConfig::default();
+        let reducer = PaprReducer::from_config(&config, method);
+        let sent = symbols();
+        let burst = reducer.modulate_burst(&sent).expect("modulate");
+        let demod = OfdmDemodulator::new();
+        let mut err = 0.0;
+        for (chunk, data) in burst.samples.chunks(2304).zip(&sent) {
+            let out = demod.demodulate(chunk).expect("demodulate");
+            err += out
+                .iter()
+                .zip(data)
+                .map(|(a, b)| (a - b).norm_sqr())
+                .sum::<f32>();
+        }
+        (burst.report, err / (sent.len() * 104) as f32)
+    }
+
+    #[test]
+    fn spare_bins_sit_inside_the_passband() {
+        let bins = reserved_bins(&OfdmConfig::default());
+        assert!(bins.len() >= 8, "{:?}", bins);
+        assert!(bins.iter().all(|&k| !(1..=104).contains(&k)));
+    }
+
+    #[test]
+    fn every_method_lowers_the_crest_factor() {
+        let (plain, plain_err) = mean_error(PaprMethod::None);
+        assert!((plain.before_db - plain.after_db).abs() < 1e-3);
+        assert!(plain_err < 1e-8);
+
+        let (clip, clip_err) = mean_error(PaprMethod::ClipFilter);
+        assert!(clip.after_db < plain.after_db - 3.0, "{:?}", clip);
+        // Distortion stays well inside what QPSK tolerates.
+        assert!(clip_err < 0.05, "{}", clip_err);
+
+        let (tr, tr_err) = mean_error(PaprMethod::ToneReservation);
+        assert!(tr.after_db < plain.after_db - 1.0, "{:?}", tr);
+        assert!(tr_err < 1e-8, "{}", tr_err);
+    }
+}
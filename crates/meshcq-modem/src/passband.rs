@@ -0,0 +1,297 @@
+//! Conversion between complex baseband and real audio passband.
+//!
+//! The modulator places subcarriers on bins starting just above DC. On transmit the
+//! occupied band is shifted to sit centred on an audio carrier and the real part is
+//! sent to the sound card. On receive the audio is mixed down by the carrier,
+//! low-pass filtered to remove the image, and shifted back so the subcarriers land on
+//! their original bins.
+
+use crate::config::OfdmConfig;
+use rustfft::num_complex::Complex;
+
+const DEFAULT_CARRIER_HZ: f32 = 1650.0;
+const DEFAULT_LOW_HZ: f32 = 300.0;
+const DEFAULT_HIGH_HZ: f32 = 3000.0;
+const MAX_FILTER_TAPS: usize = 511;
+
+/// Audio passband placement of the OFDM subcarriers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Passband {
+    sample_rate_hz: f32,
+    nfft: usize,
+    first_bin: usize,
+    active_bins: usize,
+    carrier_hz: f32,
+    low_hz: f32,
+    high_hz: f32,
+}
+
+impl Default for Passband {
+    fn default() -> Self {
+        OfdmConfig::default().passband()
+    }
+}
+
+impl Passband {
+    /// Create a passband for subcarriers on bins `first_bin..first_bin + active_bins`
+    /// of an `nfft`-point FFT, centred on 1650 Hz in a 300-3000 Hz radio passband.
+    pub fn new(sample_rate_hz: f32, nfft: usize, first_bin: usize, active_bins: usize) -> Self {
+        Self {
+            sample_rate_hz,
+            nfft,
+            first_bin,
+            active_bins,
+            carrier_hz: DEFAULT_CARRIER_HZ,
+            low_hz: DEFAULT_LOW_HZ,
+            high_hz: DEFAULT_HIGH_HZ,
+        }
+    }
+
+    /// Set the audio frequency the occupied band is centred on.
+    pub fn with_carrier_hz(mut self, carrier_hz: f32) -> Self {
+        self.carrier_hz = carrier_hz;
+        self
+    }
+
+    /// Set the usable audio passband of the radio.
+    pub fn with_audio_band(mut self, low_hz: f32, high_hz: f32) -> Self {
+        self.low_hz = low_hz;
+        self.high_hz = high_hz;
+        self
+    }
+
+    /// Audio carrier in Hz.
+    pub fn carrier_hz(&self) -> f32 {
+        self.carrier_hz
+    }
+
+    /// Subcarrier spacing in Hz.
+    pub fn spacing_hz(&self) -> f32 {
+        self.sample_rate_hz / self.nfft as f32
+    }
+
+    /// Width of the occupied band in Hz (outer subcarrier edges).
+    pub fn bandwidth_hz(&self) -> f32 {
+        self.active_bins as f32 * self.spacing_hz()
+    }
+
+    /// Lowest and highest audio frequency occupied by the subcarriers.
+    pub fn occupied_hz(&self) -> (f32, f32) {
+        let half = self.bandwidth_hz() / 2.0;
+        (self.carrier_hz - half, self.carrier_hz + half)
+    }
+
+    /// Check that the occupied band fits the radio passband and the sample rate.
+    pub fn check(&self) -> Result<(), String> {
+        let (low, high) = self.occupied_hz();
+        if self.active_bins == 0 || self.nfft == 0 {
+            return Err("no active subcarriers".into());
+        }
+        if self.first_bin + self.active_bins > self.nfft / 2 {
+            return Err(format!(
+                "bins {}..{} exceed half of the {}-point FFT",
+                self.first_bin,
+                self.first_bin + self.active_bins,
+                self.nfft
+            ));
+        }
+        if high > self.sample_rate_hz / 2.0 {
+            return Err(format!(
+                "occupied band {:.0}-{:.0} Hz exceeds Nyquist at {} Hz",
+                low, high, self.sample_rate_hz
+            ));
+        }
+        if low < self.low_hz || high > self.high_hz {
+            return Err(format!(
+                "occupied band {:.0}-{:.0} Hz ({:.0} Hz wide at {:.0} Hz) does not fit the {:.0}-{:.0} Hz passband",
+                low,
+                high,
+                self.bandwidth_hz(),
+                self.carrier_hz,
+                self.low_hz,
+                self.high_hz
+            ));
+        }
+        Ok(())
+    }
+
+    /// Centre of the occupied band in complex baseband.
+    pub(crate) fn baseband_centre_hz(&self) -> f32 {
+        (self.first_bin as f32 + (self.active_bins as f32 - 1.0) / 2.0) * self.spacing_hz()
+    }
+
+    /// Create a transmit up-converter after checking the passband.
+    pub fn upconverter(&self) -> Result<Upconverter, String> {
+        self.check()?;
+        Ok(Upconverter {
+            mixer: Oscillator::new(
+                self.carrier_hz - self.baseband_centre_hz(),
+                self.sample_rate_hz,
+            ),
+        })
+    }
+
+    /// Create a receive down-converter after checking the passband.
+    pub fn downconverter(&self) -> Result<Downconverter, String> {
+        self.check()?;
+        // The image after mixing down sits around -2 * carrier; the filter has to pass
+        // half the band and stop everything from the image's near edge.
+        let half = self.bandwidth_hz() / 2.0;
+        let stop = 2.0 * self.carrier_hz - half;
+        let transition = (stop - half).max(1.0);
+        let cutoff = (half + stop) / 2.0;
+        // Blackman window: transition width is about 5.5 / taps of the sample rate.
+        let taps =
+            ((5.5 * self.sample_rate_hz / transition).ceil() as usize | 1).min(MAX_FILTER_TAPS);
+        let mut recentre = Oscillator::new(self.baseband_centre_hz(), self.sample_rate_hz);
+        // Output lags the mixer by the filter delay; start the shift back that far.
+        recentre.phase = (-recentre.step * (taps / 2) as f64).rem_euclid(std::f64::consts::TAU);
+        Ok(Downconverter {
+            carrier: Oscillator::new(-self.carrier_hz, self.sample_rate_hz),
+            recentre,
+            taps: lowpass_taps(taps, cutoff / self.sample_rate_hz),
+            history: vec![Complex::new(0.0, 0.0); taps],
+            pos: 0,
+        })
+    }
+}
+
+/// Complex oscillator with its phase kept in double precision.
+#[derive(Debug, Clone)]
+struct Oscillator {
+    phase: f64,
+    step: f64,
+}
+
+impl Oscillator {
+    fn new(freq_hz: f32, sample_rate_hz: f32) -> Self {
+        Self {
+            phase: 0.0,
+            step: std::f64::consts::TAU * freq_hz as f64 / sample_rate_hz as f64,
+        }
+    }
+
+    fn next(&mut self) -> Complex<f32> {
+        let out = Complex::new(self.phase.cos() as f32, self.phase.sin() as f32);
+        self.phase = (self.phase + self.step).rem_euclid(std::f64::consts::TAU);
+        out
+    }
+}
+
+/// Shifts complex baseband to a real audio signal.
+pub struct Upconverter {
+    mixer: Oscillator,
+}
+
+impl Upconverter {
+    /// Convert baseband samples to audio, keeping phase across calls.
+    ///
+    /// The output has the same power as the input.
+    pub fn process(&mut self, baseband: &[Complex<f32>], out: &mut Vec<f32>) {
+        out.extend(
+            baseband
+                .iter()
+                .map(|&x| std::f32::consts::SQRT_2 * (x * self.mixer.next()).re),
+        );
+    }
+}
+
+/// Recovers complex baseband from real audio.
+pub struct Downconverter {
+    carrier: Oscillator,
+    recentre: Oscillator,
+    taps: Vec<f32>,
+    history: Vec<Complex<f32>>,
+    pos: usize,
+}
+
+impl Downconverter {
+    /// Delay of the image filter in samples.
+    ///
+    /// Output sample `n` corresponds to input sample `n - delay()`.
+    pub fn delay(&self) -> usize {
+        self.taps.len() / 2
+    }
+
+    /// Convert audio to baseband, keeping filter and phase state across calls.
+    pub fn process(&mut self, audio: &[f32], out: &mut Vec<Complex<f32>>) {
+        let len = self.taps.len();
+        for &x in audio {
+            self.history[self.pos] = self.carrier.next() * (std::f32::consts::SQRT_2 * x);
+            self.pos = (self.pos + 1) % len;
+            // history[pos] is now the oldest sample.
+            let mut acc = Complex::new(0.0, 0.0);
+            let (old, new) = self.history.split_at(self.pos);
+            for (tap, &h) in self.taps.iter().zip(new.iter().chain(old)) {
+                acc += h * *tap;
+            }
+            out.push(acc * self.recentre.next());
+        }
+    }
+}
+
+/// Blackman-windowed sinc low-pass with `cutoff` as a fraction of the sample rate.
+pub(crate) fn lowpass_taps(len: usize, cutoff: f32) -> Vec<f32> {
+    let mid = (len - 1) as f32 / 2.0;
+    let mut taps: Vec<f32> = (0..len)
+        .map(|i| {
+            let t = i as f32 - mid;
+            let sinc = if t == 0.0 {
+                2.0 * cutoff
+            } else {
+                (std::f32::consts::TAU * cutoff * t).sin() / (std::f32::consts::PI * t)
+            };
+            let w = std::f32::consts::TAU * i as f32 / (len - 1).max(1) as f32;
+            sinc * (0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos())
+        })
+        .collect();
+    let sum: f32 = taps.iter().sum();
+    for tap in &mut taps {
+        *tap /= sum;
+    }
+    taps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OfdmDemodulator, OfdmModulator};
+
+    #[test]
+    fn default_band_fits_voice_passband() {
+        let band = Passband::default();
+        assert!(band.check().is_ok());
+        let (low, high) = band.occupied_hz();
+        assert!(low > 300.0 && high < 3000.0, "{} {}", low, high);
+        // 104 subcarriers are too wide to centre on 1500 Hz.
+        assert!(band.with_carrier_hz(1500.0).check().is_err());
+        assert!(Passband::new(4_000.0, 512, 1, 104).check().is_err());
+    }
+
+    #[test]
+    fn round_trip_through_audio() {
+        let band = Passband::default();
+        let modem = OfdmModulator::new();
+        let data: Vec<_> = (0..104)
+            .map(|i| Complex::from_polar(1.0, i as f32 * 0.7))
+            .collect();
+        let baseband = modem.modulate(&data).expect("modulate");
+        let mut padded = baseband.clone();
+        padded.extend(vec![Complex::new(0.0, 0.0); 1024]);
+
+        let mut audio = Vec::new();
+        band.upconverter().expect("up").process(&padded, &mut audio);
+        let mut down = band.downconverter().expect("down");
+        let mut recovered = Vec::new();
+        down.process(&audio, &mut recovered);
+
+        let delay = down.delay();
+        let symbol = &recovered[delay..delay + baseband.len()];
+        let out = OfdmDemodulator::new()
+            .demodulate(symbol)
+            .expect("demodulate");
+        for (a, b) in out.iter().zip(&data) {
+            assert!((a - b).norm() < 0.05, "{} vs {}", a, b);
+        }
+    }
+}
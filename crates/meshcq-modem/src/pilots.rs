@@ -0,0 +1,122 @@
+//! Scattered pilot layout for the OFDM subcarriers.
+
+use crate::config::OfdmConfig;
+use crate::sync::pn_sequence;
+use rustfft::num_complex::Complex;
+
+const PILOT_SEED: u8 = 0x3d;
+
+/// Scattered pilot pattern.
+///
+/// In symbol `t`, every `spacing`-th subcarrier starting at `(t * shift) % spacing` is a
+/// pilot. With the default spacing of 8 and shift of 2 each symbol has 13 pilots and 91
+/// data carriers, and every even subcarrier sees a pilot once every 4 symbols.
+pub struct PilotPattern {
+    carriers: usize,
+    spacing: usize,
+    shift: usize,
+    values: Vec<Complex<f32>>,
+}
+
+impl Default for PilotPattern {
+    fn default() -> Self {
+        OfdmConfig::default().pilot_pattern()
+    }
+}
+
+impl PilotPattern {
+    /// Create a pattern for `carriers` subcarriers.
+    pub fn new(carriers: usize, spacing: usize, shift: usize) -> Self {
+        let spacing = spacing.max(1);
+        let values = pn_sequence(carriers, PILOT_SEED)
+            .into_iter()
+            .map(|v| Complex::new(v, 0.0))
+            .collect();
+        Self {
+            carriers,
+            spacing,
+            shift: shift % spacing,
+            values,
+        }
+    }
+
+    /// Total number of subcarriers.
+    pub fn carriers(&self) -> usize {
+        self.carriers
+    }
+
+    /// Pilot spacing in subcarriers.
+    pub fn spacing(&self) -> usize {
+        self.spacing
+    }
+
+    /// Number of symbols before the pattern repeats.
+    pub fn period(&self) -> usize {
+        if self.shift == 0 {
+            1
+        } else {
+            self.spacing / gcd(self.spacing, self.shift)
+        }
+    }
+
+    /// Whether subcarrier `carrier` of symbol `symbol` is a pilot.
+    pub fn is_pilot(&self, symbol: usize, carrier: usize) -> bool {
+        carrier % self.spacing == (symbol * self.shift) % self.spacing
+    }
+
+    /// Known value transmitted on a pilot subcarrier.
+    pub fn value(&self, carrier: usize) -> Complex<f32> {
+        self.values[carrier]
+    }
+
+    /// Number of data subcarriers in symbol `symbol`.
+    pub fn data_carriers(&self, symbol: usize) -> usize {
+        (0..self.carriers)
+            .filter(|&c| !self.is_pilot(symbol, c))
+            .count()
+    }
+
+    /// Combine data and pilots into one symbol of `carriers` subcarrier values.
+    pub fn assemble(
+        &self,
+        symbol: usize,
+        data: &[Complex<f32>],
+    ) -> Result<Vec<Complex<f32>>, String> {
+        let expected = self.data_carriers(symbol);
+        if data.len() != expected {
+            return Err(format!(
+                "expected {} data carriers, got {}",
+                expected,
+                data.len()
+            ));
+        }
+        let mut data = data.iter();
+        Ok((0..self.carriers)
+            .map(|c| {
+                if self.is_pilot(symbol, c) {
+                    self.values[c]
+                } else {
+                    *data.next().expect("data length checked")
+                }
+            })
+            .collect())
+    }
+
+    /// Return the data subcarriers of a received or equalised symbol.
+    pub fn extract<T: Copy>(&self, symbol: usize, carriers: &[T]) -> Vec<T> {
+        carriers
+            .iter()
+            .enumerate()
+            .filter(|&(c, _)| !self.is_pilot(symbol, c))
+            .map(|(_, &v)| v)
+            .collect()
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
@@ -0,0 +1,160 @@
+//! Link quality metrics for received frames.
+
+use crate::channel::ChannelEstimate;
+use rustfft::num_complex::Complex;
+
+/// Reported SNRs are capped here; a noiseless channel would otherwise be infinite.
+const MAX_SNR_DB: f32 = 60.0;
+
+/// How good the link was for one received frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkQuality {
+    /// Average signal-to-noise ratio per subcarrier over the frame, in dB.
+    pub snr_db: f32,
+    /// SNR of each subcarrier averaged over the frame, in dB.
+    pub carrier_snr_db: Vec<f32>,
+    /// RMS error vector magnitude of the payload, relative to the constellation RMS,
+    /// in dB.
+    pub evm_db: f32,
+    /// Coded bits the header and payload decoders had to correct.
+    pub corrected_bits: usize,
+    /// Coded bits received for the header and payload.
+    pub coded_bits: usize,
+}
+
+impl LinkQuality {
+    /// SNR figures from a channel estimate; the EVM and FEC counts are left empty.
+    pub(crate) fn from_estimate(estimate: &ChannelEstimate) -> Self {
+        let carriers = estimate.gains.first().map_or(0, Vec::len);
+        let symbols = estimate.gains.len().max(1) as f32;
+        let noise = estimate.noise_var;
+        let carrier_power: Vec<f32> = (0..carriers)
+            .map(|c| estimate.gains.iter().map(|g| g[c].norm_sqr()).sum::<f32>() / symbols)
+            .collect();
+        let mean_power = carrier_power.iter().sum::<f32>() / carriers.max(1) as f32;
+        Self {
+            snr_db: snr_db(mean_power, noise),
+            carrier_snr_db: carrier_power.iter().map(|&p| snr_db(p, noise)).collect(),
+            evm_db: 0.0,
+            corrected_bits: 0,
+            coded_bits: 0,
+        }
+    }
+
+    /// Fraction of coded bits in error before FEC.
+    pub fn raw_ber(&self) -> f32 {
+        if self.coded_bits == 0 {
+            0.0
+        } else {
+            self.corrected_bits as f32 / self.coded_bits as f32
+        }
+    }
+
+    /// Harmonic mean of the subcarrier SNRs, in dB. Faded subcarriers dominate, as
+    /// they do the coded error rate, so this is the figure to choose a mode from.
+    pub fn effective_snr_db(&self) -> f32 {
+        if self.carrier_snr_db.is_empty() {
+            return self.snr_db;
+        }
+        let inverse = self
+            .carrier_snr_db
+            .iter()
+            .map(|&db| 10f32.powf(-db / 10.0))
+            .sum::<f32>()
+            / self.carrier_snr_db.len() as f32;
+        -10.0 * inverse.log10()
+    }
+
+    /// Lowest subcarrier SNR, in dB.
+    pub fn worst_carrier_snr_db(&self) -> f32 {
+        self.carrier_snr_db
+            .iter()
+            .copied()
+            .fold(MAX_SNR_DB, f32::min)
+    }
+}
+
+/// RMS error of `received` against `reference`, relative to the reference RMS, in dB.
+pub(crate) fn evm_db(received: &[Complex<f32>], reference: &[Complex<f32>]) -> f32 {
+    let error: f32 = received
+        .iter()
+        .zip(reference)
+        .map(|(r, x)| (r - x).norm_sqr())
+        .sum();
+    let power: f32 = reference.iter().map(|x| x.norm_sqr()).sum();
+    (10.0 * (error / power.max(f32::MIN_POSITIVE)).log10()).max(-MAX_SNR_DB)
+}
+
+fn snr_db(signal: f32, noise: f32) -> f32 {
+    if noise <= 0.0 {
+        return MAX_SNR_DB;
+    }
+    (10.0 * (signal / noise).log10()).min(MAX_SNR_DB)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Frame, Header, Mode, PilotPattern};
+    use rustfft::num_complex::Complex;
+
+    #[test]
+    fn reports_snr_evm_and_corrections() {
+        let pattern = PilotPattern::default();
+        let sent = Frame {
+            header: Header {
+                mode: Mode::ALL[1],
+                destination: "CQ".into(),
+                source: "N0CALL".into(),
+                seq: 7,
+                flags: 0,
+            },
+            payload: (0..300).map(|i| (i * 11) as u8).collect(),
+        };
+        let snr_db = 12.0f32;
+        let var = 10f32.powf(-snr_db / 10.0);
+        let mut state = 0x2468_ace1u32;
+        let mut uniform = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state as f32 / u32::MAX as f32).max(1e-9)
+        };
+        // Subcarriers 60..70 sit in a 10 dB fade.
+        let symbols: Vec<Vec<Complex<f32>>> = sent
+            .encode(&pattern)
+            .expect("encode")
+            .into_iter()
+            .map(|symbol| {
+                symbol
+                    .into_iter()
+                    .enumerate()
+                    .map(|(c, x)| {
+                        let gain = if (60..70).contains(&c) { 0.316 } else { 1.0 };
+                        let r = (-var * uniform().ln()).sqrt();
+                        let phi = std::f32::consts::TAU * uniform();
+                        x * gain + Complex::from_polar(r, phi)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let (frame, quality) = Frame::decode_with_quality(&symbols, &pattern).expect("decode");
+        assert_eq!(frame, sent);
+        assert!((quality.snr_db - 11.6).abs() < 1.0, "{}", quality.snr_db);
+        assert!((quality.carrier_snr_db[20] - snr_db).abs() < 2.0);
+        assert!((quality.carrier_snr_db[65] - (snr_db - 10.0)).abs() < 2.0);
+        assert!(quality.worst_carrier_snr_db() < 4.0);
+        // ZF on the faded subcarriers pulls the payload EVM above -SNR.
+        assert!(
+            quality.evm_db > -snr_db && quality.evm_db < -6.0,
+            "{}",
+            quality.evm_db
+        );
+        assert!(quality.corrected_bits > 0);
+        assert!(
+            quality.raw_ber() > 0.001 && quality.raw_ber() < 0.1,
+            "{}",
+            quality.raw_ber()
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Frame receive chain.
+//!
+//! [`FrameReceiver`] takes the samples from a preamble found by
+//! [`crate::PreambleDetector`] and demodulates the symbols that follow. A
+//! [`Resampler`] in front of the demodulator removes the sample clock offset measured
+//! by a [`ClockTracker`], and a [`FrequencyTracker`] removes the carrier offset,
+//! starting from the preamble estimate. The clock estimate carries over from one frame
+//! to the next.
+
+use crate::afc::FrequencyTracker;
+use crate::config::OfdmConfig;
+use crate::frame::Frame;
+use crate::ofdm::OfdmDemodulator;
+use crate::resample::Resampler;
+use crate::sfo::ClockTracker;
+use crate::sync::{SyncResult, PREAMBLE_SYMBOLS};
+use rustfft::num_complex::Complex;
+
+/// Demodulates frames with frequency and clock offset tracking.
+pub struct FrameReceiver {
+    config: OfdmConfig,
+    demod: OfdmDemodulator,
+    afc: FrequencyTracker,
+    clock: ClockTracker,
+}
+
+impl Default for FrameReceiver {
+    fn default() -> Self {
+        Self::from_config(&OfdmConfig::default())
+    }
+}
+
+impl FrameReceiver {
+    /// Create a receiver for `config` with default trackers.
+    pub fn from_config(config: &OfdmConfig) -> Self {
+        Self {
+            config: *config,
+            demod: OfdmDemodulator::from_config(config),
+            afc: FrequencyTracker::from_config(config),
+            clock: ClockTracker::from_config(config),
+        }
+    }
+
+    /// Use `afc` for the carrier frequency offset.
+    pub fn with_frequency_tracker(mut self, afc: FrequencyTracker) -> Self {
+        self.afc = afc;
+        self
+    }
+
+    /// Use `clock` for the sample clock offset.
+    pub fn with_clock_tracker(mut self, clock: ClockTracker) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The frequency tracker, holding the offset at the end of the last frame.
+    pub fn frequency_tracker(&self) -> &FrequencyTracker {
+        &self.afc
+    }
+
+    /// The clock tracker, holding the offset estimated so far.
+    pub fn clock_tracker(&self) -> &ClockTracker {
+        &self.clock
+    }
+
+    /// Demodulate `count` symbols after the preamble. `samples` starts at
+    /// `sync.start`, the first sample of the preamble.
+    pub fn demodulate(
+        &mut self,
+        sync: &SyncResult,
+        samples: &[Complex<f32>],
+        count: usize,
+    ) -> Result<Vec<Vec<Complex<f32>>>, String> {
+        self.run(sync, samples, |_| Ok(count))
+    }
+
+    /// Demodulate and decode the frame after the preamble, reading its length from the
+    /// header. `samples` starts at `sync.start`.
+    pub fn receive_frame(
+        &mut self,
+        sync: &SyncResult,
+        samples: &[Complex<f32>],
+    ) -> Result<Frame, String> {
+        let pattern = self.config.pilot_pattern();
+        let header_symbols = Frame::header_symbols(&pattern);
+        let mut total = None;
+        let symbols = self.run(sync, samples, |symbols| {
+            if symbols.len() < header_symbols {
+                return Ok(header_symbols);
+            }
+            if total.is_none() {
+                let (header, len) = Frame::decode_header(symbols, &pattern)?;
+                total = Some(Frame::symbol_count(header.mode, len, &pattern));
+            }
+            Ok(total.unwrap_or(header_symbols))
+        })?;
+        Frame::decode(&symbols, &pattern)
+    }
+
+    /// Demodulate symbols until `needed`, given those so far, asks for no more.
+    fn run(
+        &mut self,
+        sync: &SyncResult,
+        samples: &[Complex<f32>],
+        mut needed: impl FnMut(&[Vec<Complex<f32>>]) -> Result<usize, String>,
+    ) -> Result<Vec<Vec<Complex<f32>>>, String> {
+        let len = self.config.symbol_len();
+        self.afc
+            .start(sync.cfo_hz(self.config.sample_rate_hz, self.config.nfft));
+        self.clock.start();
+        let mut corrector = Resampler::new(1.0, 1.0)?;
+        corrector.set_step(1.0 + self.clock.ppm() as f64 * 1e-6);
+        // Zeros after the input flush the resampler's filter for the last symbol.
+        let flush = vec![Complex::new(0.0, 0.0); corrector.latency() + 1];
+        let mut input = samples.chunks(len).chain(std::iter::once(&flush[..]));
+        let mut corrected = Vec::new();
+        let mut symbols = Vec::new();
+        while symbols.len() < needed(&symbols)? {
+            let start = (symbols.len() + PREAMBLE_SYMBOLS) * len;
+            if corrected.len() < start + len {
+                let chunk = input
+                    .next()
+                    .ok_or_else(|| format!("samples ended after {} symbols", symbols.len()))?;
+                corrector.process(chunk, &mut corrected);
+                continue;
+            }
+            let mut symbol = corrected[start..start + len].to_vec();
+            self.afc.correct(&mut symbol)?;
+            let out = self.demod.demodulate(&symbol)?;
+            self.afc.observe(&out)?;
+            self.clock.observe(&out)?;
+            corrector.set_step(1.0 + self.clock.ppm() as f64 * 1e-6);
+            symbols.push(out);
+        }
+        Ok(symbols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Header, Mode, OfdmModulator, PilotPattern, PreambleDetector};
+    use std::f64::consts::TAU;
+
+    fn frame(seq: u16, len: usize) -> Frame {
+        Frame {
+            header: Header {
+                mode: Mode::ALL[2],
+                destination: "CQ".into(),
+                source: "N0CALL".into(),
+                seq,
+                flags: 0,
+            },
+            payload: (0..len).map(|i| (i * 31 + seq as usize) as u8).collect(),
+        }
+    }
+
+    /// `frames` back to back with gaps, received `offset_hz` high on a clock `ppm`
+    /// fast.
+    fn channel(frames: &[Frame], offset_hz: f64, ppm: f64) -> Vec<Complex<f32>> {
+        let pattern = PilotPattern::default();
+        let mut stream = vec![Complex::new(0.0, 0.0); 1000];
+        for frame in frames {
+            let symbols = frame.encode(&pattern).expect("encode");
+            stream.extend(
+                OfdmModulator::new()
+                    .modulate_frame(&symbols)
+                    .expect("modulate"),
+            );
+            stream.extend(vec![Complex::new(0.0, 0.0); 6000]);
+        }
+        for (n, s) in stream.iter_mut().enumerate() {
+            *s *= Complex::from_polar(1.0, (TAU * offset_hz * n as f64 / 48_000.0) as f32);
+        }
+        let mut clock = Resampler::new(1.0, (1.0 + ppm * 1e-6) as f32).expect("rates");
+        let mut received = Vec::new();
+        clock.process(&stream, &mut received);
+        received
+    }
+
+    #[test]
+    fn receives_frames_with_frequency_and_clock_offset() {
+        let sent = [frame(1, 1200), frame(2, 1200)];
+        let stream = channel(&sent, 30.0, -300.0);
+        let syncs = PreambleDetector::new().push(&stream);
+        assert_eq!(syncs.len(), 2);
+        let mut receiver = FrameReceiver::default();
+        for (sync, frame) in syncs.iter().zip(&sent) {
+            let received = receiver
+                .receive_frame(sync, &stream[sync.start as usize..])
+                .expect("receive");
+            assert_eq!(&received, frame);
+            let offset = receiver.frequency_tracker().offset_hz();
+            assert!((offset - 30.0).abs() < 1.0, "{}", offset);
+        }
+        let ppm = receiver.clock_tracker().ppm();
+        assert!((ppm + 300.0).abs() < 15.0, "{}", ppm);
+    }
+
+    #[test]
+    fn reports_samples_running_out() {
+        let sent = frame(3, 200);
+        let count = Frame::symbol_count(sent.header.mode, 200, &PilotPattern::default());
+        let stream = channel(std::slice::from_ref(&sent), 0.0, 0.0);
+        let sync = PreambleDetector::new().push(&stream)[0];
+        let start = sync.start as usize;
+        let end = start + (count + PREAMBLE_SYMBOLS - 1) * 2304;
+        let mut receiver = FrameReceiver::default();
+        let symbols = receiver
+            .demodulate(&sync, &stream[start..], count)
+            .expect("demodulate");
+        assert_eq!(Frame::decode(&symbols, &PilotPattern::default()), Ok(sent));
+        let err = receiver
+            .demodulate(&sync, &stream[start..end], count)
+            .expect_err("short");
+        assert!(err.contains("ended"), "{}", err);
+    }
+}
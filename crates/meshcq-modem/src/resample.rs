@@ -0,0 +1,149 @@
+//! Streaming fractional resampler.
+//!
+//! A Blackman-windowed sinc kernel is tabulated once and linearly interpolated for
+//! each output position, so the ratio can be any real number and can change while
+//! streaming (for tracking a drifting sample clock).
+
+use std::ops::{Add, Mul};
+
+const HALF_WIDTH: f32 = 16.0;
+const PHASES: usize = 512;
+const PASSBAND_FRACTION: f32 = 0.9;
+
+/// Resamples a stream of `T` (real or complex samples).
+pub struct Resampler<T> {
+    step: f64,
+    half: usize,
+    table: Vec<f32>,
+    buffer: Vec<T>,
+    pos: f64,
+}
+
+impl<T> Resampler<T>
+where
+    T: Copy + Default + Add<Output = T> + Mul<f32, Output = T>,
+{
+    /// Create a resampler from `input_rate_hz` to `output_rate_hz`.
+    pub fn new(input_rate_hz: f32, output_rate_hz: f32) -> Result<Self, String> {
+        if !(input_rate_hz > 0.0 && output_rate_hz > 0.0) {
+            return Err(format!(
+                "invalid resampling rates {} Hz -> {} Hz",
+                input_rate_hz, output_rate_hz
+            ));
+        }
+        // Downsampling narrows the kernel's passband; widen the kernel to match.
+        let cutoff = PASSBAND_FRACTION * (output_rate_hz / input_rate_hz).min(1.0);
+        let half = (HALF_WIDTH / cutoff).ceil() as usize;
+        let table = (0..=2 * half * PHASES)
+            .map(|i| {
+                let x = i as f32 / PHASES as f32 - half as f32;
+                let u = cutoff * x;
+                let sinc = if u == 0.0 {
+                    1.0
+                } else {
+                    (std::f32::consts::PI * u).sin() / (std::f32::consts::PI * u)
+                };
+                let w = std::f32::consts::PI * (x / half as f32 + 1.0);
+                cutoff * sinc * (0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos())
+            })
+            .collect();
+        Ok(Self {
+            step: input_rate_hz as f64 / output_rate_hz as f64,
+            half,
+            table,
+            buffer: vec![T::default(); half],
+            pos: half as f64,
+        })
+    }
+
+    /// Input samples consumed per output sample.
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    /// Change the ratio without disturbing the stream, e.g. `1.0 + ppm * 1e-6` to
+    /// remove a sample clock offset.
+    pub fn set_step(&mut self, step: f64) {
+        self.step = step;
+    }
+
+    /// Input samples that must arrive before the output catches up with them.
+    ///
+    /// Output sample `n` corresponds exactly to input position `n * step`; this is only
+    /// how far the output lags in processing, not a time shift.
+    pub fn latency(&self) -> usize {
+        self.half
+    }
+
+    /// Clear all history.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.buffer.resize(self.half, T::default());
+        self.pos = self.half as f64;
+    }
+
+    /// Resample `input`, appending to `out`.
+    pub fn process(&mut self, input: &[T], out: &mut Vec<T>) {
+        self.buffer.extend_from_slice(input);
+        let half = self.half as isize;
+        while (self.pos.floor() as usize) + self.half < self.buffer.len() {
+            let base = self.pos.floor();
+            let frac = (self.pos - base) as f32;
+            let base = base as isize;
+            let mut acc = T::default();
+            for j in (1 - half)..=half {
+                acc = acc + self.buffer[(base + j) as usize] * self.kernel(j as f32 - frac);
+            }
+            out.push(acc);
+            self.pos += self.step;
+        }
+        let keep_from = (self.pos.floor() as usize + 1)
+            .saturating_sub(self.half)
+            .min(self.buffer.len());
+        self.buffer.drain(..keep_from);
+        self.pos -= keep_from as f64;
+    }
+
+    fn kernel(&self, x: f32) -> f32 {
+        let t = (x + self.half as f32) * PHASES as f32;
+        let i = t.floor();
+        let Some(&a) = self.table.get(i as usize) else {
+            return 0.0;
+        };
+        let b = self.table.get(i as usize + 1).copied().unwrap_or(0.0);
+        a + (b - a) * (t - i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_a_tone_between_rates() {
+        for &(from, to) in &[(44_100.0f32, 48_000.0f32), (48_000.0, 16_000.0)] {
+            let tone = |n: usize, fs: f32| (std::f32::consts::TAU * 1000.0 * n as f32 / fs).sin();
+            let input: Vec<f32> = (0..from as usize / 2).map(|n| tone(n, from)).collect();
+            let mut resampler = Resampler::new(from, to).expect("rates");
+            let mut out = Vec::new();
+            // Odd chunk sizes exercise the buffer bookkeeping.
+            for chunk in input.chunks(333) {
+                resampler.process(chunk, &mut out);
+            }
+            let expected = ((input.len() - resampler.latency()) as f64 / resampler.step()) as usize;
+            assert!(
+                out.len().abs_diff(expected) <= 2,
+                "{} vs {}",
+                out.len(),
+                expected
+            );
+            let skip = 2 * resampler.latency();
+            let err = out[skip..out.len() - skip]
+                .iter()
+                .enumerate()
+                .map(|(n, &y)| (y - tone(n + skip, to)).abs())
+                .fold(0.0f32, f32::max);
+            assert!(err < 1e-3, "{} -> {}: {}", from, to, err);
+        }
+    }
+}
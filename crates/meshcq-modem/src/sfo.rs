@@ -0,0 +1,189 @@
+//! Sample clock offset estimation.
+//!
+//! When the transmitter's and receiver's sound cards run at slightly different rates,
+//! each symbol lands a little earlier or later than the last. A timing shift of `d`
+//! samples rotates bin `k` by `-2πkd/N`, so the phase advance of each scattered pilot
+//! since it was last sent on the same subcarrier grows linearly with frequency. The
+//! slope gives the clock offset; the common part is carrier offset and is ignored.
+//!
+//! [`ClockTracker`] is meant to run in a loop with a [`crate::Resampler`] in front of
+//! the demodulator: it measures what the resampler has not yet removed and accumulates
+//! the correction.
+
+use crate::afc::coherence;
+use crate::config::OfdmConfig;
+use crate::pilots::PilotPattern;
+use rustfft::num_complex::Complex;
+use std::collections::VecDeque;
+use std::f64::consts::TAU;
+
+const DEFAULT_GAIN: f32 = 0.05;
+
+/// Sample clock offset estimator for the symbols of one frame.
+pub struct ClockTracker {
+    nfft: usize,
+    symbol_len: usize,
+    pattern: PilotPattern,
+    gain: f32,
+    ppm: f64,
+    symbol: usize,
+    history: VecDeque<Vec<Complex<f32>>>,
+}
+
+impl Default for ClockTracker {
+    fn default() -> Self {
+        Self::from_config(&OfdmConfig::default())
+    }
+}
+
+impl ClockTracker {
+    /// Create a tracker for `config`.
+    pub fn from_config(config: &OfdmConfig) -> Self {
+        Self {
+            nfft: config.nfft,
+            symbol_len: config.symbol_len(),
+            pattern: config.pilot_pattern(),
+            gain: DEFAULT_GAIN,
+            ppm: 0.0,
+            symbol: 0,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Set the loop gain (default 0.05).
+    pub fn with_gain(mut self, gain: f32) -> Self {
+        self.gain = gain.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Start a new frame. The estimate is kept: the clocks do not change between
+    /// frames. The next symbol passed to [`Self::observe`] is frame symbol 0.
+    pub fn start(&mut self) {
+        self.symbol = 0;
+        self.history.clear();
+    }
+
+    /// Accumulated clock offset in parts per million. Positive means the receiver's
+    /// clock runs fast, so a resampler step of `1.0 + ppm * 1e-6` removes it.
+    pub fn ppm(&self) -> f32 {
+        self.ppm as f32
+    }
+
+    /// Update the estimate from the pilots of the next demodulated symbol.
+    pub fn observe(&mut self, subcarriers: &[Complex<f32>]) -> Result<(), String> {
+        if subcarriers.len() != self.pattern.carriers() {
+            return Err(format!(
+                "expected {} subcarriers, got {}",
+                self.pattern.carriers(),
+                subcarriers.len()
+            ));
+        }
+        let period = self.pattern.period();
+        if self.history.len() == period {
+            let old = &self.history[0];
+            let advance: Vec<Complex<f64>> = (0..subcarriers.len())
+                .filter(|&c| self.pattern.is_pilot(self.symbol, c))
+                .map(|c| {
+                    let p = subcarriers[c] * old[c].conj();
+                    Complex::new(p.re as f64, p.im as f64)
+                })
+                .collect();
+            // Correlating neighbouring pilots removes the common phase and avoids
+            // unwrapping across the band.
+            let slope: Complex<f64> = advance.windows(2).map(|w| w[1] * w[0].conj()).sum();
+            let energy: f64 = advance.windows(2).map(|w| w[1].norm() * w[0].norm()).sum();
+            if energy > 0.0 {
+                let per_bin = slope.arg() / self.pattern.spacing() as f64;
+                let shift = -per_bin * self.nfft as f64 / TAU;
+                let residual = shift / (period * self.symbol_len) as f64 * 1e6;
+                self.ppm += self.gain as f64 * coherence(slope, energy) * residual;
+            }
+            self.history.pop_front();
+        }
+        self.history.push_back(subcarriers.to_vec());
+        self.symbol += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Frame, Header, Mode, OfdmDemodulator, OfdmModulator, PreambleDetector, Resampler,
+        PREAMBLE_SYMBOLS,
+    };
+
+    const PPM: f64 = -1000.0;
+
+    fn long_frame() -> (Frame, Vec<Complex<f32>>, usize) {
+        let frame = Frame {
+            header: Header {
+                mode: Mode::ALL[1],
+                destination: "CQ".into(),
+                source: "N0CALL".into(),
+                seq: 3,
+                flags: 0,
+            },
+            payload: (0..3000).map(|i| (i * 29 + i / 3) as u8).collect(),
+        };
+        let symbols = frame.encode(&PilotPattern::default()).expect("encode");
+        let mut stream = vec![Complex::new(0.0, 0.0); 1200];
+        stream.extend(
+            OfdmModulator::new()
+                .modulate_frame(&symbols)
+                .expect("modulate"),
+        );
+        stream.extend(vec![Complex::new(0.0, 0.0); 4 * 2304]);
+        // The receiver's clock takes (1 + ppm) samples per transmitted sample.
+        let mut clock = Resampler::new(1.0, (1.0 + PPM * 1e-6) as f32).expect("rates");
+        let mut received = Vec::new();
+        clock.process(&stream, &mut received);
+        (frame, received, symbols.len())
+    }
+
+    fn receive(
+        stream: &[Complex<f32>],
+        count: usize,
+        tracker: &mut ClockTracker,
+    ) -> Vec<Vec<Complex<f32>>> {
+        let sync = PreambleDetector::new().push(stream);
+        assert_eq!(sync.len(), 1);
+        let mut corrector = Resampler::new(1.0, 1.0).expect("rates");
+        let demod = OfdmDemodulator::new();
+        let mut corrected = Vec::new();
+        let mut symbols = Vec::new();
+        let mut input = stream[sync[0].start as usize..].chunks(2304);
+        while symbols.len() < count {
+            let start = (symbols.len() + PREAMBLE_SYMBOLS) * 2304;
+            if corrected.len() < start + 2304 {
+                corrector.process(input.next().expect("stream ended"), &mut corrected);
+                continue;
+            }
+            let out = demod
+                .demodulate(&corrected[start..start + 2304])
+                .expect("demodulate");
+            tracker.observe(&out).expect("observe");
+            corrector.set_step(1.0 + tracker.ppm() as f64 * 1e-6);
+            symbols.push(out);
+        }
+        symbols
+    }
+
+    #[test]
+    fn corrects_clock_offset_over_long_frame() {
+        let (frame, stream, count) = long_frame();
+        let pattern = PilotPattern::default();
+
+        // Without correction the symbols slide out of their window partway through.
+        let mut untracked = ClockTracker::default().with_gain(0.0);
+        let received = receive(&stream, count, &mut untracked);
+        assert!(Frame::decode(&received, &pattern).is_err());
+
+        let mut tracker = ClockTracker::default();
+        let received = receive(&stream, count, &mut tracker);
+        assert_eq!(Frame::decode(&received, &pattern).expect("decode"), frame);
+        let ppm = tracker.ppm() as f64;
+        assert!((ppm - PPM).abs() < 10.0, "{}", ppm);
+    }
+}
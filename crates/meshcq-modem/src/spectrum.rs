@@ -0,0 +1,123 @@
+//! Spectrum measurements for transmit audio.
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+const FFT_LEN: usize = 4096;
+const OCCUPIED_FRACTION: f32 = 0.99;
+
+/// Occupied bandwidth and adjacent-channel power of an audio signal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectrumReport {
+    /// Lower edge of the band holding 99% of the power, in Hz.
+    pub occupied_low_hz: f32,
+    /// Upper edge of the band holding 99% of the power, in Hz.
+    pub occupied_high_hz: f32,
+    /// Power in the channel-width band just below the channel, relative to the
+    /// in-channel power, in dB.
+    pub acpr_lower_db: f32,
+    /// Power in the channel-width band just above the channel, relative to the
+    /// in-channel power, in dB.
+    pub acpr_upper_db: f32,
+    /// As `acpr_lower_db` for the next channel-width band further down.
+    pub alternate_lower_db: f32,
+    /// As `acpr_upper_db` for the next channel-width band further up.
+    pub alternate_upper_db: f32,
+}
+
+impl SpectrumReport {
+    /// Width of the 99% power band in Hz.
+    pub fn occupied_bandwidth_hz(&self) -> f32 {
+        self.occupied_high_hz - self.occupied_low_hz
+    }
+}
+
+/// Measure real audio against the channel `low_hz..high_hz`.
+///
+/// The power spectrum is a Welch average of Hann-windowed 4096-point FFTs with 50%
+/// overlap. Adjacent and alternate channels are cut off at DC and Nyquist; a channel
+/// that lies entirely outside that range reports the floor value.
+pub fn measure(
+    audio: &[f32],
+    sample_rate_hz: f32,
+    low_hz: f32,
+    high_hz: f32,
+) -> Result<SpectrumReport, String> {
+    if audio.len() < FFT_LEN {
+        return Err(format!(
+            "need at least {} samples, got {}",
+            FFT_LEN,
+            audio.len()
+        ));
+    }
+    if !(0.0..high_hz).contains(&low_hz) || high_hz > sample_rate_hz / 2.0 {
+        return Err(format!("invalid channel {}-{} Hz", low_hz, high_hz));
+    }
+
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(FFT_LEN);
+    let window: Vec<f32> = (0..FFT_LEN)
+        .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / FFT_LEN as f32).cos())
+        .collect();
+    let mut psd = vec![0.0f32; FFT_LEN / 2 + 1];
+    let mut buf = vec![Complex::new(0.0, 0.0); FFT_LEN];
+    let mut start = 0;
+    while start + FFT_LEN <= audio.len() {
+        for ((b, &x), &w) in buf.iter_mut().zip(&audio[start..]).zip(&window) {
+            *b = Complex::new(x * w, 0.0);
+        }
+        fft.process(&mut buf);
+        for (p, b) in psd.iter_mut().zip(&buf) {
+            *p += b.norm_sqr();
+        }
+        start += FFT_LEN / 2;
+    }
+
+    let bin_hz = sample_rate_hz / FFT_LEN as f32;
+    let band_power = |lo: f32, hi: f32| -> f32 {
+        psd.iter()
+            .enumerate()
+            .filter(|&(k, _)| {
+                let f = k as f32 * bin_hz;
+                f >= lo && f < hi
+            })
+            .map(|(_, &p)| p)
+            .sum()
+    };
+
+    let total: f32 = psd.iter().sum();
+    let tail = total * (1.0 - OCCUPIED_FRACTION) / 2.0;
+    let mut acc = 0.0;
+    let mut low_bin = 0;
+    for (k, &p) in psd.iter().enumerate() {
+        acc += p;
+        if acc >= tail {
+            low_bin = k;
+            break;
+        }
+    }
+    acc = 0.0;
+    let mut high_bin = psd.len() - 1;
+    for (k, &p) in psd.iter().enumerate().rev() {
+        acc += p;
+        if acc >= tail {
+            high_bin = k;
+            break;
+        }
+    }
+
+    let width = high_hz - low_hz;
+    let in_channel = band_power(low_hz, high_hz).max(f32::MIN_POSITIVE);
+    let nyquist = sample_rate_hz / 2.0;
+    let db = |lo: f32, hi: f32| {
+        let p = band_power(lo.max(0.0), hi.min(nyquist));
+        10.0 * (p.max(f32::MIN_POSITIVE) / in_channel).log10()
+    };
+    Ok(SpectrumReport {
+        occupied_low_hz: low_bin as f32 * bin_hz,
+        occupied_high_hz: high_bin as f32 * bin_hz,
+        acpr_lower_db: db(low_hz - width, low_hz),
+        acpr_upper_db: db(high_hz, high_hz + width),
+        alternate_lower_db: db(low_hz - 2.0 * width, low_hz - width),
+        alternate_upper_db: db(high_hz + width, high_hz + 2.0 * width),
+    })
+}
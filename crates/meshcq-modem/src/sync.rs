@@ -0,0 +1,523 @@
+//! Frame synchronisation with a Schmidl-Cox preamble.
+//!
+//! The preamble is two OFDM symbols. The first only uses even FFT bins, so its useful
+//! part is two identical halves; the receiver finds it by correlating the stream against
+//! itself half a symbol later, which gives frame timing and the fractional carrier
+//! frequency offset. The second symbol carries a known sequence on every subcarrier and
+//! resolves the integer part of the offset.
+
+use crate::config::OfdmConfig;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::sync::Arc;
+
+/// Symbols of preamble ahead of every frame.
+pub const PREAMBLE_SYMBOLS: usize = 2;
+
+const DEFAULT_THRESHOLD: f32 = 0.5;
+const DEFAULT_MAX_INT_OFFSET: i32 = 8;
+const PEAK_FRACTION: f32 = 0.9;
+const RESYNC_INTERVAL: usize = 4096;
+const MIN_ENERGY: f64 = 1e-20;
+const MIN_SEQUENCE_SCORE: f32 = 0.4;
+
+/// Known two-symbol preamble in the subcarrier domain.
+pub struct Preamble {
+    first_bin: usize,
+    symbols: [Vec<Complex<f32>>; PREAMBLE_SYMBOLS],
+}
+
+impl Default for Preamble {
+    fn default() -> Self {
+        Self::new(1, 104)
+    }
+}
+
+impl Preamble {
+    /// Build the preamble for `active_bins` subcarriers mapped to consecutive bins
+    /// starting at `first_bin`.
+    pub fn new(first_bin: usize, active_bins: usize) -> Self {
+        let pn1 = pn_sequence(active_bins, 0x5b);
+        let pn2 = pn_sequence(active_bins, 0x27);
+        // Subcarrier i sits on FFT bin i + first_bin; only the even bins are used. They
+        // are boosted by sqrt(2) to keep the symbol energy equal to a fully loaded one.
+        let boost = std::f32::consts::SQRT_2;
+        let first = (0..active_bins)
+            .map(|i| {
+                if (i + first_bin).is_multiple_of(2) {
+                    Complex::new(pn1[i] * boost, 0.0)
+                } else {
+                    Complex::new(0.0, 0.0)
+                }
+            })
+            .collect();
+        let second = pn2.iter().map(|&v| Complex::new(v, 0.0)).collect();
+        Self {
+            first_bin,
+            symbols: [first, second],
+        }
+    }
+
+    /// Subcarrier symbols for each preamble symbol, ready for `OfdmModulator::modulate`.
+    pub fn symbols(&self) -> &[Vec<Complex<f32>>; PREAMBLE_SYMBOLS] {
+        &self.symbols
+    }
+}
+
+/// Result of a preamble detection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncResult {
+    /// Absolute stream index of the first preamble sample (start of its cyclic prefix).
+    pub start: u64,
+    /// Carrier frequency offset in subcarrier spacings (fractional plus integer part).
+    pub cfo_bins: f32,
+    /// Peak timing metric (0.0 - 1.0).
+    pub metric: f32,
+}
+
+impl SyncResult {
+    /// Carrier frequency offset in Hz.
+    pub fn cfo_hz(&self, sample_rate_hz: f32, nfft: usize) -> f32 {
+        self.cfo_bins * sample_rate_hz / nfft as f32
+    }
+}
+
+struct Plateau {
+    metrics: Vec<(u64, f32, Complex<f64>)>,
+    /// Ran longer than any preamble can; ignored until the metric drops.
+    too_long: bool,
+}
+
+struct Pending {
+    start: u64,
+    frac_bins: f32,
+    metric: f32,
+}
+
+/// Streaming Schmidl-Cox preamble detector.
+pub struct PreambleDetector {
+    nfft: usize,
+    cp_len: usize,
+    threshold: f32,
+    max_int_offset: i32,
+    preamble: Preamble,
+    fft: Arc<dyn Fft<f32>>,
+    buffer: Vec<Complex<f32>>,
+    buffer_start: u64,
+    next_d: u64,
+    sums: Option<(Complex<f64>, f64)>,
+    steps_since_resync: usize,
+    plateau: Option<Plateau>,
+    pending: Vec<Pending>,
+}
+
+impl Default for PreambleDetector {
+    fn default() -> Self {
+        Self::from_config(&OfdmConfig::default())
+    }
+}
+
+impl PreambleDetector {
+    /// Create a detector for the default numerology.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a detector for `config`.
+    pub fn from_config(config: &OfdmConfig) -> Self {
+        Self {
+            nfft: config.nfft,
+            cp_len: config.cp_len,
+            threshold: DEFAULT_THRESHOLD,
+            max_int_offset: DEFAULT_MAX_INT_OFFSET,
+            preamble: Preamble::new(config.first_bin, config.carriers),
+            fft: FftPlanner::<f32>::new().plan_fft_forward(config.nfft),
+            buffer: Vec::new(),
+            buffer_start: 0,
+            next_d: 0,
+            sums: None,
+            steps_since_resync: 0,
+            plateau: None,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Set the timing metric threshold (default 0.5).
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set the integer offset search range, in steps of two subcarriers (default 8).
+    pub fn with_max_int_offset(mut self, steps: i32) -> Self {
+        self.max_int_offset = steps.max(0);
+        self
+    }
+
+    /// Clear all history. The next pushed sample is stream index 0.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.buffer_start = 0;
+        self.next_d = 0;
+        self.sums = None;
+        self.steps_since_resync = 0;
+        self.plateau = None;
+        self.pending.clear();
+    }
+
+    /// Feed the next block of the stream and return completed detections.
+    pub fn push(&mut self, samples: &[Complex<f32>]) -> Vec<SyncResult> {
+        self.buffer.extend_from_slice(samples);
+        let half = (self.nfft / 2) as u64;
+        let end = self.buffer_start + self.buffer.len() as u64;
+
+        while self.next_d + 2 * half <= end {
+            let d = self.next_d;
+            let (p, r) = self.metric_sums(d);
+            let metric = if r > MIN_ENERGY {
+                (p.norm_sqr() / (r * r)) as f32
+            } else {
+                0.0
+            };
+
+            if metric >= self.threshold {
+                // A preamble holds the metric up for its prefix plus at most a
+                // half-symbol window either side. Anything longer is a steady tone.
+                let max_len = self.cp_len + self.nfft;
+                let plateau = self.plateau.get_or_insert(Plateau {
+                    metrics: Vec::new(),
+                    too_long: false,
+                });
+                if !plateau.too_long {
+                    plateau.metrics.push((d, metric, p));
+                    if plateau.metrics.len() > max_len {
+                        plateau.metrics = Vec::new();
+                        plateau.too_long = true;
+                    }
+                }
+            } else if let Some(plateau) = self.plateau.take() {
+                if !plateau.too_long {
+                    self.pending.push(self.locate(&plateau));
+                }
+            }
+            self.next_d += 1;
+        }
+
+        let mut results = Vec::new();
+        let symbol_len = (self.nfft + self.cp_len) as u64;
+        let mut i = 0;
+        while i < self.pending.len() {
+            if self.pending[i].start + 2 * symbol_len <= end {
+                let pending = self.pending.remove(i);
+                results.extend(self.resolve(&pending));
+            } else {
+                i += 1;
+            }
+        }
+
+        self.trim();
+        results
+    }
+
+    /// Running correlation P(d) and energy R(d) over half a symbol.
+    ///
+    /// R averages the energy of both halves, which bounds the metric by 1 even where
+    /// one half is signal and the other is near-silence.
+    fn metric_sums(&mut self, d: u64) -> (Complex<f64>, f64) {
+        let half = self.nfft / 2;
+        let at = |buffer: &[Complex<f32>], idx: u64, start: u64| -> Complex<f64> {
+            let s = buffer[(idx - start) as usize];
+            Complex::new(s.re as f64, s.im as f64)
+        };
+        let sums = match self.sums {
+            Some((p, r)) if self.steps_since_resync < RESYNC_INTERVAL => {
+                // Slide the window by one sample.
+                let prev = d - 1;
+                let old_a = at(&self.buffer, prev, self.buffer_start);
+                let old_b = at(&self.buffer, prev + half as u64, self.buffer_start);
+                let new_a = old_b;
+                let new_b = at(&self.buffer, prev + 2 * half as u64, self.buffer_start);
+                self.steps_since_resync += 1;
+                (
+                    p + new_a.conj() * new_b - old_a.conj() * old_b,
+                    r + 0.5 * (new_b.norm_sqr() - old_a.norm_sqr()),
+                )
+            }
+            _ => {
+                let mut p = Complex::new(0.0, 0.0);
+                let mut r = 0.0;
+                for m in 0..half as u64 {
+                    let a = at(&self.buffer, d + m, self.buffer_start);
+                    let b = at(&self.buffer, d + m + half as u64, self.buffer_start);
+                    p += a.conj() * b;
+                    r += 0.5 * (a.norm_sqr() + b.norm_sqr());
+                }
+                self.steps_since_resync = 0;
+                (p, r)
+            }
+        };
+        self.sums = Some(sums);
+        sums
+    }
+
+    /// Estimate frame start and fractional offset from a plateau of the timing metric.
+    fn locate(&self, plateau: &Plateau) -> Pending {
+        let peak = plateau
+            .metrics
+            .iter()
+            .fold(0.0f32, |max, &(_, m, _)| max.max(m));
+        let level = peak * PEAK_FRACTION;
+        let first = plateau
+            .metrics
+            .iter()
+            .find(|&&(_, m, _)| m >= level)
+            .map(|&(d, _, _)| d)
+            .unwrap_or(plateau.metrics[0].0);
+        let last = plateau
+            .metrics
+            .iter()
+            .rev()
+            .find(|&&(_, m, _)| m >= level)
+            .map(|&(d, _, _)| d)
+            .unwrap_or(first);
+        // The metric is flat while the correlation window slides through the cyclic
+        // prefix, so the middle of the plateau is half a prefix after the frame start.
+        let mid = first + (last - first) / 2;
+        let p = plateau
+            .metrics
+            .iter()
+            .find(|&&(d, _, _)| d == mid)
+            .map(|&(_, _, p)| p)
+            .unwrap_or_default();
+        Pending {
+            start: mid.saturating_sub(self.cp_len as u64 / 2),
+            frac_bins: (p.im.atan2(p.re) / std::f64::consts::PI) as f32,
+            metric: peak,
+        }
+    }
+
+    /// Resolve the integer frequency offset from both preamble symbols.
+    ///
+    /// Any steady tone also has repeated halves, so the second symbol must carry the
+    /// known sequence for the detection to count.
+    fn resolve(&self, pending: &Pending) -> Option<SyncResult> {
+        let n = self.nfft;
+        let symbol_len = n + self.cp_len;
+        let offset = (pending.start - self.buffer_start) as usize;
+
+        let mut spectra = [Vec::new(), Vec::new()];
+        for (sym, spectrum) in spectra.iter_mut().enumerate() {
+            let begin = offset + sym * symbol_len + self.cp_len;
+            let base = (sym * symbol_len + self.cp_len) as f32;
+            *spectrum = self.buffer[begin..begin + n]
+                .iter()
+                .enumerate()
+                .map(|(m, &s)| {
+                    // Remove the fractional offset before the FFT.
+                    let phase =
+                        -std::f32::consts::TAU * pending.frac_bins * (base + m as f32) / n as f32;
+                    s * Complex::from_polar(1.0, phase)
+                })
+                .collect();
+            self.fft.process(spectrum);
+        }
+
+        let [first, second] = self.preamble.symbols();
+        let first_bin = self.preamble.first_bin;
+        let mut best_shift = 0i32;
+        let mut best_score = f32::MIN;
+        for g in -self.max_int_offset..=self.max_int_offset {
+            let shift = 2 * g;
+            let mut acc = Complex::new(0.0f32, 0.0);
+            for (i, (a, b)) in first.iter().zip(second).enumerate() {
+                if a.re == 0.0 {
+                    continue;
+                }
+                // Differential reference between the two symbols on the even bins.
+                let v = b / a;
+                let bin = ((i + first_bin) as i64 + shift as i64).rem_euclid(n as i64) as usize;
+                acc += spectra[0][bin].conj() * spectra[1][bin] * v.conj();
+            }
+            let score = acc.norm_sqr();
+            if score > best_score {
+                best_score = score;
+                best_shift = shift;
+            }
+        }
+
+        // Adjacent subcarriers see nearly the same channel, so de-spreading neighbours
+        // adds coherently only for the real sequence.
+        let bin =
+            |i: usize| ((i + first_bin) as i64 + best_shift as i64).rem_euclid(n as i64) as usize;
+        let mut coherent = Complex::new(0.0f32, 0.0);
+        let mut energy = 0.0f32;
+        for i in 0..second.len() {
+            let y = spectra[1][bin(i)] * second[i].conj();
+            energy += y.norm_sqr();
+            if i + 1 < second.len() {
+                coherent += y * (spectra[1][bin(i + 1)] * second[i + 1].conj()).conj();
+            }
+        }
+        if energy <= 0.0 || coherent.norm() / energy < MIN_SEQUENCE_SCORE {
+            return None;
+        }
+
+        Some(SyncResult {
+            start: pending.start,
+            cfo_bins: pending.frac_bins + best_shift as f32,
+            metric: pending.metric,
+        })
+    }
+
+    /// Drop history that no search or pending detection still needs.
+    fn trim(&mut self) {
+        let mut keep_from = self.next_d.saturating_sub(1);
+        if let Some(plateau) = &self.plateau {
+            if let Some(&(d, _, _)) = plateau.metrics.first() {
+                keep_from = keep_from.min(d);
+            }
+        }
+        // A plateau can close up to one prefix after the frame start.
+        keep_from = keep_from.saturating_sub(self.cp_len as u64);
+        for pending in &self.pending {
+            keep_from = keep_from.min(pending.start);
+        }
+        if keep_from > self.buffer_start {
+            let drop = (keep_from - self.buffer_start) as usize;
+            if drop >= self.nfft {
+                self.buffer.drain(..drop);
+                self.buffer_start = keep_from;
+            }
+        }
+    }
+}
+
+/// +/-1 sequence from a 7-bit maximal-length LFSR (x^7 + x^6 + 1).
+pub(crate) fn pn_sequence(len: usize, seed: u8) -> Vec<f32> {
+    let mut state = (seed & 0x7f).max(1);
+    (0..len)
+        .map(|_| {
+            let bit = ((state >> 6) ^ (state >> 5)) & 1;
+            state = ((state << 1) | bit) & 0x7f;
+            if bit == 0 {
+                1.0
+            } else {
+                -1.0
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OfdmModulator;
+
+    fn preamble_samples() -> Vec<Complex<f32>> {
+        let modem = OfdmModulator::new();
+        let preamble = Preamble::default();
+        let mut out = Vec::new();
+        for sym in preamble.symbols() {
+            out.extend(modem.modulate(sym).expect("modulate"));
+        }
+        out
+    }
+
+    fn run(offset: usize, cfo_bins: f32, chunk: usize) -> Vec<SyncResult> {
+        let mut stream = vec![Complex::new(0.0, 0.0); offset];
+        stream.extend(preamble_samples());
+        stream.extend(vec![Complex::new(0.0, 0.0); 6000]);
+        // Small noise floor so the metric is defined everywhere.
+        let mut seed = 0x1234_5678u32;
+        for (n, s) in stream.iter_mut().enumerate() {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let noise = (seed as f32 / u32::MAX as f32 - 0.5) * 1e-5;
+            let rot =
+                Complex::from_polar(1.0, std::f32::consts::TAU * cfo_bins * n as f32 / 2048.0);
+            *s = *s * rot + Complex::new(noise, -noise);
+        }
+        let mut det = PreambleDetector::default();
+        let mut results = Vec::new();
+        for block in stream.chunks(chunk) {
+            results.extend(det.push(block));
+        }
+        results
+    }
+
+    #[test]
+    fn preamble_first_symbol_has_repeated_halves() {
+        let samples = preamble_samples();
+        let body = &samples[256..256 + 2048];
+        for m in 0..1024 {
+            assert!((body[m] - body[m + 1024]).norm() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn detects_timing_in_stream() {
+        let results = run(5000, 0.0, 1024);
+        assert_eq!(results.len(), 1);
+        let r = results[0];
+        assert!((r.start as i64 - 5000).abs() <= 64, "start {}", r.start);
+        assert!(r.cfo_bins.abs() < 0.05, "cfo {}", r.cfo_bins);
+    }
+
+    #[test]
+    fn ignores_steady_tone() {
+        let mut det = PreambleDetector::default();
+        let tone: Vec<_> = (0..48_000)
+            .map(|n| {
+                Complex::new(
+                    (std::f32::consts::TAU * 700.0 * n as f32 / 48_000.0).sin(),
+                    0.0,
+                )
+            })
+            .collect();
+        assert!(det.push(&tone).is_empty());
+    }
+
+    #[test]
+    fn steady_tone_keeps_history_bounded() {
+        let mut det = PreambleDetector::default();
+        let step = std::f32::consts::TAU * 500.0 / 48_000.0;
+        let mut n = 0u32;
+        let mut max_buffer = 0;
+        for _ in 0..200 {
+            let block: Vec<_> = (0..2400)
+                .map(|_| {
+                    n += 1;
+                    Complex::from_polar(1.0, step * (n % 96) as f32)
+                })
+                .collect();
+            assert!(det.push(&block).is_empty());
+            max_buffer = max_buffer.max(det.buffer.len());
+        }
+        assert!(max_buffer < 4 * 2304, "buffer grew to {}", max_buffer);
+
+        // The detector still finds a preamble once the tone stops.
+        let mut stream = vec![Complex::new(1e-4, 0.0); 5000];
+        stream.extend(preamble_samples());
+        stream.extend(vec![Complex::new(1e-4, 0.0); 6000]);
+        let mut results = Vec::new();
+        for block in stream.chunks(1024) {
+            results.extend(det.push(block));
+        }
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn estimates_fractional_and_integer_offset() {
+        for &cfo in &[0.3f32, -0.45, 2.3, -5.7] {
+            let results = run(3000, cfo, 777);
+            assert_eq!(results.len(), 1, "cfo {}", cfo);
+            assert!(
+                (results[0].cfo_bins - cfo).abs() < 0.05,
+                "expected {}, got {}",
+                cfo,
+                results[0].cfo_bins
+            );
+        }
+    }
+}
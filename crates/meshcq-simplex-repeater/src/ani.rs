@@ -0,0 +1,144 @@
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::path::Path;
+
+const ANI_TONE_MS: f32 = 80.0;
+const ANI_GAP_MS: f32 = 120.0;
+const ANI_LEVEL: f32 = 0.2;
+
+/// Where the repeater sends its own ANI burst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AniPosition {
+    /// Before the repeated audio.
+    Start,
+    /// After the repeated audio and any CW ID.
+    End,
+}
+
+pub struct AniBurst {
+    pub samples: Vec<f32>,
+    pub position: AniPosition,
+}
+
+pub struct Caller {
+    pub digits: String,
+    pub callsign: String,
+}
+
+pub fn build_burst(
+    digits: &str,
+    position: AniPosition,
+    sample_rate_hz: f32,
+) -> Result<AniBurst, Box<dyn std::error::Error>> {
+    if digits.is_empty() {
+        return Err("ani: empty digit string".into());
+    }
+    if let Some(bad) = digits
+        .chars()
+        .find(|&ch| meshcq_dtmf::key_frequencies(ch).is_none())
+    {
+        return Err(format!("ani: '{}' is not a DTMF key", bad).into());
+    }
+    let samples =
+        meshcq_dtmf::generate_sequence(digits, sample_rate_hz, ANI_TONE_MS, ANI_GAP_MS, ANI_LEVEL);
+    Ok(AniBurst { samples, position })
+}
+
+/// Load a roster file with one `digits=CALLSIGN` entry per line.
+///
+/// Blank lines and lines starting with `#` are ignored; no ANI can start with `#`, as
+/// that would read as a mailbox command.
+pub fn load_roster(path: &Path) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut roster = HashMap::new();
+    for (lineno, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let bad = |why: &str| format!("{}:{}: {}", path.display(), lineno + 1, why);
+        let Some((digits, callsign)) = line.split_once('=') else {
+            return Err(bad("expected digits=CALLSIGN").into());
+        };
+        let (digits, callsign) = (digits.trim().to_ascii_uppercase(), callsign.trim());
+        if digits.is_empty() || callsign.is_empty() {
+            return Err(bad("expected digits=CALLSIGN").into());
+        }
+        if let Some(ch) = digits
+            .chars()
+            .find(|&ch| meshcq_dtmf::key_frequencies(ch).is_none())
+        {
+            return Err(bad(&format!("'{}' is not a DTMF key", ch)).into());
+        }
+        roster.insert(digits, callsign.to_ascii_uppercase());
+    }
+    Ok(roster)
+}
+
+/// Match the longest roster entry that prefixes `digits`.
+///
+/// Anything after the ANI (e.g. a command keyed straight after it) is left to the caller.
+pub fn match_prefix(digits: &str, roster: &HashMap<String, String>) -> Option<Caller> {
+    roster
+        .iter()
+        .filter(|(ani, _)| !ani.is_empty() && digits.starts_with(ani.as_str()))
+        .max_by_key(|(ani, _)| ani.len())
+        .map(|(ani, callsign)| Caller {
+            digits: ani.clone(),
+            callsign: callsign.clone(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn roster(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|&(d, c)| (d.to_string(), c.to_string()))
+            .collect()
+    }
+
+    fn load(text: &str) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "meshcq-roster-{}-{}.txt",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, text)?;
+        let result = load_roster(&path);
+        std::fs::remove_file(&path).ok();
+        result
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let roster = roster(&[("12", "W1AW"), ("123", "N0CALL")]);
+        let caller = match_prefix("123*4", &roster).expect("match");
+        assert_eq!(caller.callsign, "N0CALL");
+        assert_eq!(caller.digits, "123");
+        assert_eq!(
+            match_prefix("129", &roster).expect("match").callsign,
+            "W1AW"
+        );
+        assert!(match_prefix("456", &roster).is_none());
+        assert!(match_prefix("1", &roster).is_none());
+    }
+
+    #[test]
+    fn roster_skips_comments_and_rejects_bad_lines() {
+        let roster =
+            load("# club members\n\n  123 = n0call \n#1=IGNORED\n4a=w1aw\n").expect("load");
+        assert_eq!(roster.len(), 2);
+        assert_eq!(roster["123"], "N0CALL");
+        assert_eq!(roster["4A"], "W1AW");
+
+        for bad in ["123 N0CALL\n", "=N0CALL\n", "123=\n", "12x=N0CALL\n"] {
+            let err = load(bad).expect_err(bad).to_string();
+            assert!(err.contains(":1:"), "{}", err);
+        }
+    }
+}
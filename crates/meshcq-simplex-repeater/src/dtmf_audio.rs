@@ -0,0 +1,227 @@
+use crate::{noise, notch};
+use clap::ValueEnum;
+
+const NOISE_WINDOW_SECS: f32 = 0.02;
+const NOISE_CUTOFF_HZ: f32 = 3000.0;
+const EVENT_MARGIN_SECS: f32 = 0.03;
+const FADE_SECS: f32 = 0.01;
+const REGEN_TONE_SECS: f32 = 0.1;
+const REGEN_MIN_GAP_SECS: f32 = 0.05;
+const REGEN_LEVEL: f32 = 0.2;
+
+/// What to do with DTMF tones in repeated or recorded audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DtmfPolicy {
+    /// Leave the audio unchanged.
+    Pass,
+    /// Silence the tone span.
+    Mute,
+    /// Replace the tone span with band-limited noise.
+    Noise,
+    /// Notch out the tone frequencies, keeping any speech underneath.
+    Notch,
+    /// Replace the tones with clean ones at standard level and timing.
+    Regenerate,
+}
+
+pub fn apply_policy(
+    samples: &mut [f32],
+    events: &[(char, usize, usize)],
+    policy: DtmfPolicy,
+    sample_rate_hz: f32,
+) {
+    if events.is_empty() {
+        return;
+    }
+    match policy {
+        DtmfPolicy::Pass => {}
+        DtmfPolicy::Mute => mute(samples, events, sample_rate_hz),
+        DtmfPolicy::Noise => fill_noise(samples, events, sample_rate_hz),
+        DtmfPolicy::Notch => notch(samples, events, sample_rate_hz),
+        DtmfPolicy::Regenerate => regenerate(samples, events, sample_rate_hz),
+    }
+}
+
+fn fill_noise(samples: &mut [f32], events: &[(char, usize, usize)], sample_rate_hz: f32) {
+    let mut ranges = collect_event_ranges(samples.len(), events, 0);
+    if ranges.is_empty() {
+        return;
+    }
+    ranges.sort_unstable_by_key(|r| r.0);
+    let window_len = secs_to_samples(NOISE_WINDOW_SECS, sample_rate_hz);
+    let noise_level = noise::estimate_floor(samples, &ranges, window_len);
+    for (start, end) in ranges {
+        noise::fill_band_limited_gaussian_noise(
+            &mut samples[start..end],
+            noise_level,
+            sample_rate_hz,
+            NOISE_CUTOFF_HZ,
+        );
+    }
+}
+
+fn notch(samples: &mut [f32], events: &[(char, usize, usize)], sample_rate_hz: f32) {
+    let margin = secs_to_samples(EVENT_MARGIN_SECS, sample_rate_hz);
+    let fade_len = secs_to_samples(FADE_SECS, sample_rate_hz);
+    for &(ch, start, end) in events {
+        let Some((row_hz, col_hz)) = meshcq_dtmf::key_frequencies(ch) else {
+            continue;
+        };
+        notch::notch_tones(
+            samples,
+            start.saturating_sub(margin),
+            end.saturating_add(1 + margin),
+            &[row_hz, col_hz],
+            sample_rate_hz,
+            fade_len,
+        );
+    }
+}
+
+fn mute(samples: &mut [f32], events: &[(char, usize, usize)], sample_rate_hz: f32) {
+    let margin = secs_to_samples(EVENT_MARGIN_SECS, sample_rate_hz);
+    let fade_len = secs_to_samples(FADE_SECS, sample_rate_hz);
+    for (start, end) in collect_event_ranges(samples.len(), events, margin) {
+        fade_out(samples, start, end, fade_len);
+    }
+}
+
+fn regenerate(samples: &mut [f32], events: &[(char, usize, usize)], sample_rate_hz: f32) {
+    mute(samples, events, sample_rate_hz);
+    let margin = secs_to_samples(EVENT_MARGIN_SECS, sample_rate_hz);
+    let tone_len = secs_to_samples(REGEN_TONE_SECS, sample_rate_hz);
+    let min_gap = secs_to_samples(REGEN_MIN_GAP_SECS, sample_rate_hz);
+    for (i, &(ch, start, _)) in events.iter().enumerate() {
+        if start >= samples.len() {
+            continue;
+        }
+        // Never run into the next key's slot, so the digit count is preserved.
+        let limit = events
+            .get(i + 1)
+            .map(|&(_, next, _)| next.saturating_sub(margin + min_gap))
+            .unwrap_or(samples.len())
+            .min(samples.len());
+        let len = tone_len.min(limit.saturating_sub(start));
+        if let Some(tone) = meshcq_dtmf::generate_key(ch, sample_rate_hz, len, REGEN_LEVEL) {
+            samples[start..start + len].copy_from_slice(&tone);
+        }
+    }
+}
+
+fn fade_out(samples: &mut [f32], start: usize, end: usize, fade_len: usize) {
+    let fade_start = start.saturating_sub(fade_len);
+    let fade_end = end.saturating_add(fade_len).min(samples.len());
+    for (i, sample) in samples
+        .iter_mut()
+        .enumerate()
+        .take(fade_end)
+        .skip(fade_start)
+    {
+        let gain = if i < start {
+            (start - i) as f32 / (start - fade_start) as f32
+        } else if i < end {
+            0.0
+        } else {
+            (i + 1 - end) as f32 / (fade_end - end) as f32
+        };
+        *sample *= gain;
+    }
+}
+
+fn collect_event_ranges(
+    len: usize,
+    events: &[(char, usize, usize)],
+    margin: usize,
+) -> Vec<(usize, usize)> {
+    events
+        .iter()
+        .filter_map(|&(_, start, end)| {
+            if start >= len {
+                return None;
+            }
+            let start = start.saturating_sub(margin);
+            let end = end.saturating_add(margin).min(len.saturating_sub(1));
+            Some((start, end.saturating_add(1)))
+        })
+        .collect()
+}
+
+fn secs_to_samples(secs: f32, sample_rate_hz: f32) -> usize {
+    (sample_rate_hz * secs).round() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FS: f32 = 48_000.0;
+    const START: usize = 24_000;
+    const END: usize = 33_599;
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    /// Low background hiss with key 5 at 0.2 over the event span.
+    fn keyed() -> (Vec<f32>, Vec<(char, usize, usize)>) {
+        let mut seed = 0x2545_f491u32;
+        let mut samples: Vec<f32> = (0..57_600)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                (seed as f32 / u32::MAX as f32 - 0.5) * 0.02
+            })
+            .collect();
+        let tone = meshcq_dtmf::generate_key('5', FS, END + 1 - START, 0.2).unwrap();
+        for (s, t) in samples[START..=END].iter_mut().zip(tone) {
+            *s += t;
+        }
+        (samples, vec![('5', START, END)])
+    }
+
+    #[test]
+    fn pass_leaves_samples_untouched() {
+        let (mut samples, events) = keyed();
+        let before = samples.clone();
+        apply_policy(&mut samples, &events, DtmfPolicy::Pass, FS);
+        assert_eq!(
+            samples.iter().map(|x| x.to_bits()).collect::<Vec<_>>(),
+            before.iter().map(|x| x.to_bits()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn mute_zeroes_span_and_padding() {
+        let mut samples = vec![1.0; 57_600];
+        apply_policy(&mut samples, &[('5', START, END)], DtmfPolicy::Mute, FS);
+        let margin = secs_to_samples(EVENT_MARGIN_SECS, FS);
+        let fade = secs_to_samples(FADE_SECS, FS);
+        let (lo, hi) = (START - margin, END + margin);
+        assert!(samples[lo..=hi].iter().all(|&x| x == 0.0));
+        assert!(samples[lo - fade..lo].iter().all(|&x| x > 0.0 && x <= 1.0));
+        assert!(samples[hi + 1..hi + 1 + fade].iter().all(|&x| x > 0.0));
+        assert!(samples[..lo - fade].iter().all(|&x| x == 1.0));
+        assert!(samples[hi + 1 + fade..].iter().all(|&x| x == 1.0));
+    }
+
+    #[test]
+    fn noise_stays_below_signal() {
+        let (mut samples, events) = keyed();
+        let tone_rms = rms(&samples[START..=END]);
+        apply_policy(&mut samples, &events, DtmfPolicy::Noise, FS);
+        let filled = rms(&samples[START..=END]);
+        assert!(filled > 0.0);
+        assert!(filled < tone_rms / 4.0, "{} vs {}", filled, tone_rms);
+    }
+
+    #[test]
+    fn regenerate_emits_standard_tone() {
+        let (mut samples, events) = keyed();
+        apply_policy(&mut samples, &events, DtmfPolicy::Regenerate, FS);
+        let len = secs_to_samples(REGEN_TONE_SECS, FS);
+        let expected = meshcq_dtmf::generate_key('5', FS, len, REGEN_LEVEL).unwrap();
+        assert_eq!(&samples[START..START + len], &expected[..]);
+        assert!(samples[START + len..=END].iter().all(|&x| x == 0.0));
+    }
+}
@@ -1,15 +1,21 @@
 use clap::Parser;
-use meshcq_dtmf::DtmfDebouncer;
+use meshcq_dtmf::{DtmfDebouncer, ToneBurstDetector};
 
+mod ani;
 mod callsign;
+mod dtmf_audio;
 mod noise;
+mod notch;
+use ani::{AniBurst, AniPosition, Caller};
+use dtmf_audio::{apply_policy, DtmfPolicy};
+use std::collections::HashMap;
 mod recording;
 use meshcq_modem::device::TimedChunk;
 use recording::{
     format_timestamp_filename, latest_recording_path, read_recording, recording_metadata,
     write_recording, write_recording_to,
 };
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const SAMPLE_RATE_HZ: f32 = 48_000.0;
 const TONE_FREQ_HZ: f32 = 700.0;
@@ -27,6 +33,10 @@ const DTMF_COMMAND_GAP_SECS: f32 = 2.0;
 const MAILBOX_BEEP_SECS: f32 = 0.5;
 const MAILBOX_BEEP_FREQ_HZ: f32 = 1000.0;
 const MAILBOX_BEEP_LEVEL: f32 = 0.3;
+const TONE_BURST_START_SECS: f32 = 1.0;
+const DEFAULT_TONE_BURST_HOLD_SECS: f32 = 0.0;
+const ANI_START_SECS: f32 = 1.0;
+const ANI_GAP_SECS: f32 = 0.3;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum RepeaterState {
@@ -45,6 +55,16 @@ enum MailboxCommand {
     ReplayLast,
 }
 
+struct Identification {
+    callsign: Vec<f32>,
+    ani: Option<AniBurst>,
+}
+
+struct DtmfSequence {
+    digits: String,
+    start: usize,
+}
+
 struct MailboxState {
     pending_record: Option<u8>,
 }
@@ -60,9 +80,42 @@ struct Args {
     /// Regex to select input/output device by name.
     #[arg(long)]
     sound_device: Option<String>,
+    /// Where to receive audio: `sound`, `wav:PATH`, `f32:PATH` or `s16:PATH` (raw PCM),
+    /// with `-` as the path for stdin.
+    #[arg(long, default_value = "sound")]
+    input: String,
+    /// Replay file input at real time rather than as fast as possible, so idle ID
+    /// timers run as they would on the air.
+    #[arg(long)]
+    real_time: bool,
+    /// Where to send audio, named as for `--input`.
+    #[arg(long, default_value = "sound")]
+    output: String,
     /// Directory to store received messages as Ogg Opus.
     #[arg(long, default_value = DEFAULT_RECORDINGS_DIR)]
     recordings_dir: PathBuf,
+    /// Only repeat messages, or act on their DTMF commands, if they open with a 1750 Hz
+    /// tone burst.
+    #[arg(long)]
+    tone_burst: bool,
+    /// Keep access open this many seconds after the last accepted message.
+    #[arg(long, default_value_t = DEFAULT_TONE_BURST_HOLD_SECS)]
+    tone_burst_hold_secs: f32,
+    /// How DTMF tones appear in repeated audio.
+    #[arg(long, value_enum, default_value_t = DtmfPolicy::Noise)]
+    repeat_dtmf: DtmfPolicy,
+    /// How DTMF tones appear in recordings and mailboxes.
+    #[arg(long, value_enum, default_value_t = DtmfPolicy::Noise)]
+    record_dtmf: DtmfPolicy,
+    /// DTMF ANI digits to send with each transmission.
+    #[arg(long)]
+    ani: Option<String>,
+    /// Where to send the ANI burst in each transmission.
+    #[arg(long, value_enum, default_value_t = AniPosition::Start)]
+    ani_position: AniPosition,
+    /// Roster mapping incoming ANI digits to callsigns, one `digits=CALLSIGN` per line.
+    #[arg(long)]
+    ani_roster: Option<PathBuf>,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -72,9 +125,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (input_tx, input_rx) = std::sync::mpsc::channel();
     let (output_tx, output_rx) = std::sync::mpsc::channel();
     let device_regex = args.sound_device.as_deref();
-    let _output =
-        meshcq_modem::device::start_default_output(output_rx, args.output_level, device_regex)?;
-    let _input = meshcq_modem::device::start_default_input(input_tx, device_regex)?;
+    let _output = meshcq_modem::device::open_sink(&args.output, args.output_level, device_regex)?
+        .start(output_rx)?;
+    let _input = meshcq_modem::device::open_source(&args.input, device_regex, args.real_time)?
+        .start(input_tx)?;
 
     let level = 10.0_f32.powf(-CW_LEVEL_DB_DOWN / 20.0);
     let callsign_samples = callsign::pre_modulate_callsign(
@@ -84,27 +138,46 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         WPM,
         level,
     )?;
+    let ani = match &args.ani {
+        Some(digits) => Some(ani::build_burst(digits, args.ani_position, SAMPLE_RATE_HZ)?),
+        None => None,
+    };
+    let ident = Identification {
+        callsign: callsign_samples,
+        ani,
+    };
+    let roster = match &args.ani_roster {
+        Some(path) => ani::load_roster(path)?,
+        None => HashMap::new(),
+    };
 
     let mut dtmf = DtmfDebouncer::builder(SAMPLE_RATE_HZ).build();
+    let mut tone_burst = ToneBurstDetector::builder(SAMPLE_RATE_HZ).build();
+    let mut last_access_end: Option<u64> = None;
 
     let mut last_id: Option<u64> = None;
     let mut last_message_end: Option<u64> = None;
     let mut state = RepeaterState::Idle;
     let mut mailbox = MailboxState { pending_record: None };
+    let mut held: Option<TimedChunk> = None;
 
     loop {
         let timeout = match state {
             RepeaterState::Idle => None,
             RepeaterState::MidConversation => Some(std::time::Duration::from_secs(ID_IDLE_SECS)),
         };
-        let message = read_message(&input_rx, timeout)?;
+        let message = read_message(&input_rx, timeout, &mut held);
 
         let mut message = match message {
-            Some(message) => message,
-            None => {
+            Received::Message(message) => message,
+            Received::Closed => {
+                eprintln!("audio input: closed");
+                return Ok(());
+            }
+            Received::Timeout => {
                 if let Some(end) = last_message_end {
                     let now = end.saturating_add(samples_from_secs(ID_IDLE_SECS as f32));
-                    let len = transmit_callsign(&callsign_samples, &output_tx);
+                    let len = transmit_callsign(&ident, &output_tx);
                     last_id = Some(now.saturating_add(len as u64));
                 }
                 state = RepeaterState::Idle;
@@ -118,12 +191,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             .end_sample
             .saturating_sub(message.samples.len() as u64);
         let events = dtmf.push(&message.samples);
-        let sequences = split_dtmf_sequences(&events, message_start);
+        let mut sequences = split_dtmf_sequences(&events, message_start);
+        let caller = take_caller(&mut sequences, &roster);
+        if let Some(caller) = &caller {
+            eprintln!("ani: {} ({})", caller.callsign, caller.digits);
+        }
         for seq in &sequences {
-            eprintln!("dtmf seq: {}", seq);
+            eprintln!("dtmf seq: {}", seq.digits);
         }
+        // Every command keys the transmitter, so it needs access just as repeating does.
+        let access = !args.tone_burst
+            || tone_burst_access(
+                || has_access_burst(&mut tone_burst, &message.samples),
+                last_access_end,
+                message_start,
+                args.tone_burst_hold_secs,
+            );
         let commands = parse_commands(&sequences);
         if !commands.is_empty() {
+            if !access {
+                eprintln!("tone burst: no access, {} command(s) ignored", commands.len());
+                continue;
+            }
+            if args.tone_burst {
+                last_access_end = Some(message.end_sample);
+            }
             for cmd in &commands {
                 match cmd {
                     MailboxCommand::Record(digit) => {
@@ -133,42 +225,52 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                     MailboxCommand::Play(digit) => {
                         eprintln!("dtmf cmd: play {}", digit);
-                        replay_mailbox(*digit, &args.recordings_dir, &callsign_samples, &output_tx);
+                        replay_mailbox(*digit, &args.recordings_dir, &ident, &output_tx);
                     }
                     MailboxCommand::ReplayLast => {
                         eprintln!("dtmf cmd: replay last");
-                        replay_latest(
-                            &args.recordings_dir,
-                            &callsign_samples,
-                            &output_tx,
-                        );
+                        replay_latest(&args.recordings_dir, &ident, &output_tx);
                     }
                 }
             }
             continue;
         }
-        suppress_dtmf(&mut message.samples, &events);
+        let caller_callsign = caller.as_ref().map(|caller| caller.callsign.as_str());
+        let mut recorded = message.samples.clone();
+        apply_policy(&mut recorded, &events, args.record_dtmf, SAMPLE_RATE_HZ);
+        apply_policy(&mut message.samples, &events, args.repeat_dtmf, SAMPLE_RATE_HZ);
         if let Some(digit) = record_target {
             if let Err(err) = record_mailbox(
                 digit,
                 &args.recordings_dir,
                 SAMPLE_RATE_HZ,
-                &message.samples,
+                &recorded,
+                caller_callsign,
             ) {
                 eprintln!("mailbox record failed: {}", err);
             }
         }
-        if let Err(err) = write_recording(&args.recordings_dir, SAMPLE_RATE_HZ, &message.samples) {
+        if let Err(err) = write_recording(
+            &args.recordings_dir,
+            SAMPLE_RATE_HZ,
+            &recorded,
+            caller_callsign,
+        ) {
             eprintln!("recording failed: {}", err);
         }
+        if !access {
+            eprintln!(
+                "tone burst: no access, message not repeated ({} samples)",
+                message.samples.len()
+            );
+            continue;
+        }
+        if args.tone_burst {
+            last_access_end = Some(message.end_sample);
+        }
 
         last_message_end = Some(message.end_sample);
-        let result = transmit_message(
-            message,
-            &callsign_samples,
-            &output_tx,
-            last_id,
-        );
+        let result = transmit_message(message, &ident, &output_tx, last_id);
         if result.sent_callsign {
             last_id = Some(result.transmission_end_sample);
             state = RepeaterState::Idle;
@@ -178,19 +280,33 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 }
 
+enum Received {
+    Message(TimedChunk),
+    Timeout,
+    Closed,
+}
+
+/// Wait for a message and join on any that follow it closely.
+///
+/// Chunks further apart than the continuity gap in input time stay separate even if
+/// they arrive together, as they do when a file is replayed faster than real time; the
+/// next one is kept in `held` for the following call.
 fn read_message(
     input_rx: &std::sync::mpsc::Receiver<TimedChunk>,
     first_timeout: Option<std::time::Duration>,
-) -> Result<Option<TimedChunk>, Box<dyn std::error::Error>> {
-    let first = match first_timeout {
-        Some(timeout) => match input_rx.recv_timeout(timeout) {
+    held: &mut Option<TimedChunk>,
+) -> Received {
+    let first = match (held.take(), first_timeout) {
+        (Some(message), _) => message,
+        (None, Some(timeout)) => match input_rx.recv_timeout(timeout) {
             Ok(message) => message,
-            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => return Ok(None),
-            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
-                return Err("input channel disconnected".into());
-            }
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => return Received::Timeout,
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return Received::Closed,
+        },
+        (None, None) => match input_rx.recv() {
+            Ok(message) => message,
+            Err(_) => return Received::Closed,
         },
-        None => input_rx.recv()?,
     };
 
     let mut combined = first.samples;
@@ -200,8 +316,12 @@ fn read_message(
         match input_rx.recv_timeout(std::time::Duration::from_secs_f32(CONTINUITY_GAP_SECS)) {
             Ok(next) => {
                 let next_start = next.end_sample.saturating_sub(next.samples.len() as u64);
-                let gap_samples = next_start.saturating_sub(last_end) as usize;
-                combined.extend(std::iter::repeat_n(0.0, gap_samples));
+                let gap_samples = next_start.saturating_sub(last_end);
+                if gap_samples > samples_from_secs(CONTINUITY_GAP_SECS) {
+                    *held = Some(next);
+                    break;
+                }
+                combined.extend(std::iter::repeat_n(0.0, gap_samples as usize));
                 combined.extend(next.samples);
                 last_end = next.end_sample;
             }
@@ -210,17 +330,14 @@ fn read_message(
         }
     }
 
-    Ok(Some(TimedChunk {
+    Received::Message(TimedChunk {
         samples: combined,
         end_sample: last_end,
-    }))
+    })
 }
 
-fn transmit_callsign(
-    callsign_samples: &[f32],
-    output_tx: &std::sync::mpsc::Sender<Vec<f32>>,
-) -> usize {
-    let out = build_transmit_message(&[], callsign_samples, true);
+fn transmit_callsign(ident: &Identification, output_tx: &std::sync::mpsc::Sender<Vec<f32>>) -> usize {
+    let out = build_transmit_message(&[], ident, true);
     let out_len = out.len();
     let _ = output_tx.send(out);
     out_len
@@ -228,20 +345,20 @@ fn transmit_callsign(
 
 fn transmit_message(
     message: TimedChunk,
-    callsign_samples: &[f32],
+    ident: &Identification,
     output_tx: &std::sync::mpsc::Sender<Vec<f32>>,
     last_id: Option<u64>,
 ) -> TransmitResult {
     let message_end = message.end_sample;
     let id_due = last_id.map(|last| last.saturating_add(samples_from_secs(ID_INTERVAL_SECS as f32)));
 
-    let base_len = transmit_len(message.samples.len(), callsign_samples.len(), false);
+    let base_len = transmit_len(message.samples.len(), ident, false);
     let will_expire = match id_due {
         Some(due) => message_end.saturating_add(base_len as u64) >= due,
         None => true,
     };
 
-    let out = build_transmit_message(&message.samples, callsign_samples, will_expire);
+    let out = build_transmit_message(&message.samples, ident, will_expire);
     let out_len = out.len();
     let _ = output_tx.send(out);
 
@@ -255,7 +372,7 @@ fn samples_from_secs(secs: f32) -> u64 {
     (SAMPLE_RATE_HZ * secs).round() as u64
 }
 
-fn transmit_len(message_len: usize, callsign_len: usize, include_callsign: bool) -> usize {
+fn transmit_len(message_len: usize, ident: &Identification, include_callsign: bool) -> usize {
     let lead_samples = (SAMPLE_RATE_HZ * TX_LEAD_TIME_SECS).round() as usize;
     let hang_samples = (SAMPLE_RATE_HZ * TX_HANG_TIME_SECS).round() as usize;
     let gap_samples = if include_callsign {
@@ -263,16 +380,21 @@ fn transmit_len(message_len: usize, callsign_len: usize, include_callsign: bool)
     } else {
         0
     };
+    let ani_samples = match &ident.ani {
+        Some(ani) => ani.samples.len() + (SAMPLE_RATE_HZ * ANI_GAP_SECS).round() as usize,
+        None => 0,
+    };
     lead_samples
+        + ani_samples
         + message_len
         + gap_samples
-        + if include_callsign { callsign_len } else { 0 }
+        + if include_callsign { ident.callsign.len() } else { 0 }
         + hang_samples
 }
 
 fn build_transmit_message(
     message: &[f32],
-    callsign_samples: &[f32],
+    ident: &Identification,
     include_callsign: bool,
 ) -> Vec<f32> {
     let lead_samples = (SAMPLE_RATE_HZ * TX_LEAD_TIME_SECS).round() as usize;
@@ -282,84 +404,111 @@ fn build_transmit_message(
     } else {
         0
     };
+    let ani_gap_samples = (SAMPLE_RATE_HZ * ANI_GAP_SECS).round() as usize;
 
-    let mut out = Vec::with_capacity(transmit_len(
-        message.len(),
-        callsign_samples.len(),
-        include_callsign,
-    ));
+    let mut out = Vec::with_capacity(transmit_len(message.len(), ident, include_callsign));
     out.extend(std::iter::repeat_n(0.0, lead_samples));
+    if let Some(ani) = ident.ani.as_ref().filter(|ani| ani.position == AniPosition::Start) {
+        out.extend_from_slice(&ani.samples);
+        out.extend(std::iter::repeat_n(0.0, ani_gap_samples));
+    }
     out.extend_from_slice(message);
     if include_callsign {
         out.extend(std::iter::repeat_n(0.0, gap_samples));
-        out.extend_from_slice(callsign_samples);
+        out.extend_from_slice(&ident.callsign);
+    }
+    if let Some(ani) = ident.ani.as_ref().filter(|ani| ani.position == AniPosition::End) {
+        out.extend(std::iter::repeat_n(0.0, ani_gap_samples));
+        out.extend_from_slice(&ani.samples);
     }
     out.extend(std::iter::repeat_n(0.0, hang_samples));
     out
 }
 
-fn suppress_dtmf(samples: &mut [f32], events: &[(char, usize, usize)]) {
-    if events.is_empty() {
-        return;
-    }
-    let mut ranges = collect_event_ranges(samples.len(), events);
-    if ranges.is_empty() {
-        return;
-    }
-    ranges.sort_unstable_by_key(|r| r.0);
-    let window_len = samples_from_secs(0.02) as usize;
-    let noise_level = noise::estimate_floor(samples, &ranges, window_len);
-    let cutoff_hz = 3000.0;
-    for (start, end) in ranges {
-        noise::fill_band_limited_gaussian_noise(
-            &mut samples[start..end],
-            noise_level,
-            SAMPLE_RATE_HZ,
-            cutoff_hz,
-        );
-    }
+/// Whether a message gets through with `--tone-burst`: it opens with a burst, or starts
+/// within the hold time after the last message that had access.
+fn tone_burst_access(
+    heard_burst: impl FnOnce() -> bool,
+    last_access_end: Option<u64>,
+    message_start: u64,
+    hold_secs: f32,
+) -> bool {
+    heard_burst()
+        || last_access_end
+            .is_some_and(|end| message_start <= end.saturating_add(samples_from_secs(hold_secs)))
 }
 
-fn collect_event_ranges(len: usize, events: &[(char, usize, usize)]) -> Vec<(usize, usize)> {
-    events
-        .iter()
-        .filter_map(|&(_, start, end)| {
-            if start >= len {
-                return None;
-            }
-            let end = end.min(len.saturating_sub(1));
-            Some((start, end.saturating_add(1)))
-        })
-        .collect()
+fn has_access_burst(detector: &mut ToneBurstDetector, samples: &[f32]) -> bool {
+    detector.reset();
+    let mut bursts = detector.push(samples);
+    bursts.extend(detector.finish());
+    let start_limit = samples_from_secs(TONE_BURST_START_SECS) as usize;
+    match bursts.first() {
+        Some(burst) if burst.start_sample <= start_limit => {
+            eprintln!("tone burst: {:.1} Hz", burst.freq_hz);
+            true
+        }
+        _ => false,
+    }
 }
 
-fn split_dtmf_sequences(events: &[(char, usize, usize)], message_start: u64) -> Vec<String> {
+fn split_dtmf_sequences(
+    events: &[(char, usize, usize)],
+    message_start: u64,
+) -> Vec<DtmfSequence> {
     let mut sequences = Vec::new();
     let mut current = String::new();
+    let mut current_start = 0usize;
     let mut last_end: Option<u64> = None;
     let gap_limit = samples_from_secs(DTMF_COMMAND_GAP_SECS);
 
-    for &(ch, _, end) in events {
+    for &(ch, start, end) in events {
         let abs_end = message_start.saturating_add(end as u64);
         if let Some(last) = last_end {
             if abs_end.saturating_sub(last) > gap_limit && !current.is_empty() {
-                sequences.push(current.clone());
-                current.clear();
+                sequences.push(DtmfSequence {
+                    digits: std::mem::take(&mut current),
+                    start: current_start,
+                });
             }
         }
+        if current.is_empty() {
+            current_start = start;
+        }
         current.push(ch);
         last_end = Some(abs_end);
     }
 
     if !current.is_empty() {
-        sequences.push(current);
+        sequences.push(DtmfSequence {
+            digits: current,
+            start: current_start,
+        });
     }
     sequences
 }
 
-fn parse_commands(sequences: &[String]) -> Vec<MailboxCommand> {
+/// Strip a known ANI burst from the start of the message so it never reads as a command.
+fn take_caller(
+    sequences: &mut Vec<DtmfSequence>,
+    roster: &HashMap<String, String>,
+) -> Option<Caller> {
+    let first = sequences.first_mut()?;
+    if first.start > samples_from_secs(ANI_START_SECS) as usize {
+        return None;
+    }
+    let caller = ani::match_prefix(&first.digits, roster)?;
+    first.digits.drain(..caller.digits.len());
+    if first.digits.is_empty() {
+        sequences.remove(0);
+    }
+    Some(caller)
+}
+
+fn parse_commands(sequences: &[DtmfSequence]) -> Vec<MailboxCommand> {
     let mut cmds = Vec::new();
     for seq in sequences {
+        let seq = &seq.digits;
         let chars: Vec<char> = seq.chars().collect();
         for i in 0..chars.len().saturating_sub(1) {
             let prefix = chars[i];
@@ -381,29 +530,30 @@ fn parse_commands(sequences: &[String]) -> Vec<MailboxCommand> {
     cmds
 }
 
-fn mailbox_path(recordings_dir: &PathBuf, digit: u8) -> PathBuf {
+fn mailbox_path(recordings_dir: &Path, digit: u8) -> PathBuf {
     recordings_dir.join(format!("mailbox-{}.ogg", digit))
 }
 
 fn record_mailbox(
     digit: u8,
-    recordings_dir: &PathBuf,
+    recordings_dir: &Path,
     sample_rate_hz: f32,
     samples: &[f32],
+    caller: Option<&str>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let (serial, timestamp, secs, nanos) = recording_metadata();
     let path = mailbox_path(recordings_dir, digit);
-    write_recording_to(&path, sample_rate_hz, samples, serial, &timestamp)?;
+    write_recording_to(&path, sample_rate_hz, samples, serial, &timestamp, caller)?;
     let ts = format_timestamp_filename(&timestamp, secs, nanos);
     let stamped = recordings_dir.join(format!("mailbox-{}-{}.ogg", digit, ts));
-    write_recording_to(&stamped, sample_rate_hz, samples, serial, &timestamp)?;
+    write_recording_to(&stamped, sample_rate_hz, samples, serial, &timestamp, caller)?;
     Ok(())
 }
 
 fn replay_mailbox(
     digit: u8,
-    recordings_dir: &PathBuf,
-    callsign_samples: &[f32],
+    recordings_dir: &Path,
+    ident: &Identification,
     output_tx: &std::sync::mpsc::Sender<Vec<f32>>,
 ) {
     let path = mailbox_path(recordings_dir, digit);
@@ -413,7 +563,7 @@ fn replay_mailbox(
     }
     match read_recording(&path, SAMPLE_RATE_HZ) {
         Ok(samples) => {
-            let out = build_transmit_message(&samples, callsign_samples, false);
+            let out = build_transmit_message(&samples, ident, false);
             let _ = output_tx.send(out);
         }
         Err(err) => {
@@ -423,8 +573,8 @@ fn replay_mailbox(
 }
 
 fn replay_latest(
-    recordings_dir: &PathBuf,
-    callsign_samples: &[f32],
+    recordings_dir: &Path,
+    ident: &Identification,
     output_tx: &std::sync::mpsc::Sender<Vec<f32>>,
 ) {
     let Some(path) = latest_recording_path(recordings_dir) else {
@@ -433,7 +583,7 @@ fn replay_latest(
     };
     match read_recording(&path, SAMPLE_RATE_HZ) {
         Ok(samples) => {
-            let out = build_transmit_message(&samples, callsign_samples, false);
+            let out = build_transmit_message(&samples, ident, false);
             let _ = output_tx.send(out);
         }
         Err(err) => {
@@ -468,3 +618,95 @@ fn generate_beep(sample_rate_hz: f32, freq_hz: f32, secs: f32, level: f32) -> Ve
     }
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(freq_hz: f32, secs: f32) -> Vec<f32> {
+        let len = samples_from_secs(secs) as usize;
+        (0..len)
+            .map(|n| 0.5 * (std::f32::consts::TAU * freq_hz * n as f32 / SAMPLE_RATE_HZ).sin())
+            .collect()
+    }
+
+    #[test]
+    fn tone_burst_grants_and_holds_access() {
+        let mut detector = ToneBurstDetector::builder(SAMPLE_RATE_HZ).build();
+        let mut opened = tone(1750.0, 0.5);
+        opened.extend(tone(800.0, 2.0));
+        let plain = tone(800.0, 2.0);
+        let hold = 5.0;
+
+        // No burst, no access.
+        assert!(!tone_burst_access(
+            || has_access_burst(&mut detector, &plain),
+            None,
+            0,
+            hold
+        ));
+        // The burst grants access.
+        assert!(tone_burst_access(
+            || has_access_burst(&mut detector, &opened),
+            None,
+            0,
+            hold
+        ));
+        // A follow-up without a burst inside the hold time gets through...
+        let end = samples_from_secs(10.0);
+        assert!(tone_burst_access(
+            || has_access_burst(&mut detector, &plain),
+            Some(end),
+            end + samples_from_secs(hold - 0.1),
+            hold
+        ));
+        // ...but not once it has expired.
+        assert!(!tone_burst_access(
+            || has_access_burst(&mut detector, &plain),
+            Some(end),
+            end + samples_from_secs(hold + 0.1),
+            hold
+        ));
+    }
+
+    #[test]
+    fn ani_is_taken_from_the_first_sequence_only() {
+        let roster: HashMap<String, String> = [("123".to_string(), "N0CALL".to_string())].into();
+        let late = samples_from_secs(ANI_START_SECS) as usize + 1;
+        let mut sequences = vec![
+            DtmfSequence {
+                digits: "123*4".to_string(),
+                start: 4_800,
+            },
+            DtmfSequence {
+                digits: "123#5".to_string(),
+                start: late + 96_000,
+            },
+        ];
+        let caller = take_caller(&mut sequences, &roster).expect("caller");
+        assert_eq!(caller.callsign, "N0CALL");
+        assert_eq!(sequences[0].digits, "*4");
+        assert_eq!(sequences[1].digits, "123#5");
+        let commands = parse_commands(&sequences);
+        assert!(matches!(
+            commands.as_slice(),
+            [MailboxCommand::Record(4), MailboxCommand::Play(5)]
+        ));
+
+        // A bare ANI leaves nothing behind.
+        let mut sequences = vec![DtmfSequence {
+            digits: "123".to_string(),
+            start: 0,
+        }];
+        assert!(take_caller(&mut sequences, &roster).is_some());
+        assert!(sequences.is_empty());
+
+        // Too late in the message to be an ANI.
+        let mut sequences = vec![DtmfSequence {
+            digits: "123*4".to_string(),
+            start: late,
+        }];
+        assert!(take_caller(&mut sequences, &roster).is_none());
+        assert_eq!(sequences[0].digits, "123*4");
+    }
+}
@@ -0,0 +1,175 @@
+// Narrow enough (about 23-54 Hz across the DTMF tones) to leave the speech around the
+// tones alone; the frequency search puts the notch within 0.25% of the tone.
+const NOTCH_Q: f32 = 30.0;
+const SEARCH_SPAN: f32 = 0.02;
+const SEARCH_STEPS: usize = 16;
+
+pub fn notch_tones(
+    samples: &mut [f32],
+    start: usize,
+    end: usize,
+    nominal_hz: &[f32],
+    sample_rate_hz: f32,
+    fade_len: usize,
+) {
+    let end = end.min(samples.len());
+    if start >= end {
+        return;
+    }
+    let mut filters: Vec<Notch> = nominal_hz
+        .iter()
+        .map(|&f| {
+            let freq_hz = estimate_tone_freq(&samples[start..end], f, sample_rate_hz);
+            Notch::new(sample_rate_hz, freq_hz, NOTCH_Q)
+        })
+        .collect();
+
+    // Let the filters settle for one fade length before they are blended in.
+    let fade_start = start.saturating_sub(fade_len);
+    let fade_end = end.saturating_add(fade_len).min(samples.len());
+    let preroll_start = fade_start.saturating_sub(fade_len);
+    for (i, sample) in samples
+        .iter_mut()
+        .enumerate()
+        .take(fade_end)
+        .skip(preroll_start)
+    {
+        let x = *sample;
+        let mut y = x;
+        for filt in &mut filters {
+            y = filt.process(y);
+        }
+        let weight = if i < fade_start {
+            0.0
+        } else if i < start {
+            raised_cosine((i - fade_start) as f32 / (start - fade_start) as f32)
+        } else if i < end {
+            1.0
+        } else {
+            raised_cosine((fade_end - i) as f32 / (fade_end - end) as f32)
+        };
+        *sample = x + weight * (y - x);
+    }
+}
+
+fn raised_cosine(t: f32) -> f32 {
+    0.5 - 0.5 * (std::f32::consts::PI * t.clamp(0.0, 1.0)).cos()
+}
+
+fn estimate_tone_freq(samples: &[f32], nominal_hz: f32, sample_rate_hz: f32) -> f32 {
+    let mut best_hz = nominal_hz;
+    let mut best_power = f32::MIN;
+    for step in 0..=SEARCH_STEPS {
+        let offset = -SEARCH_SPAN + 2.0 * SEARCH_SPAN * step as f32 / SEARCH_STEPS as f32;
+        let freq_hz = nominal_hz * (1.0 + offset);
+        let power = goertzel_power(samples, freq_hz, sample_rate_hz);
+        if power > best_power {
+            best_power = power;
+            best_hz = freq_hz;
+        }
+    }
+    best_hz
+}
+
+fn goertzel_power(samples: &[f32], freq_hz: f32, sample_rate_hz: f32) -> f32 {
+    let coeff = 2.0 * (2.0 * std::f32::consts::PI * freq_hz / sample_rate_hz).cos();
+    let mut s1 = 0.0f32;
+    let mut s2 = 0.0f32;
+    for &x in samples {
+        let s0 = x + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+    s1 * s1 + s2 * s2 - coeff * s1 * s2
+}
+
+struct Notch {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl Notch {
+    fn new(sample_rate_hz: f32, freq_hz: f32, q: f32) -> Self {
+        let omega = 2.0 * std::f32::consts::PI * freq_hz / sample_rate_hz;
+        let alpha = omega.sin() / (2.0 * q);
+        let cos = omega.cos();
+        let a0 = 1.0 + alpha;
+        Self {
+            b0: 1.0 / a0,
+            b1: -2.0 * cos / a0,
+            b2: 1.0 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1
+            - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FS: f32 = 48_000.0;
+
+    fn sine(freq_hz: f32, len: usize) -> impl Iterator<Item = f32> {
+        (0..len).map(move |n| 0.3 * (std::f32::consts::TAU * freq_hz * n as f32 / FS).sin())
+    }
+
+    fn level_db(samples: &[f32], freq_hz: f32) -> f32 {
+        10.0 * goertzel_power(samples, freq_hz, FS).log10()
+    }
+
+    #[test]
+    fn removes_tones_and_keeps_other_audio() {
+        // Key 5 a little off nominal, over a steady 1.1 kHz tone standing in for speech.
+        let (row_hz, col_hz) = (770.0 * 1.01, 1336.0 * 0.99);
+        let len = 28_800;
+        let (tone_start, tone_end) = (9_600, 19_200);
+        let mut samples: Vec<f32> = sine(1100.0, len).collect();
+        for (n, (r, c)) in sine(row_hz, len).zip(sine(col_hz, len)).enumerate() {
+            if (tone_start..tone_end).contains(&n) {
+                samples[n] += r + c;
+            }
+        }
+        let before = samples.clone();
+
+        notch_tones(
+            &mut samples,
+            tone_start - 1_440,
+            tone_end + 1_440,
+            &[770.0, 1336.0],
+            FS,
+            480,
+        );
+
+        let window = 12_000..16_800;
+        for freq in [row_hz, col_hz] {
+            let drop =
+                level_db(&before[window.clone()], freq) - level_db(&samples[window.clone()], freq);
+            assert!(drop > 30.0, "{} Hz only down {:.1} dB", freq, drop);
+        }
+        let change = level_db(&samples[window.clone()], 1100.0) - level_db(&before[window], 1100.0);
+        assert!(change.abs() < 1.0, "1.1 kHz changed by {:.2} dB", change);
+    }
+}
@@ -11,6 +11,7 @@ pub fn write_recording(
     recordings_dir: &Path,
     sample_rate_hz: f32,
     samples: &[f32],
+    caller: Option<&str>,
 ) -> Result<PathBuf, Box<dyn std::error::Error>> {
     let (serial, timestamp, secs, nanos) = recording_metadata();
     let filename = format!(
@@ -18,7 +19,7 @@ pub fn write_recording(
         format_timestamp_filename(&timestamp, secs, nanos)
     );
     let path = recordings_dir.join(filename);
-    write_recording_to(&path, sample_rate_hz, samples, serial, &timestamp)?;
+    write_recording_to(&path, sample_rate_hz, samples, serial, &timestamp, caller)?;
     Ok(path)
 }
 
@@ -44,6 +45,7 @@ pub fn write_recording_to(
     samples: &[f32],
     serial: u32,
     timestamp: &str,
+    caller: Option<&str>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let file = std::fs::File::create(path)?;
     let mut writer = std::io::BufWriter::new(file);
@@ -62,7 +64,7 @@ pub fn write_recording_to(
         PacketWriteEndInfo::EndPage,
         0,
     )?;
-    let opus_tags = build_opus_tags("meshcq-simplex-repeater", timestamp);
+    let opus_tags = build_opus_tags("meshcq-simplex-repeater", timestamp, caller);
     ogg.write_packet(
         opus_tags.into_boxed_slice(),
         serial,
@@ -115,15 +117,20 @@ fn build_opus_head(sample_rate_hz: u32, channels: u8, preskip: u16) -> Vec<u8> {
     head
 }
 
-fn build_opus_tags(vendor: &str, timestamp: &str) -> Vec<u8> {
+fn build_opus_tags(vendor: &str, timestamp: &str, caller: Option<&str>) -> Vec<u8> {
+    let mut comments = vec![format!("TIMESTAMP={}", timestamp)];
+    if let Some(caller) = caller {
+        comments.push(format!("CALLER={}", caller));
+    }
     let mut tags = Vec::new();
     tags.extend_from_slice(b"OpusTags");
     tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
     tags.extend_from_slice(vendor.as_bytes());
-    tags.extend_from_slice(&1u32.to_le_bytes());
-    let comment = format!("TIMESTAMP={}", timestamp);
-    tags.extend_from_slice(&(comment.len() as u32).to_le_bytes());
-    tags.extend_from_slice(comment.as_bytes());
+    tags.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        tags.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        tags.extend_from_slice(comment.as_bytes());
+    }
     tags
 }
 